@@ -1,51 +1,188 @@
-use std::{fs, path::PathBuf, str::FromStr};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use alloy::{
     primitives::FixedBytes,
     signers::{k256::ecdsa::SigningKey, local::LocalSigner},
 };
-use eyre::{Context, Result};
-use tracing::debug;
+use eyre::{Context, Result, bail};
+use rand_core::OsRng;
+use rayon::prelude::*;
+use tokio::sync::OnceCell;
+use tracing::{debug, warn};
+use zeroize::Zeroizing;
+
+use crate::config::{self, KeySource};
 
 pub type Signer = LocalSigner<SigningKey>;
 
-pub(crate) async fn read_accounts() -> Result<Vec<Signer>> {
-    let key_path = PathBuf::from_str("keys.private").context("Недопустимое значение пути")?;
+/// Переменная окружения с паролем от keystore
+pub const PASSWORD_ENV: &str = "BRIDGE_KEYSTORE_PASSWORD";
+/// Файл с ключами в открытом виде (только режим разработки)
+pub const PLAINTEXT_KEYS_PATH: &str = "keys.private";
+
+/// Ключи расшифровываются один раз за запуск
+static ACCOUNTS: OnceCell<Vec<Signer>> = OnceCell::const_new();
+
+/// Чтение ключей аккаунтов из источника, указанного в текущем профиле
+pub async fn read_accounts() -> Result<Vec<Signer>> {
+    ACCOUNTS
+        .get_or_try_init(|| async {
+            let profile = config::profile()?;
+            match &profile.keys {
+                KeySource::Keystore(path) if profile.plaintext_keys => read_plaintext_cache(path),
+                KeySource::Keystore(path) => KeyStore::open(path)?.signers(),
+                KeySource::Env(var) => read_keys_from_env(var),
+            }
+        })
+        .await
+        .cloned()
+}
+
+/// Замена ключей из профиля готовыми, например ключами тестовых аккаунтов.
+/// Вызывается до первого [`read_accounts`]
+pub fn set_accounts(signers: Vec<Signer>) -> Result<()> {
+    if ACCOUNTS.set(signers).is_err() {
+        bail!("Ключи аккаунтов уже прочитаны");
+    }
+    Ok(())
+}
+
+/// Чтение приватных ключей (hex через запятую) из переменной окружения
+fn read_keys_from_env(var: &str) -> Result<Vec<Signer>> {
+    let keys = Zeroizing::new(
+        env::var(var).with_context(|| format!("Переменная окружения {var} не задана"))?,
+    );
+    keys.split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|v| Signer::from_str(v).context("Ошибка при преобразовании ключа"))
+        .collect()
+}
+
+/// Пароль от keystore. Порядок поиска: переменная окружения, файл из профиля, ввод с клавиатуры
+pub fn read_password() -> Result<Zeroizing<String>> {
+    if let Ok(password) = env::var(PASSWORD_ENV) {
+        return Ok(Zeroizing::new(password));
+    }
+
+    if let Some(path) = &config::profile()?.password_file
+        && path.exists()
+    {
+        let password =
+            Zeroizing::new(fs::read_to_string(path).context("Неудалось прочитать файл с паролем")?);
+        return Ok(Zeroizing::new(
+            password.trim_end_matches(['\r', '\n']).to_string(),
+        ));
+    }
+
+    rpassword::prompt_password("Пароль от keystore: ")
+        .map(Zeroizing::new)
+        .context("Неудалось прочитать пароль")
+}
+
+/// Хранилище ключей в формате geth keystore. Ключи на диске всегда зашифрованы
+pub struct KeyStore {
+    path: PathBuf,
+    password: Zeroizing<String>,
+}
+
+impl KeyStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        if !path.is_dir() {
+            bail!("Директория keystore {path:?} не найдена");
+        }
+        Ok(KeyStore::new(path, read_password()?))
+    }
+
+    pub fn new(path: &Path, password: Zeroizing<String>) -> Self {
+        KeyStore {
+            path: path.to_path_buf(),
+            password,
+        }
+    }
+
+    /// Файлы ключей в порядке имён (geth добавляет дату создания в начало имени)
+    fn key_files(&self) -> Result<Vec<PathBuf>> {
+        let mut files: Vec<_> = self
+            .path
+            .read_dir()
+            .context("Неудалось прочитать директорию")?
+            .filter_map(|v| v.ok())
+            .map(|v| v.path())
+            .filter(|v| v.is_file())
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+
+    /// Расшифровка всех ключей
+    pub fn signers(&self) -> Result<Vec<Signer>> {
+        self.key_files()?
+            .par_iter()
+            .inspect(|v| debug!("Ключ: {v:?}"))
+            .map(|path| {
+                LocalSigner::decrypt_keystore(path, self.password.as_bytes())
+                    .with_context(|| format!("Ошибка при декодировании ключа {path:?}"))
+            })
+            .collect()
+    }
+
+    /// Шифрование и сохранение существующего ключа
+    pub fn import(&self, key: &FixedBytes<32>) -> Result<Signer> {
+        let (signer, _) = LocalSigner::encrypt_keystore(
+            &self.path,
+            &mut OsRng,
+            key,
+            self.password.as_bytes(),
+            None,
+        )
+        .context("Ошибка при сохранении ключа")?;
+        Ok(signer)
+    }
+
+    /// Создание нового ключа
+    pub fn create(&self) -> Result<Signer> {
+        let (signer, _) =
+            LocalSigner::new_keystore(&self.path, &mut OsRng, self.password.as_bytes(), None)
+                .context("Ошибка при создании ключа")?;
+        Ok(signer)
+    }
+}
+
+/// Режим разработки: ключи кэшируются в открытом виде в `keys.private`
+fn read_plaintext_cache(keystore_path: &Path) -> Result<Vec<Signer>> {
+    let key_path = PathBuf::from_str(PLAINTEXT_KEYS_PATH).context("Недопустимое значение пути")?;
+    warn!("Ключи хранятся в открытом виде в {key_path:?}");
 
     if !key_path.exists() {
         // Чтение из директории geth keystore
-        let keys = read_keystore_from_geth().await?;
+        let keys = KeyStore::open(keystore_path)?.signers()?;
 
         // Сохранение ключей в файл в расшифрованном виде
-        let keys_bytes: Vec<_> = keys.iter().map(|v| v.to_bytes()).collect();
-        let keys_json = serde_json::to_string_pretty(&keys_bytes)
-            .context("Неудалось преобразовать ключ в JSON")?;
-        fs::write(&key_path, keys_json).context("Ошибка при записи файла ключей")?;
+        let keys_hex: Zeroizing<Vec<String>> = Zeroizing::new(
+            keys.iter()
+                .map(|v| format!("0x{}", hex::encode(v.to_bytes())))
+                .collect(),
+        );
+        let keys_json = Zeroizing::new(
+            serde_json::to_string_pretty(&*keys_hex)
+                .context("Неудалось преобразовать ключ в JSON")?,
+        );
+        fs::write(&key_path, &*keys_json).context("Ошибка при записи файла ключей")?;
         return Ok(keys);
     }
 
-    let keys_json = fs::read_to_string(&key_path).context("Неудалось прочитать файл ключей")?;
-    let keys_bytes = serde_json::from_str::<Vec<FixedBytes<32>>>(&keys_json)
-        .context("Неудалось преобразовать JSON в ключи")?;
-    keys_bytes
-        .into_iter()
-        .map(|v| LocalSigner::from_bytes(&v).context("Ошибка при преобразовании байтов в ключ"))
+    let keys_json =
+        Zeroizing::new(fs::read_to_string(&key_path).context("Неудалось прочитать файл ключей")?);
+    let keys_hex: Zeroizing<Vec<String>> = Zeroizing::new(
+        serde_json::from_str(&keys_json).context("Неудалось преобразовать JSON в ключи")?,
+    );
+    keys_hex
+        .iter()
+        .map(|v| Signer::from_str(v).context("Ошибка при преобразовании байтов в ключ"))
         .collect::<Result<Vec<_>>>()
 }
-
-/// Чтение ключей из директории geth (keystore)
-async fn read_keystore_from_geth() -> Result<Vec<Signer>> {
-    let keystore_path = PathBuf::from_str("data/keystore").context("Путь до ключей не валиден")?;
-
-    let read_keys = keystore_path
-        .read_dir()
-        .context("Неудалось прочитать директорию")?
-        .filter_map(|v| v.ok())
-        .map(|v| v.path())
-        .filter(|v| v.is_file())
-        .inspect(|v| debug!("Ключ: {v:?}"))
-        .map(|path| async move { LocalSigner::decrypt_keystore(&path, "") });
-    futures_util::future::try_join_all(read_keys)
-        .await
-        .context("Ошибка при декодировании ключей")
-}
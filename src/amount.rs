@@ -0,0 +1,255 @@
+//! Перевод сумм между точностями l1 и l2 без обращения к сети
+//!
+//! Повторяет `convert_amount`, `convert_amount_to_l2` и `convert_amount_to_l1` из `Bridge.sol`,
+//! включая отказы: потерю младших разрядов, превышение `MAX_AMOUNT` и переполнение `uint256`.
+//!
+//! [`TokenAmount`] хранит сумму вместе с точностью, [`TokenUnits`] описывает точности токена
+//! на l1 и l2 и разбирает суммы вида `1.5 DERC`.
+
+use std::fmt::{self, Display, Formatter};
+
+use alloy::{
+    primitives::{Address, U256},
+    providers::Provider,
+};
+use alloy_sol_types::PanicKind;
+use eyre::{Context, Result, bail};
+
+use crate::{
+    contracts::{Bridge::BridgeTokenInfo, DemoERC20},
+    errors::BridgeError,
+};
+
+/// Точность ETH на l1 (`DEFAULT_ETH_DECIMALS`)
+pub const ETH_DECIMALS: u8 = 18;
+/// Точность сумм на l2 (`DEFAULT_MOVE_DECIMALS`)
+pub const L2_DECIMALS: u8 = 8;
+/// Максимальная сумма на l2 (`MAX_AMOUNT`)
+pub const MAX_AMOUNT: U256 = U256::from_limbs([u64::MAX, 0, 0, 0]);
+
+/// Переполнение при вычислениях, как `Panic(0x11)` в контракте
+fn overflow() -> BridgeError {
+    BridgeError::Panic {
+        code: U256::from(PanicKind::UnderOverflow as u32),
+    }
+}
+
+/// Перевод суммы из точности `from` в точность `to`.
+/// При уменьшении точности младшие разряды должны быть нулевыми
+pub fn convert_amount(amount: U256, from: u8, to: u8) -> Result<U256, BridgeError> {
+    if from == to {
+        return Ok(amount);
+    }
+
+    if from > to {
+        let r = U256::from(10)
+            .checked_pow(U256::from(from - to))
+            .ok_or_else(overflow)?;
+        let new_amount = amount / r;
+        if new_amount * r != amount {
+            return Err(BridgeError::Precision(to));
+        }
+        Ok(new_amount)
+    } else {
+        let r = U256::from(10)
+            .checked_pow(U256::from(to - from))
+            .ok_or_else(overflow)?;
+        amount.checked_mul(r).ok_or_else(overflow)
+    }
+}
+
+/// Перевод суммы ERC20 в точность l2 (`convert_amount_to_l2`)
+pub fn convert_amount_to_l2(info: &BridgeTokenInfo, amount: U256) -> Result<U256, BridgeError> {
+    if !info.turn {
+        return Err(BridgeError::BridgeNotCreated);
+    }
+    let amount = convert_amount(amount, info.base_decimals, info.decimals)?;
+    if amount > MAX_AMOUNT {
+        return Err(BridgeError::AmountExceedsMaximum);
+    }
+    Ok(amount)
+}
+
+/// Перевод суммы ERC20 из точности l2 в точность токена (`convert_amount_to_l1`)
+pub fn convert_amount_to_l1(info: &BridgeTokenInfo, amount: U256) -> Result<U256, BridgeError> {
+    if !info.turn {
+        return Err(BridgeError::BridgeNotCreated);
+    }
+    convert_amount(amount, info.decimals, info.base_decimals)
+}
+
+/// Точность токена на l2: не больше [`L2_DECIMALS`]
+pub fn l2_decimals(base_decimals: u8) -> u8 {
+    base_decimals.min(L2_DECIMALS)
+}
+
+/// Сумма в минимальных единицах вместе с точностью
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TokenAmount {
+    value: U256,
+    decimals: u8,
+}
+
+impl TokenAmount {
+    /// Сумма в минимальных единицах
+    pub const fn new(value: U256, decimals: u8) -> Self {
+        TokenAmount { value, decimals }
+    }
+
+    /// Целое количество токенов
+    pub fn from_tokens(tokens: u64, decimals: u8) -> Self {
+        TokenAmount::new(
+            U256::from(tokens) * U256::from(10).pow(U256::from(decimals)),
+            decimals,
+        )
+    }
+
+    /// Разбор десятичной записи: `1`, `1.5`, `0.000001`.
+    /// Знаков после запятой не может быть больше, чем `decimals`
+    pub fn parse(s: &str, decimals: u8) -> Result<Self> {
+        let s = s.trim().replace('_', "");
+        let (whole, fraction) = s.split_once('.').unwrap_or((&s, ""));
+        if whole.is_empty() && fraction.is_empty()
+            || !whole
+                .chars()
+                .chain(fraction.chars())
+                .all(|v| v.is_ascii_digit())
+        {
+            bail!("Некорректная сумма {s:?}");
+        }
+        if fraction.len() > decimals as usize {
+            bail!("Сумма {s} точнее {decimals} знаков после запятой");
+        }
+
+        let digits = format!("{whole}{fraction:0<width$}", width = decimals as usize);
+        let value = U256::from_str_radix(&digits, 10)
+            .with_context(|| format!("Сумма {s} слишком большая"))?;
+        Ok(TokenAmount::new(value, decimals))
+    }
+
+    pub fn value(&self) -> U256 {
+        self.value
+    }
+
+    pub fn decimals(&self) -> u8 {
+        self.decimals
+    }
+
+    /// Та же сумма в другой точности. Потеря младших разрядов - ошибка
+    pub fn convert(self, decimals: u8) -> Result<Self, BridgeError> {
+        convert_amount(self.value, self.decimals, decimals).map(|v| TokenAmount::new(v, decimals))
+    }
+}
+
+/// Десятичная запись без лишних нулей: `1.5`, `10`, `0.00000001`
+impl Display for TokenAmount {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let digits = self.value.to_string();
+        let decimals = self.decimals as usize;
+        if decimals == 0 {
+            return f.write_str(&digits);
+        }
+
+        let digits = format!("{digits:0>width$}", width = decimals + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - decimals);
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.is_empty() {
+            f.write_str(whole)
+        } else {
+            write!(f, "{whole}.{fraction}")
+        }
+    }
+}
+
+/// Символ и точности токена на l1 и l2
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenUnits {
+    pub symbol: String,
+    /// Точность на l1 (`decimals()` токена, `BridgeTokenInfo.base_decimals`)
+    pub decimals: u8,
+    /// Точность на l2 (`BridgeTokenInfo.decimals`)
+    pub l2_decimals: u8,
+}
+
+impl TokenUnits {
+    pub fn eth() -> Self {
+        TokenUnits {
+            symbol: "ETH".to_string(),
+            decimals: ETH_DECIMALS,
+            l2_decimals: L2_DECIMALS,
+        }
+    }
+
+    /// Точности из состояния моста (`status_bridge_erc20`)
+    pub fn from_info(info: &BridgeTokenInfo) -> Self {
+        TokenUnits {
+            symbol: info.symbol.clone(),
+            decimals: info.base_decimals,
+            l2_decimals: info.decimals,
+        }
+    }
+
+    /// Точности из контракта токена
+    pub async fn fetch<P: Provider>(token: Address, provider: P) -> Result<Self> {
+        let token = DemoERC20::new(token, provider);
+        let symbol = token
+            .symbol()
+            .call()
+            .await
+            .context("Не удалось получить символ токена")?;
+        let decimals = token
+            .decimals()
+            .call()
+            .await
+            .context("Не удалось получить точность токена")?;
+        Ok(TokenUnits {
+            symbol,
+            decimals,
+            l2_decimals: l2_decimals(decimals),
+        })
+    }
+
+    /// Разбор суммы на l1: `1.5` или `1.5 DERC`. Символ, если указан, должен совпадать
+    pub fn parse(&self, s: &str) -> Result<TokenAmount> {
+        let s = s.trim();
+        let number = match s.split_once(char::is_whitespace) {
+            Some((number, symbol)) => {
+                if !symbol.trim().eq_ignore_ascii_case(&self.symbol) {
+                    bail!("Ожидается сумма в {}, указано {s:?}", self.symbol);
+                }
+                number
+            }
+            None => s,
+        };
+        TokenAmount::parse(number, self.decimals)
+    }
+
+    /// Сумма на l1
+    pub fn l1(&self, value: U256) -> TokenAmount {
+        TokenAmount::new(value, self.decimals)
+    }
+
+    /// Сумма на l2
+    pub fn l2(&self, value: U256) -> TokenAmount {
+        TokenAmount::new(value, self.l2_decimals)
+    }
+
+    /// Перевод суммы l1 в единицы l2 с проверкой `MAX_AMOUNT`
+    pub fn to_l2(&self, amount: TokenAmount) -> Result<TokenAmount, BridgeError> {
+        let amount = amount.convert(self.l2_decimals)?;
+        if amount.value > MAX_AMOUNT {
+            return Err(BridgeError::AmountExceedsMaximum);
+        }
+        Ok(amount)
+    }
+
+    /// Перевод суммы l2 в единицы l1
+    pub fn to_l1(&self, amount: TokenAmount) -> Result<TokenAmount, BridgeError> {
+        amount.convert(self.decimals)
+    }
+
+    /// Сумма с символом: `1.5 DERC`
+    pub fn format(&self, amount: TokenAmount) -> String {
+        format!("{amount} {}", self.symbol)
+    }
+}
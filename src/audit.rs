@@ -0,0 +1,104 @@
+//! Проверка истории депозитов ETH
+//!
+//! `Bridge.deposit` приводит сумму к `uint64` без проверки `MAX_AMOUNT`, поэтому при слишком
+//! большом `msg.value` в `EventDeposit` попадает остаток по модулю 2^64, а на мосту остаётся вся
+//! сумма. Аудит сравнивает `EventDeposit.value` с `msg.value` транзакции, переведённым в точность
+//! l2, и сообщает о расхождениях.
+
+use alloy::{
+    consensus::Transaction as _,
+    primitives::{Address, U256},
+    providers::Provider,
+};
+use eyre::{Context, OptionExt, Result};
+use tracing::debug;
+
+use crate::{
+    amount::{ETH_DECIMALS, L2_DECIMALS, convert_amount},
+    contracts::Bridge::EventDeposit,
+    errors::BridgeError,
+    events::{Backfill, BridgeEvent, EventFilter, EventKind, LogMeta},
+};
+
+/// Причина, по которой депозит не прошёл проверку
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Discrepancy {
+    /// Зачислено не `msg.value`: сумма обрезана до `uint64`
+    Truncated { expected: U256 },
+    /// `msg.value` не переводится в точность l2
+    Unconvertible(BridgeError),
+    /// `deposit` вызван другим контрактом, `msg.value` вызова по транзакции не определить
+    IndirectCall { to: Option<Address> },
+}
+
+/// Депозит с расхождением
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepositFinding {
+    pub meta: LogMeta,
+    pub event: EventDeposit,
+    /// `value` транзакции в wei
+    pub msg_value: U256,
+    pub discrepancy: Discrepancy,
+}
+
+/// Результат аудита
+#[derive(Debug, Clone, Default)]
+pub struct AuditReport {
+    /// Количество проверенных депозитов
+    pub checked: usize,
+    pub findings: Vec<DepositFinding>,
+}
+
+/// Проверка депозита по `value` и получателю транзакции
+pub fn check_deposit(
+    bridge: Address,
+    event: &EventDeposit,
+    tx_to: Option<Address>,
+    msg_value: U256,
+) -> Option<Discrepancy> {
+    if tx_to != Some(bridge) {
+        return Some(Discrepancy::IndirectCall { to: tx_to });
+    }
+    match convert_amount(msg_value, ETH_DECIMALS, L2_DECIMALS) {
+        Ok(expected) if expected == U256::from(event.value) => None,
+        Ok(expected) => Some(Discrepancy::Truncated { expected }),
+        Err(err) => Some(Discrepancy::Unconvertible(err)),
+    }
+}
+
+/// Проверка всех `EventDeposit` моста в блоках `from_block..=to_block`
+pub async fn audit_deposits<P: Provider>(
+    provider: &P,
+    bridge: Address,
+    from_block: u64,
+    to_block: u64,
+) -> Result<AuditReport> {
+    let filter = EventFilter::new().address(bridge).kind(EventKind::Deposit);
+    let mut backfill = Backfill::new(filter, from_block, to_block);
+    let mut report = AuditReport::default();
+
+    while let Some(chunk) = backfill.next_chunk(provider).await? {
+        for log in chunk {
+            let BridgeEvent::Deposit(event) = log.event else {
+                continue;
+            };
+            let tx = provider
+                .get_transaction_by_hash(log.meta.tx_hash)
+                .await
+                .with_context(|| format!("Не удалось получить транзакцию {}", log.meta.tx_hash))?
+                .ok_or_eyre(format!("Транзакция {} не найдена", log.meta.tx_hash))?;
+
+            report.checked += 1;
+            if let Some(discrepancy) = check_deposit(bridge, &event, tx.to(), tx.value()) {
+                debug!("Расхождение в {}: {discrepancy:?}", log.meta.tx_hash);
+                report.findings.push(DepositFinding {
+                    meta: log.meta,
+                    event,
+                    msg_value: tx.value(),
+                    discrepancy,
+                });
+            }
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,327 @@
+//! Шина событий с несколькими подписчиками
+//!
+//! [`EventBus`] рассылает каждое событие всем подписчикам. У каждого подписчика своя очередь
+//! и своя политика [`Overflow`] на случай, если он не успевает читать:
+//! - [`Overflow::Block`] - издатель ждёт, пока в очереди появится место;
+//! - [`Overflow::DropOldest`] - из очереди выбрасывается самое старое событие;
+//! - [`Overflow::Spill`] - события сверх очереди дописываются в файл и читаются из него по порядку.
+//!
+//! Подписчик получает `None`, когда все копии шины удалены и очередь прочитана.
+
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Seek, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use eyre::{Context, Result};
+use serde::{Serialize, de::DeserializeOwned};
+use tokio::sync::Notify;
+use tracing::{debug, warn};
+
+/// Поведение при заполненной очереди подписчика
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Overflow {
+    /// Ждать, пока подписчик прочитает событие. Медленный подписчик задерживает издателя
+    Block,
+    /// Выбросить самое старое событие из очереди
+    DropOldest,
+    /// Дописать событие в файл
+    Spill(PathBuf),
+}
+
+/// Файл с событиями, не поместившимися в очередь
+#[derive(Debug)]
+struct SpillFile {
+    path: PathBuf,
+    writer: File,
+    reader: BufReader<File>,
+    /// Записано и ещё не прочитано
+    pending: usize,
+}
+
+impl SpillFile {
+    fn create(path: &Path) -> Result<Self> {
+        let writer = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Не удалось создать файл очереди {}", path.display()))?;
+        // Отдельный дескриптор: у клона общее смещение с записью
+        let reader = BufReader::new(
+            File::open(path)
+                .with_context(|| format!("Не удалось открыть файл очереди {}", path.display()))?,
+        );
+        Ok(SpillFile {
+            path: path.to_path_buf(),
+            writer,
+            reader,
+            pending: 0,
+        })
+    }
+
+    fn push(&mut self, line: &str) -> Result<()> {
+        self.writer.seek(std::io::SeekFrom::End(0))?;
+        writeln!(self.writer, "{line}").context("Ошибка записи в файл очереди")?;
+        self.pending += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<String> {
+        let mut line = String::new();
+        self.reader
+            .read_line(&mut line)
+            .context("Ошибка чтения файла очереди")?;
+        self.pending -= 1;
+        // Файл прочитан целиком, место освобождается
+        if self.pending == 0 {
+            self.writer.set_len(0)?;
+            self.reader.rewind()?;
+        }
+        Ok(line)
+    }
+}
+
+/// Очередь одного подписчика
+#[derive(Debug)]
+struct Queue<T> {
+    events: VecDeque<T>,
+    capacity: usize,
+    overflow: Overflow,
+    spill: Option<SpillFile>,
+    dropped: u64,
+    /// Все копии шины удалены
+    closed: bool,
+    /// Подписчик удалён
+    unsubscribed: bool,
+}
+
+#[derive(Debug)]
+struct Shared<T> {
+    queue: Mutex<Queue<T>>,
+    /// Новое событие для подписчика
+    readable: Notify,
+    /// Место в очереди для издателя
+    writable: Notify,
+}
+
+#[derive(Debug)]
+struct Inner<T> {
+    subscribers: Mutex<Vec<Arc<Shared<T>>>>,
+    publishers: Mutex<usize>,
+}
+
+/// Шина событий. Копии шины публикуют в одних и тех же подписчиков
+#[derive(Debug)]
+pub struct EventBus<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Default for EventBus<T> {
+    fn default() -> Self {
+        EventBus {
+            inner: Arc::new(Inner {
+                subscribers: Mutex::new(Vec::new()),
+                publishers: Mutex::new(1),
+            }),
+        }
+    }
+}
+
+impl<T> Clone for EventBus<T> {
+    fn clone(&self) -> Self {
+        *self.inner.publishers.lock().unwrap() += 1;
+        EventBus {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Drop for EventBus<T> {
+    fn drop(&mut self) {
+        let mut publishers = self.inner.publishers.lock().unwrap();
+        *publishers -= 1;
+        if *publishers > 0 {
+            return;
+        }
+        for subscriber in self.inner.subscribers.lock().unwrap().iter() {
+            subscriber.queue.lock().unwrap().closed = true;
+            subscriber.readable.notify_one();
+        }
+    }
+}
+
+impl<T: Clone + Serialize + DeserializeOwned + Send + 'static> EventBus<T> {
+    pub fn new() -> Self {
+        EventBus::default()
+    }
+
+    /// Подписка на события, опубликованные после неё. `capacity` - размер очереди в памяти
+    pub fn subscribe(&self, capacity: usize, overflow: Overflow) -> Result<Subscriber<T>> {
+        let spill = match &overflow {
+            Overflow::Spill(path) => Some(SpillFile::create(path)?),
+            _ => None,
+        };
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                events: VecDeque::with_capacity(capacity),
+                capacity: capacity.max(1),
+                overflow,
+                spill,
+                dropped: 0,
+                closed: false,
+                unsubscribed: false,
+            }),
+            readable: Notify::new(),
+            writable: Notify::new(),
+        });
+        self.inner.subscribers.lock().unwrap().push(shared.clone());
+        Ok(Subscriber { shared })
+    }
+
+    /// Количество подписчиков
+    pub fn subscribers(&self) -> usize {
+        let mut subscribers = self.inner.subscribers.lock().unwrap();
+        subscribers.retain(|v| !v.queue.lock().unwrap().unsubscribed);
+        subscribers.len()
+    }
+
+    /// Рассылка события. Ждёт только подписчиков с [`Overflow::Block`]
+    pub async fn publish(&self, event: T) -> Result<()> {
+        let subscribers = {
+            let mut subscribers = self.inner.subscribers.lock().unwrap();
+            subscribers.retain(|v| !v.queue.lock().unwrap().unsubscribed);
+            subscribers.clone()
+        };
+        for subscriber in subscribers {
+            deliver(&subscriber, event.clone()).await?;
+        }
+        Ok(())
+    }
+}
+
+async fn deliver<T: Serialize>(subscriber: &Shared<T>, mut event: T) -> Result<()> {
+    loop {
+        let writable = subscriber.writable.notified();
+        tokio::pin!(writable);
+        writable.as_mut().enable();
+        match try_deliver(subscriber, event)? {
+            Some(rejected) => event = rejected,
+            None => return Ok(()),
+        }
+        writable.await;
+    }
+}
+
+/// Событие возвращается, если подписчик с [`Overflow::Block`] ещё не освободил место
+fn try_deliver<T: Serialize>(subscriber: &Shared<T>, event: T) -> Result<Option<T>> {
+    let mut queue = subscriber.queue.lock().unwrap();
+    if queue.unsubscribed {
+        return Ok(None);
+    }
+    let full = queue.events.len() >= queue.capacity;
+    let spilling = queue.spill.as_ref().is_some_and(|v| v.pending > 0);
+    if !full && !spilling {
+        queue.events.push_back(event);
+    } else {
+        match queue.overflow {
+            Overflow::Block => return Ok(Some(event)),
+            Overflow::DropOldest => {
+                queue.events.pop_front();
+                queue.dropped += 1;
+                queue.events.push_back(event);
+            }
+            Overflow::Spill(_) => {
+                let line = serde_json::to_string(&event)
+                    .context("Не удалось сохранить событие в файл очереди")?;
+                queue.spill.as_mut().expect("spill file").push(&line)?;
+            }
+        }
+    }
+    drop(queue);
+    subscriber.readable.notify_one();
+    Ok(None)
+}
+
+/// Подписчик шины
+#[derive(Debug)]
+pub struct Subscriber<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: DeserializeOwned> Subscriber<T> {
+    /// Следующее событие. `None`, когда шина удалена и все события прочитаны
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            let readable = self.shared.readable.notified();
+            {
+                let mut queue = self.shared.queue.lock().unwrap();
+                if let Some(event) = queue.events.pop_front() {
+                    if let Err(err) = queue.refill() {
+                        warn!("{err:#}");
+                    }
+                    drop(queue);
+                    self.shared.writable.notify_one();
+                    return Some(event);
+                }
+                if queue.closed {
+                    return None;
+                }
+            }
+            readable.await;
+        }
+    }
+
+    /// Следующее событие, для которого `f` возвращает `Some`. Остальные события пропускаются
+    pub async fn recv_map<U>(&mut self, mut f: impl FnMut(T) -> Option<U>) -> Option<U> {
+        loop {
+            if let Some(value) = f(self.recv().await?) {
+                return Some(value);
+            }
+        }
+    }
+
+    /// Количество событий, выброшенных по [`Overflow::DropOldest`]
+    pub fn dropped(&self) -> u64 {
+        self.shared.queue.lock().unwrap().dropped
+    }
+
+    /// Количество событий в файле очереди
+    pub fn spilled(&self) -> usize {
+        let queue = self.shared.queue.lock().unwrap();
+        queue.spill.as_ref().map_or(0, |v| v.pending)
+    }
+}
+
+impl<T: DeserializeOwned> Queue<T> {
+    /// Перенос событий из файла в освободившуюся очередь
+    fn refill(&mut self) -> Result<()> {
+        while self.events.len() < self.capacity
+            && let Some(spill) = self.spill.as_mut()
+            && spill.pending > 0
+        {
+            let line = spill.pop()?;
+            let event = serde_json::from_str(&line)
+                .with_context(|| format!("Ошибка в файле очереди {}", spill.path.display()))?;
+            self.events.push_back(event);
+        }
+        Ok(())
+    }
+}
+
+impl<T> Drop for Subscriber<T> {
+    fn drop(&mut self) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.unsubscribed = true;
+        if let Some(spill) = queue.spill.take() {
+            debug!("Удаление файла очереди {}", spill.path.display());
+            let _ = fs::remove_file(&spill.path);
+        }
+        drop(queue);
+        // Издатель мог ждать места в очереди
+        self.shared.writable.notify_waiters();
+    }
+}
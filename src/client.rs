@@ -0,0 +1,284 @@
+//! Клиент контракта `Bridge`
+//!
+//! Каждый метод отправляет транзакцию, дожидается квитанции и возвращает [`TxOutcome`] с
+//! затраченным газом и разобранными событиями. Откат транзакции возвращается как ошибка с
+//! [`BridgeError`] в цепочке: причина отката в блоке берётся из повторного `eth_call`. Транзакции отправляются через
+//! [`TxSupervisor`]: зависшая транзакция повторяется с большей ценой газа.
+
+use alloy::{
+    consensus::constants::ETH_TO_WEI,
+    contract::SolCallBuilder,
+    eips::BlockId,
+    network::TransactionBuilder,
+    primitives::{Address, B256, Bytes, U256},
+    providers::{Provider, WalletProvider as _},
+    rpc::types::{TransactionReceipt, TransactionRequest},
+    sol_types::SolCall,
+};
+use eyre::{Result, bail};
+use tracing::{info, warn};
+
+use crate::{
+    accounts::Signer,
+    amount::{ETH_DECIMALS, TokenAmount, TokenUnits},
+    contracts::{Bridge::BridgeInstance, DemoERC20, WalletProvider},
+    deployer::Deployer,
+    errors::{BridgeError, RevertExt},
+    events::{self, EventLog},
+    supervisor::{TxResult, TxSupervisor},
+};
+
+/// Ошибка отката транзакции `tx_hash` с [`BridgeError`]. Квитанция не содержит причину,
+/// поэтому `request` повторяется через `eth_call` в блоке `block`. Если откат не повторился,
+/// причина неизвестна: [`BridgeError::Raw`] без данных
+pub async fn revert_error<P: Provider>(
+    provider: &P,
+    request: TransactionRequest,
+    tx_hash: B256,
+    block: BlockId,
+) -> eyre::Report {
+    let reason = match provider.call(request).block(block).await {
+        Ok(_) => None,
+        Err(err) => BridgeError::from_transport_error(&err),
+    }
+    .unwrap_or_else(|| {
+        warn!("Причина отката {tx_hash} не найдена");
+        BridgeError::Raw(Bytes::new())
+    });
+    eyre::Report::new(reason).wrap_err(format!("Транзакция {tx_hash} откатилась"))
+}
+
+/// Комиссия за создание моста для ERC20 (`CREATION_COMMISSION_BRIDGE`)
+pub const CREATION_COMMISSION: U256 = U256::from_limbs([ETH_TO_WEI as u64, 0, 0, 0]);
+
+/// Результат выполненной транзакции
+#[derive(Debug, Clone)]
+pub struct TxOutcome {
+    pub tx_hash: B256,
+    pub block_number: u64,
+    pub gas_used: u64,
+    /// Цена газа в wei
+    pub effective_gas_price: u128,
+    /// События всех контрактов из квитанции
+    pub events: Vec<EventLog>,
+    pub receipt: TransactionReceipt,
+}
+
+/// Результат депозита ERC20
+#[derive(Debug, Clone)]
+pub struct Erc20DepositOutcome {
+    /// Транзакция `approve`, если текущего разрешения не хватало
+    pub approval: Option<TxOutcome>,
+    pub deposit: TxOutcome,
+}
+
+impl TxOutcome {
+    fn from_receipt(receipt: TransactionReceipt) -> Self {
+        TxOutcome {
+            tx_hash: receipt.transaction_hash,
+            block_number: receipt.block_number.unwrap_or_default(),
+            gas_used: receipt.gas_used,
+            effective_gas_price: receipt.effective_gas_price,
+            events: events::decode_logs(receipt.inner.logs().to_vec()),
+            receipt,
+        }
+    }
+
+    /// Стоимость транзакции в wei
+    pub fn fee(&self) -> U256 {
+        U256::from(self.gas_used) * U256::from(self.effective_gas_price)
+    }
+}
+
+/// Клиент моста от имени одного аккаунта
+#[derive(Debug, Clone)]
+pub struct BridgeClient {
+    bridge: BridgeInstance<WalletProvider>,
+    supervisor: TxSupervisor,
+}
+
+impl BridgeClient {
+    pub fn new(bridge: BridgeInstance<WalletProvider>) -> Self {
+        let supervisor = TxSupervisor::new(bridge.provider().clone());
+        BridgeClient { bridge, supervisor }
+    }
+
+    /// Сопровождение транзакций с другими таймаутами
+    pub fn with_supervisor(mut self, supervisor: TxSupervisor) -> Self {
+        self.supervisor = supervisor;
+        self
+    }
+
+    /// Клиент для моста из текущего профиля
+    pub async fn connect(user: Signer) -> Result<Self> {
+        let bridge = Deployer::connect(&user)
+            .await?
+            .find::<BridgeInstance<WalletProvider>>()
+            .await?;
+        Ok(BridgeClient::new(bridge.into_inner()))
+    }
+
+    pub fn address(&self) -> Address {
+        *self.bridge.address()
+    }
+
+    /// Аккаунт, от имени которого отправляются транзакции
+    pub fn sender(&self) -> Address {
+        self.bridge.provider().default_signer_address()
+    }
+
+    pub fn bridge(&self) -> &BridgeInstance<WalletProvider> {
+        &self.bridge
+    }
+
+    /// Отправка транзакции и ожидание квитанции
+    async fn execute<C: SolCall>(
+        &self,
+        call: SolCallBuilder<&WalletProvider, C>,
+    ) -> Result<TxOutcome> {
+        let request = call.into_transaction_request().with_from(self.sender());
+        let mut pending = self
+            .supervisor
+            .send(request.clone())
+            .await
+            .decode_revert()?;
+        let receipt = match self.supervisor.wait(&mut pending).await? {
+            TxResult::Mined(receipt) => receipt,
+            TxResult::Cancelled(_) => bail!("Транзакция {} отменена", C::SIGNATURE),
+            TxResult::Replaced => bail!(
+                "Nonce {} транзакции {} занят другой транзакцией",
+                pending.nonce(),
+                C::SIGNATURE
+            ),
+            TxResult::Stuck => bail!(
+                "Транзакция {} ({}) не попала в блок",
+                pending.tx_hash(),
+                C::SIGNATURE
+            ),
+        };
+        if !receipt.status() {
+            let block = receipt
+                .block_number
+                .map_or(BlockId::latest(), BlockId::number);
+            let err = revert_error(
+                self.bridge.provider(),
+                request,
+                receipt.transaction_hash,
+                block,
+            )
+            .await;
+            return Err(err.wrap_err(C::SIGNATURE));
+        }
+
+        let outcome = TxOutcome::from_receipt(receipt);
+        info!(
+            "{}: {} газ {}",
+            C::SIGNATURE,
+            outcome.tx_hash,
+            outcome.gas_used
+        );
+        Ok(outcome)
+    }
+
+    /// Перевод ETH l1 => l2. Сумма в wei должна делиться на 10^10.
+    ///
+    /// Контракт приводит сумму к `uint64` без проверки `MAX_AMOUNT`: большая сумма была бы
+    /// зачислена на l2 по модулю 2^64, а весь `msg.value` остался бы на мосту. Поэтому сумма
+    /// проверяется до отправки
+    pub async fn deposit(&self, to: Address, amount: U256) -> Result<TxOutcome> {
+        TokenUnits::eth().to_l2(TokenAmount::new(amount, ETH_DECIMALS))?;
+        self.execute(self.bridge.deposit(to).value(amount)).await
+    }
+
+    /// Перевод ERC20 l1 => l2. Если разрешения на перевод не хватает, сначала вызывается `approve`
+    pub async fn deposit_erc20(
+        &self,
+        token: Address,
+        to: Address,
+        amount: U256,
+    ) -> Result<Erc20DepositOutcome> {
+        let erc20 = DemoERC20::new(token, self.bridge.provider().clone());
+        let allowance = erc20
+            .allowance(self.sender(), self.address())
+            .call()
+            .await
+            .decode_revert()?;
+
+        let approval = if allowance < amount {
+            Some(self.execute(erc20.approve(self.address(), amount)).await?)
+        } else {
+            None
+        };
+
+        let deposit = self
+            .execute(self.bridge.deposit_erc20(token, to, amount))
+            .await?;
+        Ok(Erc20DepositOutcome { approval, deposit })
+    }
+
+    /// Создание моста для ERC20 с комиссией [`CREATION_COMMISSION`]
+    pub async fn create_bridge(&self, token: Address) -> Result<TxOutcome> {
+        self.execute(
+            self.bridge
+                .create_bridge_erc20(token)
+                .value(CREATION_COMMISSION),
+        )
+        .await
+    }
+
+    /// Одобрение вывода ETH l2 => l1. Только owner
+    pub async fn apply_withdrawal(&self, to: Address, amount: u64) -> Result<TxOutcome> {
+        self.execute(self.bridge.apply_withdrawal_request(to, amount))
+            .await
+    }
+
+    /// Одобрение вывода ERC20 l2 => l1. Только owner
+    pub async fn apply_withdrawal_erc20(
+        &self,
+        token: Address,
+        to: Address,
+        amount: u64,
+    ) -> Result<TxOutcome> {
+        self.execute(
+            self.bridge
+                .apply_withdrawal_request_erc20(token, to, amount),
+        )
+        .await
+    }
+
+    /// Вывод одобренного ETH
+    pub async fn withdraw(&self) -> Result<TxOutcome> {
+        self.execute(self.bridge.withdraw()).await
+    }
+
+    /// Вывод одобренного ERC20
+    pub async fn withdraw_erc20(&self, token: Address) -> Result<TxOutcome> {
+        self.execute(self.bridge.withdraw_erc20(token)).await
+    }
+
+    /// Сумма ETH в wei, одобренная к выводу для отправителя
+    pub async fn available_to_withdraw(&self) -> Result<U256> {
+        self.bridge
+            .available_to_withdraw()
+            .call()
+            .await
+            .decode_revert()
+    }
+
+    /// Сумма ERC20, одобренная к выводу для отправителя
+    pub async fn available_to_withdraw_erc20(&self, token: Address) -> Result<U256> {
+        self.bridge
+            .available_to_withdraw_erc20(token)
+            .call()
+            .await
+            .decode_revert()
+    }
+
+    pub async fn exist_bridge_erc20(&self, token: Address) -> Result<bool> {
+        self.bridge
+            .exist_bridge_erc20(token)
+            .call()
+            .await
+            .decode_revert()
+    }
+}
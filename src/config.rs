@@ -0,0 +1,217 @@
+use std::{collections::HashMap, env, fs, io, path::PathBuf, sync::OnceLock};
+
+use alloy::{primitives::Address, providers::Provider};
+use eyre::{Context, ContextCompat, Result, bail};
+use serde::Deserialize;
+use tokio::sync::OnceCell;
+use tracing::debug;
+
+/// Путь до файла настроек по умолчанию
+pub const CONFIG_PATH: &str = "config.toml";
+/// Профиль по умолчанию
+pub const DEFAULT_PROFILE: &str = "dev";
+
+static PROFILE: OnceLock<Profile> = OnceLock::new();
+static CHAIN_ID_CHECKED: OnceCell<()> = OnceCell::const_new();
+
+/// Файл настроек с именованными профилями сетей
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Профиль, который используется, если не указан явно
+    #[serde(default = "default_profile")]
+    pub default: String,
+    pub profiles: HashMap<String, Profile>,
+}
+
+/// Настройки подключения к сети
+#[derive(Debug, Clone, Deserialize)]
+pub struct Profile {
+    #[serde(skip)]
+    pub name: String,
+    pub http_url: String,
+    pub ws_url: Option<String>,
+    /// Ожидаемый chain id узла
+    pub chain_id: u64,
+    pub keys: KeySource,
+    /// Файл с паролем от keystore
+    #[serde(default = "default_password_file")]
+    pub password_file: Option<PathBuf>,
+    /// Режим разработки: хранить расшифрованные ключи в `keys.private`
+    #[serde(default)]
+    pub plaintext_keys: bool,
+    /// Адреса уже развёрнутых контрактов по имени контракта (Bridge, DemoERC20, ...)
+    #[serde(default)]
+    pub contracts: HashMap<String, Address>,
+    /// Файл реестра развёрнутых контрактов
+    #[serde(default = "default_registry")]
+    pub registry: PathBuf,
+    /// Количество подтверждений, после которого событие считается обработанным
+    #[serde(default)]
+    pub confirmations: u64,
+    /// Директория файлов прогресса наблюдателей событий
+    #[serde(default = "default_checkpoints")]
+    pub checkpoints: PathBuf,
+}
+
+/// Откуда брать ключи аккаунтов
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    /// Директория geth keystore
+    Keystore(PathBuf),
+    /// Переменная окружения с приватными ключами через запятую
+    Env(String),
+}
+
+fn default_profile() -> String {
+    DEFAULT_PROFILE.to_string()
+}
+
+fn default_password_file() -> Option<PathBuf> {
+    Some(PathBuf::from("password.txt"))
+}
+
+fn default_registry() -> PathBuf {
+    PathBuf::from("deployments.json")
+}
+
+fn default_checkpoints() -> PathBuf {
+    PathBuf::from("checkpoints")
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            name: DEFAULT_PROFILE.to_string(),
+            http_url: "http://localhost:8545".to_string(),
+            ws_url: Some("ws://localhost:8546".to_string()),
+            chain_id: 1337,
+            keys: KeySource::Keystore(PathBuf::from("data/keystore")),
+            password_file: default_password_file(),
+            plaintext_keys: false,
+            contracts: HashMap::new(),
+            registry: default_registry(),
+            confirmations: 0,
+            checkpoints: default_checkpoints(),
+        }
+    }
+}
+
+impl Config {
+    pub fn parse(toml_str: &str) -> Result<Self> {
+        toml::from_str(toml_str).context("Ошибка в файле настроек")
+    }
+
+    /// Профиль по имени. Без имени берётся профиль по умолчанию
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile> {
+        let name = name.unwrap_or(&self.default);
+        let mut profile = self
+            .profiles
+            .get(name)
+            .cloned()
+            .with_context(|| format!("Профиль {name} не найден"))?;
+        profile.name = name.to_string();
+        Ok(profile)
+    }
+}
+
+impl Profile {
+    /// Загрузка профиля из файла настроек с учётом переменных окружения
+    ///
+    /// - `BRIDGE_CONFIG` - путь до файла настроек (по умолчанию `config.toml`)
+    /// - `BRIDGE_PROFILE` - имя профиля, если не передано явно
+    /// - `BRIDGE_HTTP_URL`, `BRIDGE_WS_URL`, `BRIDGE_CHAIN_ID` - переопределение полей профиля
+    /// - `BRIDGE_PLAINTEXT_KEYS=1` - режим разработки с ключами в открытом виде
+    /// - `BRIDGE_CONFIRMATIONS` - количество подтверждений для событий
+    pub fn load(name: Option<&str>) -> Result<Self> {
+        let name = name.map(String::from).or(env::var("BRIDGE_PROFILE").ok());
+        let path = env::var("BRIDGE_CONFIG").unwrap_or(CONFIG_PATH.to_string());
+
+        let mut profile = match fs::read_to_string(&path) {
+            Ok(content) => Config::parse(&content)?.profile(name.as_deref())?,
+            Err(err)
+                if err.kind() == io::ErrorKind::NotFound
+                    && (name.is_none() || name.as_deref() == Some(DEFAULT_PROFILE)) =>
+            {
+                debug!("Файл настроек {path} не найден. Используется профиль {DEFAULT_PROFILE}");
+                Profile::default()
+            }
+            Err(err) => return Err(err).context(format!("Не удалось прочитать файл {path}")),
+        };
+
+        if let Ok(url) = env::var("BRIDGE_HTTP_URL") {
+            profile.http_url = url;
+        }
+        if let Ok(url) = env::var("BRIDGE_WS_URL") {
+            profile.ws_url = Some(url);
+        }
+        if let Ok(chain_id) = env::var("BRIDGE_CHAIN_ID") {
+            profile.chain_id = chain_id
+                .parse()
+                .context("BRIDGE_CHAIN_ID должен быть числом")?;
+        }
+        if let Ok(confirmations) = env::var("BRIDGE_CONFIRMATIONS") {
+            profile.confirmations = confirmations
+                .parse()
+                .context("BRIDGE_CONFIRMATIONS должен быть числом")?;
+        }
+        if let Ok(plaintext_keys) = env::var("BRIDGE_PLAINTEXT_KEYS") {
+            profile.plaintext_keys = matches!(plaintext_keys.as_str(), "1" | "true");
+        }
+        Ok(profile)
+    }
+
+    /// Адрес контракта, заданный в профиле
+    pub fn contract(&self, name: &str) -> Option<Address> {
+        self.contracts.get(name).copied()
+    }
+}
+
+/// Выбор профиля. Вызывается до первого обращения к [`profile`]
+pub fn select(name: Option<&str>) -> Result<&'static Profile> {
+    if PROFILE.set(Profile::load(name)?).is_err() {
+        bail!("Профиль уже выбран");
+    }
+    profile()
+}
+
+/// Выбор готового профиля, например для локального узла тестов
+pub fn set(profile: Profile) -> Result<&'static Profile> {
+    if PROFILE.set(profile).is_err() {
+        bail!("Профиль уже выбран");
+    }
+    self::profile()
+}
+
+/// Текущий профиль. Если профиль не выбран, загружается профиль по умолчанию
+pub fn profile() -> Result<&'static Profile> {
+    if let Some(profile) = PROFILE.get() {
+        return Ok(profile);
+    }
+    let profile = Profile::load(None).context("Не удалось загрузить профиль")?;
+    Ok(PROFILE.get_or_init(|| profile))
+}
+
+/// Проверка узла `http_url` текущего профиля. Выполняется один раз за запуск
+pub async fn check_rpc_chain_id<P: Provider>(provider: &P) -> Result<()> {
+    CHAIN_ID_CHECKED
+        .get_or_try_init(|| async { check_chain_id(provider, profile()?).await })
+        .await
+        .copied()
+}
+
+/// Проверка, что узел относится к сети профиля `profile`
+pub async fn check_chain_id<P: Provider>(provider: &P, profile: &Profile) -> Result<()> {
+    let expected = profile.chain_id;
+    let chain_id = provider
+        .get_chain_id()
+        .await
+        .context("Не удалось получить chain id узла")?;
+    if chain_id != expected {
+        bail!(
+            "Узел работает в сети {chain_id}, а профиль {} ожидает {expected}",
+            profile.name
+        );
+    }
+    Ok(())
+}
@@ -1,109 +1,179 @@
-#![cfg(test)]
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
-use alloy::{providers::Provider, rpc::types::Filter};
-use alloy_sol_types::SolEvent;
-use eyre::Result;
+use alloy::{primitives::Address, providers::Provider};
+use eyre::{Context, ContextCompat, Result};
 use futures_util::StreamExt;
-use tokio::{sync::mpsc::Sender, task::JoinHandle};
+use tokio::sync::Mutex;
 
 use crate::{
     accounts::Signer,
-    contract_provider,
-    contracts::{Bridge, DemoERC20, console},
-    provider,
+    bus::EventBus,
+    config,
+    contracts::{Bridge::BridgeInstance, WalletProvider},
+    deployer::Deployer,
+    events::{self, BridgeEvent, EventFilter, EventKind, EventLog},
+    tasks::TaskSupervisor,
+    watcher::{WatchUpdate, Watcher},
 };
 
-pub(crate) async fn watch_logs(user: Signer) -> Result<JoinHandle<()>> {
-    let filter = Filter::new();
-    let provider = provider!(user);
-
-    let handle = tokio::spawn(async move {
-        let watch_send_event = provider.watch_logs(&filter).await.unwrap();
-        let mut k = watch_send_event.into_stream();
+/// Вывод всех событий в консоль
+pub fn watch_logs(tasks: &mut TaskSupervisor, user: Signer) {
+    tasks.spawn("watch_logs", move |token| {
+        let user = user.clone();
+        async move {
+            let provider = Deployer::connect(&user).await?.provider().clone();
+            let mut events = events::subscribe(&provider, &EventFilter::new()).await?;
 
-        while let Some(logs) = k.next().await {
-            for log in logs {
-                let Some(log_topic) = log.topic0() else {
-                    continue;
+            loop {
+                let event = tokio::select! {
+                    _ = token.cancelled() => return Ok(()),
+                    event = events.next() => event,
                 };
-
-                match *log_topic {
-                    Bridge::EventCreateBridge::SIGNATURE_HASH => {
-                        let msg = log.log_decode::<Bridge::EventCreateBridge>().unwrap();
+                let EventLog { event, .. } = event.context("Поток событий закрыт")?;
+                match &event {
+                    BridgeEvent::CreateBridge(data) => {
                         println!("Создание моста для ERC20");
-                        println!("Log: {:#?}", msg.data());
+                        println!("Log: {data:#?}");
                     }
-                    Bridge::EventDeposit::SIGNATURE_HASH => {
-                        let msg = log.log_decode::<Bridge::EventDeposit>().unwrap();
+                    BridgeEvent::Deposit(data) => {
                         println!("Перевод ETH с l1=>l2");
-                        println!("Log: {:#?}", msg.data());
+                        println!("Log: {data:#?}");
                     }
-                    Bridge::EventDepositRC20::SIGNATURE_HASH => {
-                        let msg = log.log_decode::<Bridge::EventDepositRC20>().unwrap();
+                    BridgeEvent::DepositErc20(data) => {
                         println!("Перевод ERC20 с l1=>l2");
-                        println!("Log: {:#?}", msg.data());
-                    }
-
-                    DemoERC20::Approval::SIGNATURE_HASH => {
-                        let msg = log.log_decode::<DemoERC20::Approval>().unwrap();
-                        println!("Log: {:#?}", msg.data());
-                    }
-                    console::Vote::SIGNATURE_HASH => {
-                        let msg = log.log_decode::<console::Vote>().unwrap();
-                        println!("Log: {:#?}", msg.data());
+                        println!("Log: {data:#?}");
                     }
-                    console::VoteString::SIGNATURE_HASH => {
-                        let msg = log.log_decode::<console::VoteString>().unwrap();
-                        println!("Log: {:#?}", msg.data());
-                    }
-                    console::VoteAdderss::SIGNATURE_HASH => {
-                        let msg = log.log_decode::<console::VoteAdderss>().unwrap();
-                        println!("Log: {:#?}", msg.data());
-                    }
-                    console::VoteNumber::SIGNATURE_HASH => {
-                        let msg = log.log_decode::<console::VoteNumber>().unwrap();
-                        println!("Log: {:#?}", msg.data());
-                    }
-                    _ => (),
+                    _ => println!("Log: {event:#?}"),
                 }
             }
         }
     });
-    Ok(handle)
 }
 
-pub(crate) async fn watch_deposit_event(
-    user: Signer,
-    sender: Sender<Bridge::EventDeposit>,
-) -> Result<JoinHandle<()>> {
-    let bridge = contract_provider!(Bridge, user);
+/// Интервал опроса узла наблюдателями
+pub const WATCH_INTERVAL: Duration = Duration::from_millis(500);
 
-    let event_handle = tokio::spawn(async move {
-        use futures_util::StreamExt;
+/// Наблюдение за событиями с сохранением прогресса в `checkpoint`. Без файла прогресс
+/// не сохраняется. Без `start_block` наблюдение начинается со следующего блока.
+/// Изменения публикуются в `bus`, подписчики получают `None` после остановки задач.
+/// После перезапуска задачи наблюдение продолжается с последнего опроса, все изменения
+/// которого опубликованы
+pub async fn watch_events(
+    tasks: &mut TaskSupervisor,
+    user: Signer,
+    filter: EventFilter,
+    checkpoint: Option<&Path>,
+    start_block: Option<u64>,
+    bus: EventBus<WatchUpdate>,
+) -> Result<()> {
+    let start_block = match start_block {
+        Some(start_block) => start_block,
+        None => {
+            Deployer::connect(&user)
+                .await?
+                .provider()
+                .get_block_number()
+                .await
+                .context("Не удалось получить номер блока")?
+                + 1
+        }
+    };
+    let watcher = Arc::new(Mutex::new(Watcher::new(
+        filter,
+        config::profile()?.confirmations,
+        checkpoint,
+        start_block,
+    )?));
 
-        let watch_send_event = bridge.EventDeposit_filter().watch().await.unwrap();
-        let mut k = watch_send_event.into_stream();
-        while let Some(Ok((event, _l))) = k.next().await {
-            sender.send(event).await.unwrap();
+    tasks.spawn("watch_events", move |token| {
+        let (user, watcher, bus) = (user.clone(), watcher.clone(), bus.clone());
+        async move {
+            let provider = Deployer::connect(&user).await?.provider().clone();
+            watcher
+                .lock()
+                .await
+                .run(&provider, WATCH_INTERVAL, &bus, token.cancelled())
+                .await
         }
     });
-    Ok(event_handle)
+    Ok(())
 }
 
-pub(crate) async fn watch_deposit_erc20_event(
+/// Подтверждённые события одного типа моста `bridge`. Прогресс сохраняется в `checkpoint`,
+/// при первом запуске наблюдение начинается с `start_block`
+pub async fn watch_bridge_event(
+    tasks: &mut TaskSupervisor,
     user: Signer,
-    sender: Sender<Bridge::EventDepositRC20>,
-) -> Result<JoinHandle<()>> {
-    let bridge = contract_provider!(Bridge, user);
+    bridge: Address,
+    kind: EventKind,
+    checkpoint: Option<&Path>,
+    start_block: Option<u64>,
+    bus: EventBus<WatchUpdate>,
+) -> Result<()> {
+    let filter = EventFilter::new().address(bridge).kind(kind);
+    watch_events(tasks, user, filter, checkpoint, start_block, bus).await
+}
 
-    let event_handle = tokio::spawn(async move {
-        use futures_util::StreamExt;
+/// Адрес `Bridge` из профиля или реестра
+async fn bridge_address(user: &Signer) -> Result<Address> {
+    Ok(Deployer::connect(user)
+        .await?
+        .find::<BridgeInstance<WalletProvider>>()
+        .await?
+        .address())
+}
 
-        let watch_send_event = bridge.EventDepositRC20_filter().watch().await.unwrap();
-        let mut k = watch_send_event.into_stream();
-        while let Some(Ok((event, _l))) = k.next().await {
-            sender.send(event).await.unwrap();
-        }
-    });
-    Ok(event_handle)
+/// Файл прогресса наблюдателя событий `name` моста `bridge` в директории из профиля
+fn checkpoint_path(name: &str, bridge: Address) -> Result<PathBuf> {
+    Ok(config::profile()?
+        .checkpoints
+        .join(format!("{name}-{bridge}.json")))
+}
+
+/// Депозиты ETH. Прогресс сохраняется в директории `checkpoints` профиля
+pub async fn watch_deposit_event(
+    tasks: &mut TaskSupervisor,
+    user: Signer,
+    start_block: Option<u64>,
+    bus: EventBus<WatchUpdate>,
+) -> Result<()> {
+    let bridge = bridge_address(&user).await?;
+    let checkpoint = checkpoint_path("deposit", bridge)?;
+    let kind = EventKind::Deposit;
+    watch_bridge_event(
+        tasks,
+        user,
+        bridge,
+        kind,
+        Some(&checkpoint),
+        start_block,
+        bus,
+    )
+    .await
+}
+
+/// Депозиты ERC20. Прогресс сохраняется в директории `checkpoints` профиля
+pub async fn watch_deposit_erc20_event(
+    tasks: &mut TaskSupervisor,
+    user: Signer,
+    start_block: Option<u64>,
+    bus: EventBus<WatchUpdate>,
+) -> Result<()> {
+    let bridge = bridge_address(&user).await?;
+    let checkpoint = checkpoint_path("deposit-erc20", bridge)?;
+    let kind = EventKind::DepositErc20;
+    watch_bridge_event(
+        tasks,
+        user,
+        bridge,
+        kind,
+        Some(&checkpoint),
+        start_block,
+        bus,
+    )
+    .await
 }
@@ -0,0 +1,306 @@
+//! Провайдеры и контракты от имени аккаунта
+//!
+//! [`Deployer`] подключает аккаунт к узлу из профиля и находит контракты: адрес из профиля, из
+//! реестра развёртываний или новое развёртывание. [`Deployer::find`] только ищет контракт и
+//! не отправляет транзакций. Контракт возвращается как [`ContractHandle`] над
+//! любым типом `sol!`, реализующим [`Contract`]. [`Funder`] пополняет аккаунты ETH и токенами,
+//! [`token_balances`] возвращает балансы нескольких токенов.
+
+use std::{future::Future, ops::Deref};
+
+use alloy::{
+    network::TransactionBuilder,
+    primitives::{Address, Bytes, U256},
+    providers::{Provider, ProviderBuilder, WalletProvider as _},
+    rpc::types::TransactionRequest,
+};
+use eyre::{Context, Result, bail};
+use tracing::{debug, info};
+
+use crate::{
+    accounts::Signer,
+    amount::TokenAmount,
+    config,
+    contracts::{
+        Bridge::{self, BridgeInstance},
+        DemoERC20::{self, DemoERC20Instance},
+        ExmERC20::{self, ExmERC20Instance},
+        TestERC20::{self, TestERC20Instance},
+        WalletProvider, fillers,
+    },
+    nonce::Nonces,
+    registry::{self, DEPLOY_LOCK, DeploymentKey},
+};
+
+/// Баланс токена, до которого [`Funder`] пополняет аккаунты по умолчанию, в целых токенах
+pub const FUND_TOKENS: u64 = 10;
+
+/// Контракт, сгенерированный `sol!`, с провайдером [`WalletProvider`]
+pub trait Contract: Sized {
+    /// Имя контракта в профиле и реестре развёртываний
+    const NAME: &'static str;
+
+    /// Байткод создания
+    fn bytecode() -> &'static Bytes;
+
+    /// Контракт по известному адресу
+    fn at(address: Address, provider: WalletProvider) -> Self;
+
+    /// Развёртывание нового контракта
+    fn deploy(provider: WalletProvider) -> impl Future<Output = Result<(Address, Self)>> + Send;
+}
+
+macro_rules! impl_contract {
+    ($($module: ident => $instance: ident),* $(,)?) => {$(
+        impl Contract for $instance<WalletProvider> {
+            const NAME: &'static str = stringify!($module);
+
+            fn bytecode() -> &'static Bytes {
+                &$module::BYTECODE
+            }
+
+            fn at(address: Address, provider: WalletProvider) -> Self {
+                $module::new(address, provider)
+            }
+
+            async fn deploy(provider: WalletProvider) -> Result<(Address, Self)> {
+                let contract = $module::deploy(provider)
+                    .await
+                    .with_context(|| format!("Не удалось развернуть {}", Self::NAME))?;
+                Ok((*contract.address(), contract))
+            }
+        }
+    )*};
+}
+
+impl_contract!(
+    Bridge => BridgeInstance,
+    DemoERC20 => DemoERC20Instance,
+    TestERC20 => TestERC20Instance,
+    ExmERC20 => ExmERC20Instance,
+);
+
+/// Контракт, подключённый от имени аккаунта. Методы контракта доступны через `Deref`
+#[derive(Debug, Clone)]
+pub struct ContractHandle<C> {
+    address: Address,
+    contract: C,
+}
+
+impl<C> ContractHandle<C> {
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    pub fn into_inner(self) -> C {
+        self.contract
+    }
+}
+
+impl<C> Deref for ContractHandle<C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        &self.contract
+    }
+}
+
+/// Аккаунт, подключённый к узлу из профиля
+#[derive(Debug, Clone)]
+pub struct Deployer {
+    provider: WalletProvider,
+}
+
+impl Deployer {
+    /// Провайдер с кошельком `signer` и общим счётчиком nonce. Chain id узла сверяется с профилем
+    pub async fn connect(signer: &Signer) -> Result<Self> {
+        let provider = ProviderBuilder::new()
+            .disable_recommended_fillers()
+            .filler(fillers(Nonces::global()))
+            .wallet(signer.clone())
+            .connect(&config::profile()?.http_url)
+            .await
+            .context("Не удалось подключиться к узлу")?;
+        config::check_rpc_chain_id(&provider).await?;
+        Ok(Deployer { provider })
+    }
+
+    pub fn new(provider: WalletProvider) -> Self {
+        Deployer { provider }
+    }
+
+    pub fn provider(&self) -> &WalletProvider {
+        &self.provider
+    }
+
+    /// Адрес аккаунта
+    pub fn address(&self) -> Address {
+        self.provider.default_signer_address()
+    }
+
+    /// Контракт по известному адресу
+    pub fn at<C: Contract>(&self, address: Address) -> ContractHandle<C> {
+        ContractHandle {
+            address,
+            contract: C::at(address, self.provider.clone()),
+        }
+    }
+
+    /// Контракт из профиля или реестра без развёртывания. Если контракта нет,
+    /// возвращается ошибка
+    pub async fn find<C: Contract>(&self) -> Result<ContractHandle<C>> {
+        let profile = config::profile()?;
+        if let Some(address) = profile.contract(C::NAME) {
+            return Ok(self.at(address));
+        }
+
+        let key = DeploymentKey::new(&self.provider, C::NAME, C::bytecode()).await?;
+        match registry::lookup(&self.provider, &key).await? {
+            Some(address) => Ok(self.at(address)),
+            None => bail!(
+                "{} не найден в профиле {} и реестре {}. Разверните контракты командой deploy",
+                C::NAME,
+                profile.name,
+                profile.registry.display()
+            ),
+        }
+    }
+
+    /// Контракт из профиля или реестра. Если его нет в сети, он разворачивается
+    /// и записывается в реестр
+    pub async fn contract<C: Contract>(&self) -> Result<ContractHandle<C>> {
+        if let Some(address) = config::profile()?.contract(C::NAME) {
+            return Ok(self.at(address));
+        }
+
+        let _guard = DEPLOY_LOCK.lock().await;
+        let key = DeploymentKey::new(&self.provider, C::NAME, C::bytecode()).await?;
+        if let Some(address) = registry::lookup(&self.provider, &key).await? {
+            return Ok(self.at(address));
+        }
+
+        let contract = self.deploy::<C>().await?;
+        registry::record(&self.provider, key, contract.address()).await?;
+        Ok(contract)
+    }
+
+    /// Новое развёртывание без записи в реестр
+    pub async fn deploy<C: Contract>(&self) -> Result<ContractHandle<C>> {
+        let (address, contract) = C::deploy(self.provider.clone()).await?;
+        info!("{} развёрнут по адресу {address}", C::NAME);
+        Ok(ContractHandle { address, contract })
+    }
+}
+
+/// Пополнение аккаунтов с аккаунта [`Deployer`]
+#[derive(Debug, Clone)]
+pub struct Funder {
+    deployer: Deployer,
+    tokens: u64,
+}
+
+impl Funder {
+    pub fn new(deployer: Deployer) -> Self {
+        Funder {
+            deployer,
+            tokens: FUND_TOKENS,
+        }
+    }
+
+    /// Баланс токена после пополнения, в целых токенах. По умолчанию [`FUND_TOKENS`]
+    pub fn tokens(mut self, tokens: u64) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
+    /// Перевод `amount` wei каждому адресу
+    pub async fn fund_eth(&self, addresses: &[Address], amount: U256) -> Result<()> {
+        let provider = self.deployer.provider();
+        for &address in addresses {
+            let receipt = provider
+                .send_transaction(
+                    TransactionRequest::default()
+                        .with_to(address)
+                        .with_value(amount),
+                )
+                .await
+                .with_context(|| format!("Не удалось пополнить {address}"))?
+                .get_receipt()
+                .await
+                .with_context(|| format!("Не удалось пополнить {address}"))?;
+            if !receipt.status() {
+                bail!(
+                    "Пополнение {address} откатилось: {}",
+                    receipt.transaction_hash
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Перевод токена ERC20 адресам, у которых его меньше заданного баланса
+    pub async fn fund_token(&self, token: Address, addresses: &[Address]) -> Result<()> {
+        let token = DemoERC20::new(token, self.deployer.provider());
+        let symbol = token
+            .symbol()
+            .call()
+            .await
+            .context("Не удалось получить символ токена")?;
+        let decimals = token
+            .decimals()
+            .call()
+            .await
+            .context("Не удалось получить точность токена")?;
+        let min = TokenAmount::from_tokens(self.tokens, decimals);
+
+        for &address in addresses {
+            let balance = token
+                .balanceOf(address)
+                .call()
+                .await
+                .context("Не удалось получить баланс токена")?;
+            if balance > min.value() {
+                debug!("Баланс {symbol} в норме {address}");
+                continue;
+            }
+
+            let receipt = token
+                .transfer(address, min.value())
+                .send()
+                .await
+                .with_context(|| format!("Не удалось перевести {symbol} на {address}"))?
+                .get_receipt()
+                .await
+                .with_context(|| format!("Не удалось перевести {symbol} на {address}"))?;
+            if !receipt.status() {
+                bail!(
+                    "Перевод {min} {symbol} на {address} откатился: {}",
+                    receipt.transaction_hash
+                );
+            }
+            info!(
+                "Перевод {min} {symbol} на {address}: {}",
+                receipt.transaction_hash
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Балансы `owner` в каждом из токенов ERC20
+pub async fn token_balances<P: Provider>(
+    provider: &P,
+    owner: Address,
+    tokens: &[Address],
+) -> Result<Vec<U256>> {
+    let mut balances = Vec::with_capacity(tokens.len());
+    for &token in tokens {
+        let balance = DemoERC20::new(token, provider)
+            .balanceOf(owner)
+            .call()
+            .await
+            .with_context(|| format!("Не удалось получить баланс токена {token}"))?;
+        balances.push(balance);
+    }
+    Ok(balances)
+}
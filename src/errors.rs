@@ -0,0 +1,154 @@
+//! Причины отказа контракта `Bridge`
+//!
+//! Узел возвращает причину отката как `Error(string)` или `Panic(uint256)`. Сообщения
+//! `require` из `Bridge.sol` разбираются в [`BridgeError`], чтобы тесты и CLI проверяли
+//! конкретную причину, а не только факт ошибки.
+
+use alloy::{
+    contract,
+    primitives::{Bytes, U256},
+    transports::TransportError,
+};
+use alloy_sol_types::{Panic, PanicKind, Revert, SolError};
+use thiserror::Error;
+
+/// Отказ контракта `Bridge`. Текст варианта совпадает с сообщением `require`
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BridgeError {
+    #[error("This request can only be completed by the owner")]
+    NotOwner,
+    /// Сумма не переводится в точность `decimals` без потери младших разрядов
+    #[error("Couldn't round up to the precision of {0}")]
+    Precision(u8),
+    #[error("There are no funds for withdrawal")]
+    NoFundsForWithdrawal,
+    #[error("Insufficient funds in the wallet")]
+    InsufficientEth,
+    #[error("The bridge has not been created yet")]
+    BridgeNotCreated,
+    #[error("The amount exceeds the allowed maximum")]
+    AmountExceedsMaximum,
+    #[error("The token has already been added")]
+    TokenAlreadyAdded,
+    #[error("A 1 ETH commission is required to create a bridge.")]
+    CreationCommission,
+    #[error("the transfer must be approved")]
+    TransferNotApproved,
+    #[error("Failed to send")]
+    TransferFailed,
+    #[error("Insufficient funds to transfer funds from the wallet to the user")]
+    InsufficientTokens,
+    #[error("No withdrawal requests")]
+    NoWithdrawalRequests,
+    /// `Panic(uint256)`: переполнение, деление на ноль и т.п.
+    #[error("Panic {code}: {}", panic_kind(*code))]
+    Panic { code: U256 },
+    /// `Error(string)` с сообщением, которого нет в `Bridge.sol`
+    #[error("{0}")]
+    Revert(String),
+    /// Откат без причины или с нестандартными данными
+    #[error("Execution reverted: {0}")]
+    Raw(Bytes),
+}
+
+impl BridgeError {
+    /// Разбор сообщения `require`
+    pub fn from_reason(reason: &str) -> Self {
+        let known = [
+            BridgeError::NotOwner,
+            BridgeError::NoFundsForWithdrawal,
+            BridgeError::InsufficientEth,
+            BridgeError::BridgeNotCreated,
+            BridgeError::AmountExceedsMaximum,
+            BridgeError::TokenAlreadyAdded,
+            BridgeError::CreationCommission,
+            BridgeError::TransferNotApproved,
+            BridgeError::TransferFailed,
+            BridgeError::InsufficientTokens,
+            BridgeError::NoWithdrawalRequests,
+        ];
+        if let Some(err) = known.into_iter().find(|v| v.to_string() == reason) {
+            return err;
+        }
+
+        reason
+            .strip_prefix("Couldn't round up to the precision of ")
+            .and_then(|v| v.parse().ok())
+            .map(BridgeError::Precision)
+            .unwrap_or_else(|| BridgeError::Revert(reason.to_string()))
+    }
+
+    /// Разбор данных отката (`Error(string)`, `Panic(uint256)` или произвольные байты)
+    pub fn from_revert_data(data: &[u8]) -> Self {
+        if let Ok(revert) = Revert::abi_decode(data) {
+            return BridgeError::from_reason(&revert.reason);
+        }
+        if let Ok(panic) = Panic::abi_decode(data) {
+            return BridgeError::Panic { code: panic.code };
+        }
+        BridgeError::Raw(Bytes::copy_from_slice(data))
+    }
+
+    /// Причина отката из ошибки узла. `None`, если ошибка не связана с откатом
+    pub fn from_transport_error(err: &TransportError) -> Option<Self> {
+        let payload = err.as_error_resp()?;
+        if let Some(data) = payload.as_revert_data() {
+            return Some(BridgeError::from_revert_data(&data));
+        }
+        // Некоторые узлы не возвращают данные, только текст
+        let message = payload.message.as_ref();
+        message
+            .strip_prefix("execution reverted: ")
+            .map(BridgeError::from_reason)
+            .or_else(|| (message == "execution reverted").then(|| BridgeError::Raw(Bytes::new())))
+    }
+
+    /// Причина отката из ошибки вызова контракта
+    pub fn from_contract_error(err: &contract::Error) -> Option<Self> {
+        match err {
+            contract::Error::TransportError(err) => BridgeError::from_transport_error(err),
+            _ => None,
+        }
+    }
+
+    /// Причина отката из цепочки ошибок eyre
+    pub fn from_report(report: &eyre::Report) -> Option<Self> {
+        report.chain().find_map(|err| {
+            if let Some(err) = err.downcast_ref::<BridgeError>() {
+                Some(err.clone())
+            } else if let Some(err) = err.downcast_ref::<contract::Error>() {
+                BridgeError::from_contract_error(err)
+            } else {
+                err.downcast_ref::<TransportError>()
+                    .and_then(BridgeError::from_transport_error)
+            }
+        })
+    }
+}
+
+fn panic_kind(code: U256) -> &'static str {
+    Panic { code }.kind().map_or("unknown", PanicKind::as_str)
+}
+
+/// Замена ошибки вызова контракта на причину отката, если её удалось разобрать
+pub trait RevertExt<T> {
+    fn decode_revert(self) -> eyre::Result<T>;
+}
+
+impl<T> RevertExt<T> for Result<T, contract::Error> {
+    fn decode_revert(self) -> eyre::Result<T> {
+        self.map_err(|err| match BridgeError::from_contract_error(&err) {
+            Some(reason) => eyre::Report::new(reason),
+            None => eyre::Report::new(err),
+        })
+    }
+}
+
+impl<T> RevertExt<T> for eyre::Result<T> {
+    fn decode_revert(self) -> eyre::Result<T> {
+        self.map_err(|err| match BridgeError::from_report(&err) {
+            Some(reason) => eyre::Report::new(reason),
+            None => err,
+        })
+    }
+}
@@ -0,0 +1,579 @@
+//! Типизированные события контрактов `Bridge`, `console` и ERC20
+
+use std::{str::FromStr, time::Duration};
+
+use alloy::{
+    primitives::{self, Address, B256, LogData},
+    providers::Provider,
+    rpc::types::{Filter, Log},
+    transports::{RpcError, TransportError},
+};
+use alloy_sol_types::SolEvent;
+use eyre::{Context, ContextCompat, Result, bail};
+use futures_util::{Stream, StreamExt, stream, stream::BoxStream};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::{
+    config,
+    contracts::{Bridge, DemoERC20, console},
+    pubsub,
+};
+
+/// Событие любого из контрактов
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BridgeEvent {
+    /// Создание моста для ERC20
+    CreateBridge(Bridge::EventCreateBridge),
+    /// Перевод ETH с l1=>l2
+    Deposit(Bridge::EventDeposit),
+    /// Перевод ERC20 с l1=>l2
+    DepositErc20(Bridge::EventDepositRC20),
+    /// ERC20 Transfer
+    Transfer(DemoERC20::Transfer),
+    /// ERC20 Approval
+    Approval(DemoERC20::Approval),
+    Vote(console::Vote),
+    VoteString(console::VoteString),
+    VoteAddress(console::VoteAdderss),
+    VoteNumber(console::VoteNumber),
+}
+
+/// Тип события, для фильтрации подписки
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    CreateBridge,
+    Deposit,
+    DepositErc20,
+    Transfer,
+    Approval,
+    Vote,
+    VoteString,
+    VoteAddress,
+    VoteNumber,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 9] = [
+        EventKind::CreateBridge,
+        EventKind::Deposit,
+        EventKind::DepositErc20,
+        EventKind::Transfer,
+        EventKind::Approval,
+        EventKind::Vote,
+        EventKind::VoteString,
+        EventKind::VoteAddress,
+        EventKind::VoteNumber,
+    ];
+
+    /// topic0 события
+    pub const fn signature_hash(self) -> B256 {
+        match self {
+            EventKind::CreateBridge => Bridge::EventCreateBridge::SIGNATURE_HASH,
+            EventKind::Deposit => Bridge::EventDeposit::SIGNATURE_HASH,
+            EventKind::DepositErc20 => Bridge::EventDepositRC20::SIGNATURE_HASH,
+            EventKind::Transfer => DemoERC20::Transfer::SIGNATURE_HASH,
+            EventKind::Approval => DemoERC20::Approval::SIGNATURE_HASH,
+            EventKind::Vote => console::Vote::SIGNATURE_HASH,
+            EventKind::VoteString => console::VoteString::SIGNATURE_HASH,
+            EventKind::VoteAddress => console::VoteAdderss::SIGNATURE_HASH,
+            EventKind::VoteNumber => console::VoteNumber::SIGNATURE_HASH,
+        }
+    }
+
+    pub fn from_signature_hash(hash: &B256) -> Option<Self> {
+        EventKind::ALL
+            .into_iter()
+            .find(|v| v.signature_hash() == *hash)
+    }
+}
+
+impl FromStr for EventKind {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_lowercase().replace('-', "_").as_str() {
+            "create_bridge" => EventKind::CreateBridge,
+            "deposit" => EventKind::Deposit,
+            "deposit_erc20" => EventKind::DepositErc20,
+            "transfer" => EventKind::Transfer,
+            "approval" => EventKind::Approval,
+            "vote" => EventKind::Vote,
+            "vote_string" => EventKind::VoteString,
+            "vote_address" => EventKind::VoteAddress,
+            "vote_number" => EventKind::VoteNumber,
+            _ => bail!("Неизвестный тип события {s}"),
+        })
+    }
+}
+
+impl BridgeEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            BridgeEvent::CreateBridge(_) => EventKind::CreateBridge,
+            BridgeEvent::Deposit(_) => EventKind::Deposit,
+            BridgeEvent::DepositErc20(_) => EventKind::DepositErc20,
+            BridgeEvent::Transfer(_) => EventKind::Transfer,
+            BridgeEvent::Approval(_) => EventKind::Approval,
+            BridgeEvent::Vote(_) => EventKind::Vote,
+            BridgeEvent::VoteString(_) => EventKind::VoteString,
+            BridgeEvent::VoteAddress(_) => EventKind::VoteAddress,
+            BridgeEvent::VoteNumber(_) => EventKind::VoteNumber,
+        }
+    }
+
+    /// Разбор лога. Для неизвестных событий возвращает `None`
+    pub fn decode(log: &Log) -> Result<Option<Self>> {
+        let Some(kind) = log.topic0().and_then(EventKind::from_signature_hash) else {
+            return Ok(None);
+        };
+
+        let event = match kind {
+            EventKind::CreateBridge => BridgeEvent::CreateBridge(decode(log)?),
+            EventKind::Deposit => BridgeEvent::Deposit(decode(log)?),
+            EventKind::DepositErc20 => BridgeEvent::DepositErc20(decode(log)?),
+            EventKind::Transfer => BridgeEvent::Transfer(decode(log)?),
+            EventKind::Approval => BridgeEvent::Approval(decode(log)?),
+            EventKind::Vote => BridgeEvent::Vote(decode(log)?),
+            EventKind::VoteString => BridgeEvent::VoteString(decode(log)?),
+            EventKind::VoteAddress => BridgeEvent::VoteAddress(decode(log)?),
+            EventKind::VoteNumber => BridgeEvent::VoteNumber(decode(log)?),
+        };
+        Ok(Some(event))
+    }
+
+    /// Данные лога, из которых событие разбирается обратно [`BridgeEvent::decode`]
+    pub fn encode_log_data(&self) -> LogData {
+        match self {
+            BridgeEvent::CreateBridge(v) => v.encode_log_data(),
+            BridgeEvent::Deposit(v) => v.encode_log_data(),
+            BridgeEvent::DepositErc20(v) => v.encode_log_data(),
+            BridgeEvent::Transfer(v) => v.encode_log_data(),
+            BridgeEvent::Approval(v) => v.encode_log_data(),
+            BridgeEvent::Vote(v) => v.encode_log_data(),
+            BridgeEvent::VoteString(v) => v.encode_log_data(),
+            BridgeEvent::VoteAddress(v) => v.encode_log_data(),
+            BridgeEvent::VoteNumber(v) => v.encode_log_data(),
+        }
+    }
+}
+
+fn decode<E: SolEvent>(log: &Log) -> Result<E> {
+    log.log_decode::<E>()
+        .map(|v| v.inner.data)
+        .with_context(|| format!("Не удалось разобрать событие {}", E::SIGNATURE))
+}
+
+/// Положение лога в сети
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct LogMeta {
+    /// Контракт, который создал событие
+    pub address: Address,
+    pub block_number: u64,
+    pub block_hash: B256,
+    pub tx_hash: B256,
+    pub log_index: u64,
+}
+
+impl LogMeta {
+    pub fn from_log(log: &Log) -> Result<Self> {
+        Ok(LogMeta {
+            address: log.address(),
+            block_number: log.block_number.context("В логе нет номера блока")?,
+            block_hash: log.block_hash.context("В логе нет хэша блока")?,
+            tx_hash: log.transaction_hash.context("В логе нет хэша транзакции")?,
+            log_index: log.log_index.context("В логе нет номера")?,
+        })
+    }
+}
+
+/// Событие вместе с положением в сети.
+/// Сериализуется как исходный лог и при чтении разбирается заново
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "RawEventLog", try_from = "RawEventLog")]
+pub struct EventLog {
+    pub meta: LogMeta,
+    pub event: BridgeEvent,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RawEventLog {
+    meta: LogMeta,
+    data: LogData,
+}
+
+impl From<EventLog> for RawEventLog {
+    fn from(value: EventLog) -> Self {
+        RawEventLog {
+            meta: value.meta,
+            data: value.event.encode_log_data(),
+        }
+    }
+}
+
+impl TryFrom<RawEventLog> for EventLog {
+    type Error = eyre::Report;
+
+    fn try_from(value: RawEventLog) -> Result<Self> {
+        let meta = value.meta;
+        let log = Log {
+            inner: primitives::Log {
+                address: meta.address,
+                data: value.data,
+            },
+            block_hash: Some(meta.block_hash),
+            block_number: Some(meta.block_number),
+            transaction_hash: Some(meta.tx_hash),
+            log_index: Some(meta.log_index),
+            ..Default::default()
+        };
+        EventLog::from_log(&log)?.context("Неизвестное событие")
+    }
+}
+
+impl EventLog {
+    pub fn from_log(log: &Log) -> Result<Option<Self>> {
+        let Some(event) = BridgeEvent::decode(log)? else {
+            return Ok(None);
+        };
+        Ok(Some(EventLog {
+            meta: LogMeta::from_log(log)?,
+            event,
+        }))
+    }
+}
+
+/// Фильтр подписки: адреса контрактов и типы событий. Пустой список означает «все»
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    pub addresses: Vec<Address>,
+    pub kinds: Vec<EventKind>,
+}
+
+impl EventFilter {
+    pub fn new() -> Self {
+        EventFilter::default()
+    }
+
+    pub fn address(mut self, address: Address) -> Self {
+        self.addresses.push(address);
+        self
+    }
+
+    pub fn kind(mut self, kind: EventKind) -> Self {
+        self.kinds.push(kind);
+        self
+    }
+
+    pub fn kinds(mut self, kinds: impl IntoIterator<Item = EventKind>) -> Self {
+        self.kinds.extend(kinds);
+        self
+    }
+
+    /// Фильтр для `eth_getLogs` / `eth_newFilter`
+    pub fn to_filter(&self) -> Filter {
+        let kinds = if self.kinds.is_empty() {
+            EventKind::ALL.to_vec()
+        } else {
+            self.kinds.clone()
+        };
+        let filter = Filter::new().event_signature(
+            kinds
+                .into_iter()
+                .map(EventKind::signature_hash)
+                .collect::<Vec<_>>(),
+        );
+        if self.addresses.is_empty() {
+            filter
+        } else {
+            filter.address(self.addresses.clone())
+        }
+    }
+
+    pub fn matches(&self, event: &EventLog) -> bool {
+        (self.addresses.is_empty() || self.addresses.contains(&event.meta.address))
+            && (self.kinds.is_empty() || self.kinds.contains(&event.event.kind()))
+    }
+}
+
+/// Разбор пачки логов. Нераспознанные логи пропускаются
+pub fn decode_logs(logs: Vec<Log>) -> Vec<EventLog> {
+    logs.iter()
+        .filter_map(|log| match EventLog::from_log(log) {
+            Ok(event) => event,
+            Err(err) => {
+                warn!("{err:#}");
+                None
+            }
+        })
+        .collect()
+}
+
+/// Поток событий с текущей вершины сети: через WebSocket, если в профиле задан `ws_url`,
+/// иначе опросом фильтра через `provider`
+pub async fn subscribe<P: Provider>(
+    provider: &P,
+    filter: &EventFilter,
+) -> Result<BoxStream<'static, EventLog>> {
+    let profile = config::profile()?;
+    if profile.ws_url.is_some() {
+        match pubsub::subscribe_ws(profile, filter).await {
+            Ok(events) => return Ok(events.boxed()),
+            Err(err) => warn!("{err:#}. События читаются опросом фильтра"),
+        }
+    }
+    Ok(poll(provider, filter).await?.boxed())
+}
+
+/// Поток событий с текущей вершины сети опросом фильтра (`eth_getFilterChanges`)
+pub async fn poll<P: Provider>(
+    provider: &P,
+    filter: &EventFilter,
+) -> Result<impl Stream<Item = EventLog> + Send + 'static> {
+    let poller = provider
+        .watch_logs(&filter.to_filter())
+        .await
+        .context("Не удалось создать фильтр логов")?;
+
+    let filter = filter.clone();
+    Ok(poller
+        .into_stream()
+        .flat_map(|logs| stream::iter(decode_logs(logs)))
+        .filter(move |event| std::future::ready(filter.matches(event))))
+}
+
+/// Максимальный диапазон блоков одного запроса `eth_getLogs` по умолчанию
+pub const BACKFILL_RANGE: u64 = 10_000;
+
+/// Задержка перед первым повтором запроса после превышения лимита запросов
+pub const RATE_LIMIT_DELAY: Duration = Duration::from_secs(1);
+/// Количество повторов запроса после превышения лимита запросов
+pub const RATE_LIMIT_RETRIES: u32 = 5;
+
+/// Чтение истории событий запросами `eth_getLogs` по диапазонам блоков.
+/// Если узел отказывает из-за слишком большого диапазона, диапазон делится пополам.
+/// При превышении лимита запросов тот же диапазон запрашивается повторно с растущей задержкой
+#[derive(Debug, Clone)]
+pub struct Backfill {
+    filter: EventFilter,
+    next_block: u64,
+    to_block: u64,
+    range: u64,
+}
+
+impl Backfill {
+    /// История в блоках `from_block..=to_block`
+    pub fn new(filter: EventFilter, from_block: u64, to_block: u64) -> Self {
+        Backfill {
+            filter,
+            next_block: from_block,
+            to_block,
+            range: BACKFILL_RANGE,
+        }
+    }
+
+    pub fn max_range(mut self, range: u64) -> Self {
+        self.range = range.max(1);
+        self
+    }
+
+    /// Первый ещё не прочитанный блок
+    pub fn next_block(&self) -> u64 {
+        self.next_block
+    }
+
+    /// Текущий размер диапазона, с учётом уменьшения после отказов узла
+    pub fn range(&self) -> u64 {
+        self.range
+    }
+
+    /// События следующего диапазона блоков. `None`, когда история прочитана
+    pub async fn next_chunk<P: Provider>(&mut self, provider: &P) -> Result<Option<Vec<EventLog>>> {
+        if self.next_block > self.to_block {
+            return Ok(None);
+        }
+
+        let mut delay = RATE_LIMIT_DELAY;
+        let mut retries = 0;
+        loop {
+            let to_block = self
+                .next_block
+                .saturating_add(self.range - 1)
+                .min(self.to_block);
+            let filter = self
+                .filter
+                .to_filter()
+                .from_block(self.next_block)
+                .to_block(to_block);
+
+            match provider.get_logs(&filter).await {
+                Ok(logs) => {
+                    debug!("История: блоки {}..={to_block}", self.next_block);
+                    self.next_block = to_block + 1;
+                    let events = decode_logs(logs)
+                        .into_iter()
+                        .filter(|v| self.filter.matches(v))
+                        .collect();
+                    return Ok(Some(events));
+                }
+                Err(err) if self.range > 1 && is_range_error(&err) => {
+                    self.range /= 2;
+                    debug!(
+                        "Узел отклонил диапазон: {err}. Новый диапазон {}",
+                        self.range
+                    );
+                }
+                Err(err) if retries < RATE_LIMIT_RETRIES && is_rate_limited(&err) => {
+                    retries += 1;
+                    warn!("Превышен лимит запросов: {err}. Повтор через {delay:?}");
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                Err(err) => {
+                    return Err(err).with_context(|| {
+                        format!("Не удалось получить логи с блока {}", self.next_block)
+                    });
+                }
+            }
+        }
+    }
+
+    /// Все события истории
+    pub async fn collect<P: Provider>(mut self, provider: &P) -> Result<Vec<EventLog>> {
+        let mut events = Vec::new();
+        while let Some(chunk) = self.next_chunk(provider).await? {
+            events.extend(chunk);
+        }
+        Ok(events)
+    }
+}
+
+/// Сообщения узлов и провайдеров об отказе из-за слишком большого диапазона блоков или
+/// количества логов
+const RANGE_ERRORS: [&str; 9] = [
+    // geth, erigon, Infura
+    "query returned more than",
+    // geth
+    "exceed maximum block range",
+    // erigon, Cloudflare
+    "block range too large",
+    "block range is too large",
+    // BSC
+    "block range is too wide",
+    // reth, anvil
+    "query exceeds max block range",
+    "query exceeds max results",
+    // Alchemy
+    "log response size exceeded",
+    // QuickNode
+    "eth_getlogs is limited to",
+];
+
+/// Ошибка узла из-за слишком большого диапазона блоков или количества логов
+fn is_range_error(err: &TransportError) -> bool {
+    let Some(payload) = err.as_error_resp() else {
+        return false;
+    };
+    let message = payload.message.to_lowercase();
+    RANGE_ERRORS.iter().any(|v| message.contains(v))
+}
+
+/// Превышение лимита запросов: HTTP 429 или ответ узла с кодом ограничения частоты
+fn is_rate_limited(err: &TransportError) -> bool {
+    match err {
+        RpcError::Transport(kind) => kind.is_retry_err(),
+        RpcError::ErrorResp(payload) => payload.is_retry_err(),
+        _ => false,
+    }
+}
+
+/// Поток событий начиная с блока `from_block`: сначала история, затем новые события.
+///
+/// История читается по диапазонам по мере чтения потока и не накапливается в памяти. Фильтр новых
+/// событий создаётся после истории, чтобы узел не удалил его за время долгого чтения: история
+/// дочитывается до вершины на момент создания фильтра, а из новых событий отбрасываются уже
+/// прочитанные блоки. Поэтому нет ни пропусков, ни повторов. Ошибка чтения истории выдаётся
+/// в поток, после неё поток заканчивается
+pub async fn subscribe_from<P: Provider + Clone + 'static>(
+    provider: &P,
+    filter: &EventFilter,
+    from_block: u64,
+) -> Result<impl Stream<Item = Result<EventLog>> + Send + 'static> {
+    let head = provider
+        .get_block_number()
+        .await
+        .context("Не удалось получить номер блока")?;
+    let phase = Phase::History {
+        provider: provider.clone(),
+        filter: filter.clone(),
+        backfill: Backfill::new(filter.clone(), from_block, head),
+    };
+
+    let chunks = stream::unfold(phase, |phase| async move {
+        match phase {
+            Phase::History {
+                provider,
+                filter,
+                mut backfill,
+            } => match backfill.next_chunk(&provider).await {
+                Ok(Some(chunk)) => Some((
+                    Ok(chunk),
+                    Phase::History {
+                        provider,
+                        filter,
+                        backfill,
+                    },
+                )),
+                Ok(None) => match handover(&provider, &filter, backfill.next_block()).await {
+                    Ok((chunk, live)) => Some((Ok(chunk), Phase::Live(live))),
+                    Err(err) => Some((Err(err), Phase::Done)),
+                },
+                Err(err) => Some((Err(err), Phase::Done)),
+            },
+            Phase::Live(mut live) => live
+                .next()
+                .await
+                .map(|event| (Ok(vec![event]), Phase::Live(live))),
+            Phase::Done => None,
+        }
+    });
+    Ok(chunks.flat_map(|chunk| {
+        stream::iter(match chunk {
+            Ok(events) => events.into_iter().map(Ok).collect(),
+            Err(err) => vec![Err(err)],
+        })
+    }))
+}
+
+/// Состояние потока [`subscribe_from`]
+enum Phase<P> {
+    History {
+        provider: P,
+        filter: EventFilter,
+        backfill: Backfill,
+    },
+    Live(BoxStream<'static, EventLog>),
+    Done,
+}
+
+/// Подписка на новые события и дочитывание истории с `from_block` до вершины на момент подписки
+async fn handover<P: Provider>(
+    provider: &P,
+    filter: &EventFilter,
+    from_block: u64,
+) -> Result<(Vec<EventLog>, BoxStream<'static, EventLog>)> {
+    let live = subscribe(provider, filter).await?;
+    let head = provider
+        .get_block_number()
+        .await
+        .context("Не удалось получить номер блока")?;
+    let history = Backfill::new(filter.clone(), from_block, head)
+        .collect(provider)
+        .await?;
+    info!("История прочитана до блока {head}");
+
+    let live = live
+        .filter(move |v| std::future::ready(v.meta.block_number > head))
+        .boxed();
+    Ok((history, live))
+}
@@ -0,0 +1,414 @@
+//! Исполнитель заявок на вывод l2 => l1
+//!
+//! Читает заявки на вывод из [`L2Source`] и отправляет от имени owner транзакцию
+//! `apply_withdrawal_request` или `apply_withdrawal_request_erc20`.
+//!
+//! Заявка проходит состояния [`IntentStatus::Pending`] → [`IntentStatus::Submitted`] →
+//! [`IntentStatus::Confirmed`] или [`IntentStatus::Failed`]. Транзакция подписывается локально,
+//! и подписанные байты сохраняются до отправки. После перезапуска отправляется та же самая
+//! транзакция, поэтому одна заявка не может быть одобрена дважды. Если nonce транзакции занят
+//! другой транзакцией owner (ключ общий с CLI), подписанная транзакция уже не попадёт в блок,
+//! и заявка возвращается в [`IntentStatus::Pending`] для нового подписания.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use alloy::{
+    consensus::{Transaction as _, TxEnvelope},
+    eips::eip2718::{Decodable2718, Encodable2718},
+    primitives::{Address, B256, Bytes},
+    providers::{Provider, WalletProvider as _},
+};
+use eyre::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    contracts::{Bridge::BridgeInstance, WalletProvider},
+    errors::BridgeError,
+    tasks::{MAX_RESTART_DELAY, RESTART_DELAY},
+};
+
+/// Заявка на вывод с l2
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WithdrawalIntent {
+    /// Уникальный идентификатор заявки на l2
+    pub id: String,
+    /// Получатель на l1
+    pub to: Address,
+    /// Токен ERC20. Без токена выводится ETH
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<Address>,
+    /// Сумма в единицах l2
+    pub amount: u64,
+}
+
+/// Источник заявок на вывод
+pub trait L2Source {
+    /// Новые заявки с момента прошлого вызова
+    fn fetch(&mut self) -> impl Future<Output = Result<Vec<WithdrawalIntent>>> + Send;
+}
+
+/// Заявки в памяти, для тестов
+#[derive(Debug, Default)]
+pub struct MemorySource {
+    intents: VecDeque<WithdrawalIntent>,
+}
+
+impl MemorySource {
+    pub fn push(&mut self, intent: WithdrawalIntent) {
+        self.intents.push_back(intent);
+    }
+}
+
+impl L2Source for MemorySource {
+    async fn fetch(&mut self) -> Result<Vec<WithdrawalIntent>> {
+        Ok(self.intents.drain(..).collect())
+    }
+}
+
+/// Заявки из файла, по одному JSON на строку. Файл может дописываться во время работы
+#[derive(Debug)]
+pub struct FileSource {
+    path: PathBuf,
+    read_lines: usize,
+}
+
+impl FileSource {
+    pub fn new(path: &Path) -> Self {
+        FileSource {
+            path: path.to_path_buf(),
+            read_lines: 0,
+        }
+    }
+}
+
+impl L2Source for FileSource {
+    async fn fetch(&mut self) -> Result<Vec<WithdrawalIntent>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let content = fs::read_to_string(&self.path).context("Не удалось прочитать файл заявок")?;
+
+        // Последняя строка может быть недописана
+        let complete = match content.rfind('\n') {
+            Some(end) => &content[..=end],
+            None => "",
+        };
+        let intents = complete
+            .lines()
+            .skip(self.read_lines)
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).context("Ошибка в файле заявок"))
+            .collect::<Result<Vec<_>>>()?;
+        self.read_lines = complete.lines().count();
+        Ok(intents)
+    }
+}
+
+/// Состояние заявки
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum IntentStatus {
+    /// Заявка принята, транзакция ещё не подписана
+    Pending,
+    /// Транзакция подписана и отправлена
+    Submitted { tx_hash: B256, raw: Bytes },
+    /// Транзакция включена в блок
+    Confirmed { tx_hash: B256, block_number: u64 },
+    /// Транзакция не может быть выполнена или откатилась
+    Failed { reason: String },
+}
+
+/// Заявка и её состояние
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntentRecord {
+    pub intent: WithdrawalIntent,
+    #[serde(flatten)]
+    pub status: IntentStatus,
+}
+
+/// Сохраняемое состояние исполнителя
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ExecutorState {
+    pub intents: BTreeMap<String, IntentRecord>,
+}
+
+impl ExecutorState {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(ExecutorState::default());
+        }
+        let content = fs::read_to_string(path).context("Не удалось прочитать состояние")?;
+        serde_json::from_str(&content).context("Ошибка в файле состояния")
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content =
+            serde_json::to_string_pretty(self).context("Неудалось преобразовать в JSON")?;
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, content).context("Ошибка при записи состояния")?;
+        fs::rename(&tmp_path, path).context("Ошибка при записи состояния")
+    }
+
+    /// Добавление новых заявок. Заявки с уже известным идентификатором пропускаются.
+    /// Возвращает количество добавленных
+    pub fn accept(&mut self, intents: Vec<WithdrawalIntent>) -> usize {
+        let mut accepted = 0;
+        for intent in intents {
+            if let Some(record) = self.intents.get(&intent.id) {
+                if record.intent != intent {
+                    warn!("Заявка {} повторно получена с другими данными", intent.id);
+                }
+                continue;
+            }
+            self.intents.insert(
+                intent.id.clone(),
+                IntentRecord {
+                    intent,
+                    status: IntentStatus::Pending,
+                },
+            );
+            accepted += 1;
+        }
+        accepted
+    }
+
+    /// Повторная попытка для заявки, завершившейся ошибкой
+    pub fn retry(&mut self, id: &str) -> bool {
+        match self.intents.get_mut(id) {
+            Some(record) if matches!(record.status, IntentStatus::Failed { .. }) => {
+                record.status = IntentStatus::Pending;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn with_status(&self, filter: fn(&IntentStatus) -> bool) -> Vec<String> {
+        self.intents
+            .iter()
+            .filter(|(_, record)| filter(&record.status))
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+}
+
+/// Исполнитель заявок на вывод
+pub struct WithdrawalExecutor<S> {
+    bridge: BridgeInstance<WalletProvider>,
+    source: S,
+    state_path: PathBuf,
+    state: ExecutorState,
+}
+
+impl<S: L2Source> WithdrawalExecutor<S> {
+    /// `bridge` должен быть подключён с ключом owner
+    pub fn new(
+        bridge: BridgeInstance<WalletProvider>,
+        source: S,
+        state_path: &Path,
+    ) -> Result<Self> {
+        Ok(WithdrawalExecutor {
+            bridge,
+            source,
+            state_path: state_path.to_path_buf(),
+            state: ExecutorState::load(state_path)?,
+        })
+    }
+
+    pub fn state(&self) -> &ExecutorState {
+        &self.state
+    }
+
+    pub fn source_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    fn set_status(&mut self, id: &str, status: IntentStatus) -> Result<()> {
+        if let Some(record) = self.state.intents.get_mut(id) {
+            info!("Заявка {id}: {status:?}");
+            record.status = status;
+        }
+        self.state.save(&self.state_path)
+    }
+
+    /// Подписание транзакции. Ошибка на этом шаге (например, откат при оценке газа)
+    /// означает, что транзакция не была отправлена
+    async fn sign(&self, intent: &WithdrawalIntent) -> Result<(B256, Bytes)> {
+        let request = match intent.token {
+            Some(token) => self
+                .bridge
+                .apply_withdrawal_request_erc20(token, intent.to, intent.amount)
+                .into_transaction_request(),
+            None => self
+                .bridge
+                .apply_withdrawal_request(intent.to, intent.amount)
+                .into_transaction_request(),
+        };
+        let envelope = self
+            .bridge
+            .provider()
+            .fill(request)
+            .await?
+            .try_into_envelope()?;
+        Ok((*envelope.tx_hash(), envelope.encoded_2718().into()))
+    }
+
+    /// Подписание и отправка новых заявок
+    async fn submit_pending(&mut self) -> Result<()> {
+        for id in self
+            .state
+            .with_status(|v| matches!(v, IntentStatus::Pending))
+        {
+            let intent = self.state.intents[&id].intent.clone();
+            let (tx_hash, raw) = match self.sign(&intent).await {
+                Ok(signed) => signed,
+                Err(err) => {
+                    let reason = match BridgeError::from_report(&err) {
+                        Some(reason) => reason.to_string(),
+                        None => format!("{err:#}"),
+                    };
+                    self.set_status(&id, IntentStatus::Failed { reason })?;
+                    continue;
+                }
+            };
+
+            // Подписанная транзакция сохраняется до отправки
+            self.set_status(
+                &id,
+                IntentStatus::Submitted {
+                    tx_hash,
+                    raw: raw.clone(),
+                },
+            )?;
+            if let Err(err) = self.bridge.provider().send_raw_transaction(&raw).await {
+                warn!("Ошибка при отправке заявки {id}: {err}. Повтор на следующем шаге");
+            }
+        }
+        Ok(())
+    }
+
+    /// Проверка отправленных транзакций
+    async fn check_submitted(&mut self) -> Result<()> {
+        for id in self
+            .state
+            .with_status(|v| matches!(v, IntentStatus::Submitted { .. }))
+        {
+            let IntentStatus::Submitted { tx_hash, raw } = self.state.intents[&id].status.clone()
+            else {
+                continue;
+            };
+
+            let provider = self.bridge.provider();
+            let receipt = provider
+                .get_transaction_receipt(tx_hash)
+                .await
+                .context("Не удалось получить квитанцию")?;
+            match receipt {
+                Some(receipt) if receipt.status() => {
+                    let block_number = receipt.block_number.unwrap_or_default();
+                    self.set_status(
+                        &id,
+                        IntentStatus::Confirmed {
+                            tx_hash,
+                            block_number,
+                        },
+                    )?;
+                }
+                Some(_) => {
+                    let reason = format!("Транзакция {tx_hash} откатилась");
+                    self.set_status(&id, IntentStatus::Failed { reason })?;
+                }
+                None => {
+                    let nonce = TxEnvelope::decode_2718(&mut raw.as_ref())
+                        .context("Ошибка в подписанной транзакции")?
+                        .nonce();
+                    let mined = provider
+                        .get_transaction_count(provider.default_signer_address())
+                        .latest()
+                        .await
+                        .context("Не удалось получить nonce")?;
+                    if mined > nonce {
+                        // Транзакция могла попасть в блок между запросами квитанции и nonce
+                        let receipt = provider
+                            .get_transaction_receipt(tx_hash)
+                            .await
+                            .context("Не удалось получить квитанцию")?;
+                        if receipt.is_none() {
+                            warn!(
+                                "Заявка {id}: nonce {nonce} занят другой транзакцией, \
+                                 транзакция будет подписана заново"
+                            );
+                            self.set_status(&id, IntentStatus::Pending)?;
+                        }
+                        continue;
+                    }
+
+                    // Повторная отправка той же подписанной транзакции безопасна
+                    if let Err(err) = provider.send_raw_transaction(&raw).await {
+                        warn!("Заявка {id} ожидает включения в блок: {err}");
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Один шаг: новые заявки, отправка и проверка транзакций
+    pub async fn step(&mut self) -> Result<()> {
+        self.accept_new().await?;
+        self.process().await
+    }
+
+    /// Приём новых заявок в очередь
+    async fn accept_new(&mut self) -> Result<()> {
+        let intents = self.source.fetch().await?;
+        if self.state.accept(intents) > 0 {
+            self.state.save(&self.state_path)?;
+        }
+        Ok(())
+    }
+
+    /// Отправка ожидающих заявок и проверка отправленных
+    async fn process(&mut self) -> Result<()> {
+        self.submit_pending().await?;
+        self.check_submitted().await
+    }
+
+    /// Обработка заявок с интервалом `interval` до завершения `shutdown`. Ошибки узла
+    /// не останавливают исполнитель: попытка повторяется с растущей задержкой.
+    /// Возвращает ошибку, только если не удалось принять заявки в очередь
+    pub async fn run(
+        &mut self,
+        interval: Duration,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<()> {
+        tokio::pin!(shutdown);
+        let mut backoff = RESTART_DELAY;
+        loop {
+            self.accept_new().await?;
+            let delay = match self.process().await {
+                Ok(()) => {
+                    backoff = RESTART_DELAY;
+                    interval
+                }
+                Err(err) => {
+                    warn!("Ошибка исполнителя: {err:#}. Повтор через {backoff:?}");
+                    let delay = backoff;
+                    backoff = (backoff * 2).min(MAX_RESTART_DELAY);
+                    delay
+                }
+            };
+            tokio::select! {
+                _ = &mut shutdown => return Ok(()),
+                _ = tokio::time::sleep(delay) => (),
+            }
+        }
+    }
+}
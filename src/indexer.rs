@@ -0,0 +1,653 @@
+//! Локальный индекс операций моста
+//!
+//! [`Indexer`] читает блоки вместе с квитанциями и сохраняет в SQLite депозиты ETH и ERC20,
+//! создание мостов, одобрения выводов и сами выводы. Одобрения и выводы не создают событий,
+//! поэтому они разбираются из calldata успешных транзакций к мосту. Сумма вывода ERC20 берётся
+//! из `Transfer` токена в той же транзакции. Сумма вывода ETH - это `available_to_withdraw`
+//! в предыдущем блоке вместе с одобрениями, попавшими в блок раньше вывода. Для старых блоков
+//! нужен архивный узел. Если сумму не удалось получить, блок не записывается и читается заново.
+//!
+//! Блок записывается вместе с номером следующего блока в одной транзакции базы, поэтому
+//! прерванная индексация продолжается без пропусков и повторов. Реорганизации не отслеживаются:
+//! индексировать стоит блоки, уже получившие `confirmations` подтверждений.
+
+use std::{collections::HashMap, fmt, path::Path, str::FromStr};
+
+use alloy::{
+    consensus::Transaction as _,
+    eips::BlockId,
+    primitives::{Address, B256, U256},
+    providers::Provider,
+    rpc::types::{Block, TransactionReceipt},
+};
+use alloy_sol_types::SolInterface;
+use eyre::{Context, ContextCompat, Result, bail};
+use rusqlite::{Connection, OptionalExtension, params, params_from_iter, types::Value};
+use tracing::{debug, info, warn};
+
+use crate::{
+    amount::{ETH_DECIMALS, L2_DECIMALS, convert_amount},
+    contracts::{
+        Bridge::{self, BridgeCalls},
+        DemoERC20,
+    },
+    events::BridgeEvent,
+};
+
+/// Размер страницы по умолчанию
+pub const PAGE_SIZE: usize = 100;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS records (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        block_number INTEGER NOT NULL,
+        block_hash TEXT NOT NULL,
+        tx_hash TEXT NOT NULL,
+        tx_index INTEGER NOT NULL,
+        log_index INTEGER,
+        kind TEXT NOT NULL,
+        sender TEXT NOT NULL,
+        receiver TEXT,
+        token TEXT,
+        amount TEXT
+    );
+    CREATE INDEX IF NOT EXISTS records_block ON records (block_number);
+    CREATE INDEX IF NOT EXISTS records_sender ON records (sender);
+    CREATE INDEX IF NOT EXISTS records_receiver ON records (receiver);
+    CREATE INDEX IF NOT EXISTS records_token ON records (token);
+    CREATE TABLE IF NOT EXISTS state (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+";
+
+/// Тип операции
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecordKind {
+    /// `EventCreateBridge`
+    CreateBridge,
+    /// `EventDeposit`
+    Deposit,
+    /// `EventDepositRC20`
+    DepositErc20,
+    /// `apply_withdrawal_request` или `apply_withdrawal_request_erc20`
+    WithdrawalApproval,
+    /// `withdraw` или `withdraw_erc20`
+    Withdrawal,
+}
+
+impl RecordKind {
+    pub const ALL: [RecordKind; 5] = [
+        RecordKind::CreateBridge,
+        RecordKind::Deposit,
+        RecordKind::DepositErc20,
+        RecordKind::WithdrawalApproval,
+        RecordKind::Withdrawal,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RecordKind::CreateBridge => "create_bridge",
+            RecordKind::Deposit => "deposit",
+            RecordKind::DepositErc20 => "deposit_erc20",
+            RecordKind::WithdrawalApproval => "withdrawal_approval",
+            RecordKind::Withdrawal => "withdrawal",
+        }
+    }
+}
+
+impl fmt::Display for RecordKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for RecordKind {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.to_lowercase().replace('-', "_");
+        RecordKind::ALL
+            .into_iter()
+            .find(|v| v.as_str() == s)
+            .with_context(|| format!("Неизвестный тип операции {s}"))
+    }
+}
+
+/// Положение операции в сети
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RecordMeta {
+    pub block_number: u64,
+    pub block_hash: B256,
+    pub tx_hash: B256,
+    pub tx_index: u64,
+    /// Номер лога. `None` для операций, разобранных из calldata
+    pub log_index: Option<u64>,
+}
+
+/// Операция моста
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub meta: RecordMeta,
+    pub kind: RecordKind,
+    /// `from` события или отправитель транзакции
+    pub sender: Address,
+    /// Получатель депозита или одобренного вывода. Для вывода - сам отправитель
+    pub receiver: Option<Address>,
+    /// Токен ERC20. `None` для ETH
+    pub token: Option<Address>,
+    /// Сумма депозита и одобрения в точности l2, сумма вывода в точности l1.
+    /// `None` для создания моста и вывода ETH, сумму которого не удалось получить
+    pub amount: Option<U256>,
+}
+
+/// Запрос операций. Пустые условия не ограничивают выборку
+#[derive(Debug, Clone)]
+pub struct RecordQuery {
+    kinds: Vec<RecordKind>,
+    sender: Option<Address>,
+    receiver: Option<Address>,
+    /// `Some(None)` - только ETH
+    token: Option<Option<Address>>,
+    from_block: Option<u64>,
+    to_block: Option<u64>,
+    after: Option<u64>,
+    limit: usize,
+}
+
+impl Default for RecordQuery {
+    fn default() -> Self {
+        RecordQuery {
+            kinds: Vec::new(),
+            sender: None,
+            receiver: None,
+            token: None,
+            from_block: None,
+            to_block: None,
+            after: None,
+            limit: PAGE_SIZE,
+        }
+    }
+}
+
+impl RecordQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn kind(mut self, kind: RecordKind) -> Self {
+        self.kinds.push(kind);
+        self
+    }
+
+    pub fn kinds(mut self, kinds: impl IntoIterator<Item = RecordKind>) -> Self {
+        self.kinds.extend(kinds);
+        self
+    }
+
+    pub fn sender(mut self, sender: Address) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+
+    pub fn receiver(mut self, receiver: Address) -> Self {
+        self.receiver = Some(receiver);
+        self
+    }
+
+    pub fn token(mut self, token: Address) -> Self {
+        self.token = Some(Some(token));
+        self
+    }
+
+    /// Только операции с ETH
+    pub fn eth(mut self) -> Self {
+        self.token = Some(None);
+        self
+    }
+
+    /// Блоки `from_block..=to_block`
+    pub fn blocks(mut self, from_block: Option<u64>, to_block: Option<u64>) -> Self {
+        self.from_block = from_block;
+        self.to_block = to_block;
+        self
+    }
+
+    /// Продолжение с курсора [`Page::next`]
+    pub fn after(mut self, cursor: u64) -> Self {
+        self.after = Some(cursor);
+        self
+    }
+
+    /// Размер страницы. По умолчанию [`PAGE_SIZE`]
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit.max(1);
+        self
+    }
+}
+
+/// Страница результатов в порядке операций в сети
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    pub records: Vec<Record>,
+    /// Курсор следующей страницы для [`RecordQuery::after`]. `None` на последней странице
+    pub next: Option<u64>,
+}
+
+/// Индекс операций одного моста
+#[derive(Debug)]
+pub struct Indexer {
+    conn: Connection,
+    bridge: Address,
+}
+
+impl Indexer {
+    /// База в файле `path`. База привязана к адресу моста, с другим адресом не открывается
+    pub fn open(path: &Path, bridge: Address) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Не удалось открыть базу {}", path.display()))?;
+        Indexer::with_connection(conn, bridge)
+    }
+
+    /// База в памяти
+    pub fn in_memory(bridge: Address) -> Result<Self> {
+        Indexer::with_connection(Connection::open_in_memory()?, bridge)
+    }
+
+    fn with_connection(conn: Connection, bridge: Address) -> Result<Self> {
+        conn.execute_batch(SCHEMA)
+            .context("Не удалось создать таблицы базы")?;
+        let indexer = Indexer { conn, bridge };
+        match indexer.state("bridge")? {
+            Some(stored) if stored != bridge.to_string() => {
+                bail!("База относится к мосту {stored}, а не {bridge}")
+            }
+            Some(_) => (),
+            None => {
+                indexer.conn.execute(
+                    "INSERT INTO state (key, value) VALUES ('bridge', ?1)",
+                    [bridge.to_string()],
+                )?;
+            }
+        }
+        Ok(indexer)
+    }
+
+    pub fn bridge(&self) -> Address {
+        self.bridge
+    }
+
+    /// Первый ещё не проиндексированный блок
+    pub fn next_block(&self) -> Result<Option<u64>> {
+        self.state("next_block")?
+            .map(|v| v.parse().context("Ошибка в состоянии базы"))
+            .transpose()
+    }
+
+    fn state(&self, key: &str) -> Result<Option<String>> {
+        self.conn
+            .query_row("SELECT value FROM state WHERE key = ?1", [key], |row| {
+                row.get(0)
+            })
+            .optional()
+            .context("Ошибка чтения состояния базы")
+    }
+
+    /// Индексация блоков до `to_block` включительно. При первом запуске индексация
+    /// начинается с `start_block`, затем продолжается с [`Indexer::next_block`].
+    /// Возвращает количество записанных операций
+    pub async fn sync<P: Provider>(
+        &mut self,
+        provider: &P,
+        start_block: u64,
+        to_block: u64,
+    ) -> Result<usize> {
+        let from_block = self.next_block()?.unwrap_or(start_block);
+        let mut count = 0;
+        for block_number in from_block..=to_block {
+            let records = fetch_block(provider, self.bridge, block_number).await?;
+            count += records.len();
+            self.insert_block(block_number, &records)?;
+        }
+        if from_block <= to_block {
+            info!("Проиндексированы блоки {from_block}..={to_block}: {count} операций");
+        }
+        Ok(count)
+    }
+
+    /// Запись операций блока и переход к следующему блоку
+    pub fn insert_block(&mut self, block_number: u64, records: &[Record]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT INTO records (block_number, block_hash, tx_hash, tx_index, log_index,
+                    kind, sender, receiver, token, amount)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            )?;
+            for record in records {
+                let meta = &record.meta;
+                insert.execute(params![
+                    meta.block_number as i64,
+                    meta.block_hash.to_string(),
+                    meta.tx_hash.to_string(),
+                    meta.tx_index as i64,
+                    meta.log_index.map(|v| v as i64),
+                    record.kind.as_str(),
+                    record.sender.to_string(),
+                    record.receiver.map(|v| v.to_string()),
+                    record.token.map(|v| v.to_string()),
+                    record.amount.map(|v| v.to_string()),
+                ])?;
+            }
+        }
+        tx.execute(
+            "INSERT INTO state (key, value) VALUES ('next_block', ?1)
+            ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            [(block_number + 1).to_string()],
+        )?;
+        tx.commit()
+            .with_context(|| format!("Не удалось записать блок {block_number}"))
+    }
+
+    /// Операции, подходящие под запрос
+    pub fn query(&self, query: &RecordQuery) -> Result<Page> {
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        let mut condition = |sql: &str, value: Option<Value>| match value {
+            Some(value) => {
+                values.push(value);
+                conditions.push(format!("{sql} ?{}", values.len()));
+            }
+            None => conditions.push(sql.to_string()),
+        };
+
+        if let Some(after) = query.after {
+            condition("id >", Some(Value::Integer(after as i64)));
+        }
+        if let Some(sender) = query.sender {
+            condition("sender =", Some(Value::Text(sender.to_string())));
+        }
+        if let Some(receiver) = query.receiver {
+            condition("receiver =", Some(Value::Text(receiver.to_string())));
+        }
+        match query.token {
+            Some(Some(token)) => condition("token =", Some(Value::Text(token.to_string()))),
+            Some(None) => condition("token IS NULL", None),
+            None => (),
+        }
+        if let Some(from_block) = query.from_block {
+            condition("block_number >=", Some(Value::Integer(from_block as i64)));
+        }
+        if let Some(to_block) = query.to_block {
+            condition("block_number <=", Some(Value::Integer(to_block as i64)));
+        }
+        if !query.kinds.is_empty() {
+            let kinds = query
+                .kinds
+                .iter()
+                .map(|v| format!("'{}'", v.as_str()))
+                .collect::<Vec<_>>();
+            condition(&format!("kind IN ({})", kinds.join(", ")), None);
+        }
+
+        let mut sql = "SELECT id, block_number, block_hash, tx_hash, tx_index, log_index,
+            kind, sender, receiver, token, amount FROM records"
+            .to_string();
+        if !conditions.is_empty() {
+            sql += &format!(" WHERE {}", conditions.join(" AND "));
+        }
+        // Лишняя запись показывает, что есть следующая страница
+        sql += &format!(" ORDER BY id LIMIT {}", query.limit + 1);
+
+        let mut statement = self.conn.prepare(&sql)?;
+        let mut rows = statement.query(params_from_iter(values))?;
+        let mut records = Vec::new();
+        let mut last_id = None;
+        while let Some(row) = rows.next()? {
+            if records.len() == query.limit {
+                return Ok(Page {
+                    records,
+                    next: last_id,
+                });
+            }
+            last_id = Some(row.get::<_, i64>(0)? as u64);
+            records.push(read_record(row).context("Ошибка в записи базы")?);
+        }
+        Ok(Page {
+            records,
+            next: None,
+        })
+    }
+}
+
+fn read_record(row: &rusqlite::Row) -> Result<Record> {
+    let parse = |v: Option<String>| v.map(|v| Address::from_str(&v)).transpose();
+    Ok(Record {
+        meta: RecordMeta {
+            block_number: row.get::<_, i64>(1)? as u64,
+            block_hash: row.get::<_, String>(2)?.parse()?,
+            tx_hash: row.get::<_, String>(3)?.parse()?,
+            tx_index: row.get::<_, i64>(4)? as u64,
+            log_index: row.get::<_, Option<i64>>(5)?.map(|v| v as u64),
+        },
+        kind: row.get::<_, String>(6)?.parse()?,
+        sender: row.get::<_, String>(7)?.parse()?,
+        receiver: parse(row.get(8)?)?,
+        token: parse(row.get(9)?)?,
+        amount: row
+            .get::<_, Option<String>>(10)?
+            .map(|v| U256::from_str(&v))
+            .transpose()?,
+    })
+}
+
+/// Операции моста в блоке `block_number`
+pub async fn fetch_block<P: Provider>(
+    provider: &P,
+    bridge: Address,
+    block_number: u64,
+) -> Result<Vec<Record>> {
+    let block = provider
+        .get_block_by_number(block_number.into())
+        .full()
+        .await
+        .with_context(|| format!("Не удалось получить блок {block_number}"))?
+        .with_context(|| format!("Блок {block_number} не найден"))?;
+    let receipts = provider
+        .get_block_receipts(BlockId::number(block_number))
+        .await
+        .with_context(|| format!("Не удалось получить квитанции блока {block_number}"))?
+        .with_context(|| format!("Квитанции блока {block_number} не найдены"))?;
+
+    let mut records = block_records(bridge, &block, &receipts)?;
+    let mut available = HashMap::new();
+    for record in &records {
+        if record.kind == RecordKind::Withdrawal
+            && record.token.is_none()
+            && !available.contains_key(&record.sender)
+        {
+            let amount = available_before(provider, bridge, record.sender, block_number).await?;
+            available.insert(record.sender, amount);
+        }
+    }
+    fill_eth_withdrawals(&mut records, available)?;
+    debug!("Блок {block_number}: {} операций", records.len());
+    Ok(records)
+}
+
+/// Операции моста из транзакций блока и их квитанций. Сумма вывода ETH не заполняется
+pub fn block_records(
+    bridge: Address,
+    block: &Block,
+    receipts: &[TransactionReceipt],
+) -> Result<Vec<Record>> {
+    let mut records = Vec::new();
+    for receipt in receipts {
+        if !receipt.status() {
+            continue;
+        }
+        let meta = RecordMeta {
+            block_number: block.header.number,
+            block_hash: block.header.hash,
+            tx_hash: receipt.transaction_hash,
+            tx_index: receipt.transaction_index.unwrap_or_default(),
+            log_index: None,
+        };
+        let call = match receipt.to {
+            Some(to) if to == bridge => {
+                let tx = block
+                    .transactions
+                    .txns()
+                    .find(|v| *v.inner.tx_hash() == receipt.transaction_hash)
+                    .with_context(|| {
+                        format!("В блоке нет транзакции {}", receipt.transaction_hash)
+                    })?;
+                BridgeCalls::abi_decode(tx.input()).ok()
+            }
+            _ => None,
+        };
+
+        for log in receipt.inner.logs() {
+            if log.address() != bridge {
+                continue;
+            }
+            let event = match BridgeEvent::decode(log) {
+                Ok(Some(event)) => event,
+                Ok(None) => continue,
+                Err(err) => {
+                    warn!(
+                        "Пропущен лог {:?} транзакции {}: {err:#}",
+                        log.log_index, receipt.transaction_hash
+                    );
+                    continue;
+                }
+            };
+            let meta = RecordMeta {
+                log_index: log.log_index,
+                ..meta
+            };
+            records.push(match event {
+                BridgeEvent::CreateBridge(_) => Record {
+                    meta,
+                    kind: RecordKind::CreateBridge,
+                    sender: receipt.from,
+                    receiver: None,
+                    token: match &call {
+                        Some(BridgeCalls::create_bridge_erc20(call)) => Some(call.tokenContract),
+                        _ => None,
+                    },
+                    amount: None,
+                },
+                BridgeEvent::Deposit(event) => Record {
+                    meta,
+                    kind: RecordKind::Deposit,
+                    sender: event.from,
+                    receiver: Some(event.to),
+                    token: None,
+                    amount: Some(U256::from(event.value)),
+                },
+                BridgeEvent::DepositErc20(event) => Record {
+                    meta,
+                    kind: RecordKind::DepositErc20,
+                    sender: event.from,
+                    receiver: Some(event.to),
+                    token: Some(event.token_address),
+                    amount: Some(event.value),
+                },
+                _ => continue,
+            });
+        }
+
+        let record = |kind, receiver, token, amount| Record {
+            meta,
+            kind,
+            sender: receipt.from,
+            receiver: Some(receiver),
+            token,
+            amount,
+        };
+        match call {
+            Some(BridgeCalls::apply_withdrawal_request(call)) => records.push(record(
+                RecordKind::WithdrawalApproval,
+                call.to,
+                None,
+                Some(U256::from(call.amount)),
+            )),
+            Some(BridgeCalls::apply_withdrawal_request_erc20(call)) => records.push(record(
+                RecordKind::WithdrawalApproval,
+                call.to,
+                Some(call.tokenContract),
+                Some(U256::from(call.amount)),
+            )),
+            Some(BridgeCalls::withdraw(_)) => {
+                records.push(record(RecordKind::Withdrawal, receipt.from, None, None))
+            }
+            Some(BridgeCalls::withdraw_erc20(call)) => {
+                let token = call.tokenContract;
+                let amount = receipt
+                    .inner
+                    .logs()
+                    .iter()
+                    .filter(|v| v.address() == token)
+                    .filter_map(|v| v.log_decode::<DemoERC20::Transfer>().ok())
+                    .map(|v| v.inner.data)
+                    .find(|v| v.from == bridge && v.to == receipt.from)
+                    .map(|v| v.value);
+                records.push(record(
+                    RecordKind::Withdrawal,
+                    receipt.from,
+                    Some(token),
+                    amount,
+                ));
+            }
+            _ => (),
+        }
+    }
+    Ok(records)
+}
+
+/// Сумма, доступная `user` для вывода ETH перед блоком `block_number`
+async fn available_before<P: Provider>(
+    provider: &P,
+    bridge: Address,
+    user: Address,
+    block_number: u64,
+) -> Result<U256> {
+    let Some(block_number) = block_number.checked_sub(1) else {
+        return Ok(U256::ZERO);
+    };
+    Bridge::new(bridge, provider)
+        .available_to_withdraw()
+        .from(user)
+        .block(block_number.into())
+        .call()
+        .await
+        .with_context(|| format!("Не удалось получить сумму вывода {user} в блоке {block_number}"))
+}
+
+/// Суммы выводов ETH по порядку транзакций блока. `available` - суммы, доступные
+/// отправителям выводов перед блоком. Одобрение увеличивает доступную сумму, вывод обнуляет её
+pub fn fill_eth_withdrawals(
+    records: &mut [Record],
+    mut available: HashMap<Address, U256>,
+) -> Result<()> {
+    for record in records.iter_mut().filter(|v| v.token.is_none()) {
+        match record.kind {
+            RecordKind::WithdrawalApproval => {
+                let user = record.receiver.context("В одобрении нет получателя")?;
+                let amount = record.amount.context("В одобрении нет суммы")?;
+                if let Some(balance) = available.get_mut(&user) {
+                    *balance += convert_amount(amount, L2_DECIMALS, ETH_DECIMALS)?;
+                }
+            }
+            RecordKind::Withdrawal => {
+                let balance = available
+                    .get_mut(&record.sender)
+                    .with_context(|| format!("Нет доступной суммы {}", record.sender))?;
+                record.amount = Some(std::mem::take(balance));
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
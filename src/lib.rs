@@ -0,0 +1,75 @@
+use crate::{
+    contracts::{
+        Bridge::BridgeInstance, DemoERC20::DemoERC20Instance, ExmERC20::ExmERC20Instance,
+        TestERC20::TestERC20Instance, WalletProvider,
+    },
+    deployer::{Deployer, Funder},
+};
+use accounts::Signer;
+use eyre::Result;
+
+pub mod accounts;
+pub mod amount;
+pub mod audit;
+pub mod bus;
+pub mod client;
+pub mod config;
+pub mod console;
+pub mod contracts;
+pub mod deployer;
+pub mod errors;
+pub mod events;
+pub mod executor;
+pub mod indexer;
+pub mod nonce;
+pub mod pubsub;
+pub mod registry;
+pub mod relayer;
+pub mod storage;
+pub mod supervisor;
+pub mod tasks;
+pub mod watcher;
+pub mod withdrawals;
+
+#[cfg(test)]
+mod node;
+#[cfg(test)]
+mod tests;
+
+/// Развёртывание контрактов и пополнение токенами первых трёх аккаунтов
+pub async fn init() -> Result<Vec<Signer>> {
+    let accounts = accounts::read_accounts().await?;
+
+    let owner = &accounts[0];
+    let alice = &accounts[1];
+    let bob = &accounts[2];
+
+    let owner = Deployer::connect(owner).await?;
+    let alice = Deployer::connect(alice).await?;
+    let bob = Deployer::connect(bob).await?;
+
+    owner.contract::<BridgeInstance<WalletProvider>>().await?;
+    let demo_erc = owner
+        .contract::<DemoERC20Instance<WalletProvider>>()
+        .await?;
+    let test_erc = alice
+        .contract::<TestERC20Instance<WalletProvider>>()
+        .await?;
+    let exm_erc = bob.contract::<ExmERC20Instance<WalletProvider>>().await?;
+
+    let addrs = accounts[..3]
+        .iter()
+        .map(|v| v.address())
+        .collect::<Vec<_>>();
+    Funder::new(owner)
+        .fund_token(demo_erc.address(), &addrs)
+        .await?;
+    Funder::new(alice)
+        .fund_token(test_erc.address(), &addrs)
+        .await?;
+    Funder::new(bob)
+        .fund_token(exm_erc.address(), &addrs)
+        .await?;
+
+    Ok(accounts)
+}
@@ -1,997 +1,907 @@
-use crate::contracts::{Bridge, DemoERC20, ExmERC20, TestERC20};
-use accounts::Signer;
-use alloy::primitives::U256;
-use eyre::Result;
+use std::{path::PathBuf, str::FromStr, time::Duration};
+
+use alloy::{
+    primitives::{Address, B256},
+    providers::Provider,
+};
+use clap::{Parser, Subcommand};
+use eyre::{Context, ContextCompat, Result, bail};
+use futures_util::StreamExt;
+use tracing::debug;
+use ws_demo_eth::{
+    accounts::{self, KeyStore, Signer},
+    amount::TokenUnits,
+    audit::{self, Discrepancy},
+    bus::{EventBus, Overflow},
+    client::{BridgeClient, TxOutcome},
+    config::{self, KeySource},
+    console,
+    contracts::{
+        Bridge::BridgeInstance, DemoERC20::DemoERC20Instance, ExmERC20::ExmERC20Instance,
+        TestERC20::TestERC20Instance, WalletProvider,
+    },
+    deployer::{Deployer, token_balances},
+    events::{self, EventFilter, EventKind, EventLog},
+    executor::{FileSource, WithdrawalExecutor},
+    indexer::{Indexer, RecordKind, RecordQuery},
+    init,
+    relayer::{FileSink, Relayer},
+    storage::BridgeStorage,
+    tasks::TaskSupervisor,
+    watcher::WatchUpdate,
+    withdrawals::{self, Mismatch, PendingWithdrawal},
+};
+use zeroize::{Zeroize, Zeroizing};
+
+/// Управление мостом l1 <=> l2
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Индекс аккаунта из keystore, от имени которого отправляются транзакции
+    #[arg(short, long, global = true, default_value_t = 0)]
+    account: usize,
+
+    /// Профиль сети из config.toml. По умолчанию BRIDGE_PROFILE или профиль из файла
+    #[arg(short, long, global = true)]
+    profile: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
 
-pub(crate) mod accounts;
-pub(crate) mod console;
-pub(crate) mod contracts;
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Развёртывание контрактов и пополнение токенами тестовых аккаунтов
+    Deploy,
+    /// Создание моста для ERC20 токена (комиссия 1 ETH)
+    CreateBridge {
+        /// demo | test | exm или адрес токена
+        token: Token,
+    },
+    /// Перевод ETH с l1 => l2
+    Deposit {
+        /// Сумма в ETH: `0.5` или `0.5 ETH`
+        amount: String,
+        /// Получатель на l2. По умолчанию отправитель
+        #[arg(long)]
+        to: Option<Address>,
+    },
+    /// Перевод ERC20 с l1 => l2
+    DepositErc20 {
+        /// demo | test | exm или адрес токена
+        token: Token,
+        /// Сумма в токенах: `1.5` или `1.5 DERC`
+        amount: String,
+        /// Получатель на l2. По умолчанию отправитель
+        #[arg(long)]
+        to: Option<Address>,
+    },
+    /// Заявка на вывод l2 => l1 (только owner)
+    ApplyWithdrawal {
+        /// Получатель на l1
+        to: Address,
+        /// Сумма в ETH или токенах: `1.5`. Должна без остатка переводиться в точность l2
+        amount: String,
+        /// Токен ERC20. Без указания выводится ETH
+        #[arg(long)]
+        token: Option<Token>,
+    },
+    /// Вывод одобренных ETH
+    Withdraw,
+    /// Вывод одобренных ERC20 токенов
+    WithdrawErc20 {
+        /// demo | test | exm или адрес токена
+        token: Token,
+    },
+    /// Балансы ETH и токенов
+    Balances {
+        /// Адрес. По умолчанию адрес аккаунта
+        #[arg(long)]
+        address: Option<Address>,
+    },
+    /// Состояние моста и подключённых токенов
+    Status {
+        /// demo | test | exm или адрес токена. По умолчанию все тестовые токены
+        token: Option<Token>,
+    },
+    /// Состояние моста из хранилища контракта, без вызова view-функций
+    Storage {
+        /// Блок. По умолчанию последний
+        #[arg(long)]
+        block: Option<u64>,
+        /// demo | test | exm или адрес токена. По умолчанию все тестовые токены
+        #[arg(long)]
+        token: Vec<Token>,
+        /// Пользователь, для которого выводятся невыведенные суммы
+        #[arg(long)]
+        user: Option<Address>,
+    },
+    /// Вывод событий в консоль до Ctrl+C
+    Watch {
+        /// Адрес контракта. Можно указать несколько раз
+        #[arg(long)]
+        address: Vec<Address>,
+        /// Тип события: create-bridge, deposit, deposit-erc20, transfer, approval, vote, ...
+        #[arg(long)]
+        event: Vec<EventKind>,
+        /// Сначала вывести историю событий с этого блока
+        #[arg(long)]
+        from_block: Option<u64>,
+        /// Файл прогресса. События выводятся после подтверждений, отозванные помечаются `-`
+        #[arg(long)]
+        checkpoint: Option<PathBuf>,
+    },
+    /// Проверка истории депозитов ETH: зачисленная сумма должна совпадать с `msg.value`
+    Audit {
+        /// Первый блок
+        #[arg(long, default_value_t = 0)]
+        from_block: u64,
+        /// Последний блок. По умолчанию текущий
+        #[arg(long)]
+        to_block: Option<u64>,
+    },
+    /// Индексация операций моста в локальную базу до блока с нужным числом подтверждений
+    Index {
+        /// Файл базы SQLite
+        #[arg(long, default_value = "bridge.db")]
+        db: PathBuf,
+        /// Блок, с которого начинается индексация при первом запуске
+        #[arg(long, default_value_t = 0)]
+        from_block: u64,
+    },
+    /// Операции моста из локальной базы
+    History {
+        /// Файл базы SQLite
+        #[arg(long, default_value = "bridge.db")]
+        db: PathBuf,
+        /// Тип операции: create-bridge, deposit, deposit-erc20, withdrawal-approval, withdrawal
+        #[arg(long)]
+        kind: Vec<RecordKind>,
+        #[arg(long)]
+        sender: Option<Address>,
+        #[arg(long)]
+        receiver: Option<Address>,
+        /// Адрес токена ERC20
+        #[arg(long, conflicts_with = "eth")]
+        token: Option<Address>,
+        /// Только операции с ETH
+        #[arg(long)]
+        eth: bool,
+        #[arg(long)]
+        from_block: Option<u64>,
+        #[arg(long)]
+        to_block: Option<u64>,
+        /// Курсор следующей страницы из предыдущего вывода
+        #[arg(long)]
+        after: Option<u64>,
+        /// Размер страницы
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Невыведенные суммы всех пользователей по истории моста со сверкой с хранилищем
+    PendingWithdrawals {
+        /// Файл базы SQLite, перед расчётом дополняется до текущего блока
+        #[arg(long, default_value = "bridge.db")]
+        db: PathBuf,
+        /// Блок, с которого начинается индексация при первом запуске
+        #[arg(long, default_value_t = 0)]
+        from_block: u64,
+    },
+    /// Ретранслятор депозитов l1 => l2 до Ctrl+C
+    Relayer {
+        /// Файл зачислений на l2
+        #[arg(long, default_value = "l2_credits.jsonl")]
+        credits: PathBuf,
+        /// Файл прогресса ретранслятора
+        #[arg(long, default_value = "relayer.json")]
+        state: PathBuf,
+        /// Блок, с которого начинается обработка при первом запуске
+        #[arg(long, default_value_t = 0)]
+        from_block: u64,
+    },
+    /// Исполнитель заявок на вывод l2 => l1 до Ctrl+C (только owner)
+    Executor {
+        /// Файл заявок на вывод, по одному JSON на строку
+        #[arg(long, default_value = "l2_withdrawals.jsonl")]
+        intents: PathBuf,
+        /// Файл состояния заявок
+        #[arg(long, default_value = "executor.json")]
+        state: PathBuf,
+        /// Интервал опроса в секундах
+        #[arg(long, default_value_t = 2)]
+        interval: u64,
+    },
+    /// Управление зашифрованным keystore
+    Keys {
+        #[command(subcommand)]
+        command: KeysCommand,
+    },
+}
 
-pub const RPC_URL: &str = "http://localhost:8545";
+#[derive(Debug, Subcommand)]
+enum KeysCommand {
+    /// Адреса аккаунтов
+    List,
+    /// Создание нового ключа
+    New,
+    /// Шифрование существующего приватного ключа (вводится с клавиатуры)
+    Import,
+}
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    init().await.map(|_| ())
+/// Токен: один из развёрнутых тестовых или произвольный адрес
+#[derive(Debug, Clone, Copy)]
+enum Token {
+    Demo,
+    Test,
+    Exm,
+    Address(Address),
 }
 
-async fn init() -> Result<Vec<Signer>> {
-    let accounts = accounts::read_accounts().await?;
+impl FromStr for Token {
+    type Err = eyre::Report;
 
-    let owner = &accounts[0];
-    let alice = &accounts[1];
-    let bob = &accounts[2];
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_lowercase().as_str() {
+            "demo" => Token::Demo,
+            "test" => Token::Test,
+            "exm" => Token::Exm,
+            _ => Token::Address(s.parse().context("Ожидается demo, test, exm или адрес")?),
+        })
+    }
+}
 
-    let _bridge = contract_provider!(Bridge, owner);
-    let demo_erc = contract_provider!(DemoERC20, owner);
-    let test_erc = contract_provider!(TestERC20, alice);
-    let exm_erc = contract_provider!(ExmERC20, bob);
+impl Token {
+    async fn address(self, deployer: &Deployer) -> Result<Address> {
+        Ok(match self {
+            Token::Demo => deployer
+                .find::<DemoERC20Instance<WalletProvider>>()
+                .await?
+                .address(),
+            Token::Test => deployer
+                .find::<TestERC20Instance<WalletProvider>>()
+                .await?
+                .address(),
+            Token::Exm => deployer
+                .find::<ExmERC20Instance<WalletProvider>>()
+                .await?
+                .address(),
+            Token::Address(address) => address,
+        })
+    }
+}
 
-    let users = accounts[..3].to_vec();
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
 
-    let addrs = users.iter().map(|v| v.address()).collect::<Vec<_>>();
-    token_fund!(demo_erc, addrs);
-    token_fund!(test_erc, addrs);
-    token_fund!(exm_erc, addrs);
+    let cli = Cli::parse();
+    let profile = config::select(cli.profile.as_deref())?;
+    debug!("Профиль {}: {}", profile.name, profile.http_url);
 
-    Ok(accounts)
-}
+    match cli.command {
+        Command::Deploy => return deploy().await,
+        Command::Keys { command } => return keys(command).await,
+        _ => (),
+    }
 
-#[cfg(test)]
-mod tests {
-    use alloy::primitives::U256;
-    use tracing::debug;
-    use tracing_test::traced_test;
-
-    use crate::{console::watch_logs, contract_provider, contracts::Bridge, init};
-
-    /// ETH
-    mod test_eth {
-
-        mod deposit {
-            use alloy::{primitives::U256, providers::Provider};
-            use tokio::sync::mpsc;
-            use tracing::info;
-            use tracing_test::traced_test;
-
-            use crate::{
-                console::{watch_deposit_event, watch_logs},
-                contract_provider,
-                contracts::Bridge::{self},
-                init, provider,
-            };
-
-            #[tokio::test]
-            #[traced_test]
-            async fn deposit() {
-                let acc = init().await.unwrap();
-
-                let console_handle = watch_logs(acc[0].clone()).await.unwrap();
-
-                // Мониторинг событий пополнения депозита
-                let (sender, mut rec_deposit_events) = mpsc::channel(10);
-                let deposit_handle = watch_deposit_event(acc[0].clone(), sender).await.unwrap();
-                let min_amount = U256::from(10).pow(U256::from(10));
-
-                for user in acc.iter().skip(1).take(2) {
-                    let user_address = user.address();
-                    let provider = provider!(user);
-
-                    let bridge = contract_provider!(Bridge, user);
-                    let bridge_address = *bridge.address();
-
-                    let old_balance = provider.get_balance(user_address).await.unwrap();
-                    let old_bridge_balance = provider.get_balance(bridge_address).await.unwrap();
-                    info!("user balance: {old_balance}");
-                    info!("bridge balance: {old_bridge_balance}");
-
-                    info!("Перевод l1=>L2 {min_amount}");
-                    let tx = bridge
-                        .deposit(user_address)
-                        .value(min_amount)
-                        .send()
-                        .await
-                        .unwrap()
-                        .watch()
-                        .await
-                        .unwrap();
-                    info!("tx: {:?}", tx);
-
-                    let new_balance = provider.get_balance(user_address).await.unwrap();
-                    info!(
-                        "user {user_address}\n\
-                        old: {old_balance}\n\
-                        new: {new_balance}"
-                    );
-                    let new_bridge_balance = provider.get_balance(bridge_address).await.unwrap();
-                    info!(
-                        "bridge {bridge_address}\n\
-                        old: {old_bridge_balance}\n\
-                        new: {new_bridge_balance}"
-                    );
-
-                    assert!(old_balance - new_balance > min_amount);
-                    // переведённые деньги должны осесть на балансе моста
-                    assert_eq!(new_bridge_balance - old_bridge_balance, min_amount);
-                }
-
-                let tx1 = rec_deposit_events.recv().await.unwrap();
-                let address = acc[1].address();
-                assert_eq!(tx1.from, address);
-                assert_eq!(tx1.to, address);
-                assert_eq!(tx1.value, 1);
-
-                let tx2 = rec_deposit_events.recv().await.unwrap();
-                let address = acc[2].address();
-                assert_eq!(tx2.from, address);
-                assert_eq!(tx2.to, address);
-                assert_eq!(tx2.value, 1);
-
-                deposit_handle.abort();
-                console_handle.abort();
+    let accounts = accounts::read_accounts().await?;
+    let user = accounts
+        .get(cli.account)
+        .with_context(|| format!("Аккаунт {} не найден", cli.account))?
+        .clone();
+
+    match cli.command {
+        Command::Deploy | Command::Keys { .. } => unreachable!(),
+        Command::CreateBridge { token } => create_bridge(user, token).await,
+        Command::Deposit { amount, to } => deposit(user, amount, to).await,
+        Command::DepositErc20 { token, amount, to } => deposit_erc20(user, token, amount, to).await,
+        Command::ApplyWithdrawal { to, amount, token } => {
+            apply_withdrawal(user, to, amount, token).await
+        }
+        Command::Withdraw => withdraw(user).await,
+        Command::WithdrawErc20 { token } => withdraw_erc20(user, token).await,
+        Command::Balances { address } => balances(user, address).await,
+        Command::Status { token } => status(user, token).await,
+        Command::Storage {
+            block,
+            token,
+            user: address,
+        } => storage(user, block, token, address).await,
+        Command::Watch {
+            address,
+            event,
+            from_block,
+            checkpoint,
+        } => match checkpoint {
+            Some(checkpoint) => watch_confirmed(user, address, event, from_block, checkpoint).await,
+            None => watch(user, address, event, from_block).await,
+        },
+        Command::Audit {
+            from_block,
+            to_block,
+        } => audit(user, from_block, to_block).await,
+        Command::Index { db, from_block } => index(user, db, from_block).await,
+        Command::History {
+            db,
+            kind,
+            sender,
+            receiver,
+            token,
+            eth,
+            from_block,
+            to_block,
+            after,
+            limit,
+        } => {
+            let mut query = RecordQuery::new()
+                .kinds(kind)
+                .blocks(from_block, to_block)
+                .limit(limit);
+            if let Some(sender) = sender {
+                query = query.sender(sender);
             }
-
-            #[tokio::test]
-            #[traced_test]
-            async fn deposit_err_min_decimal() {
-                let acc = init().await.unwrap();
-                let amount = U256::from(10).pow(U256::from(10)) - U256::from(1);
-
-                let user = &acc[1];
-                let bridge = contract_provider!(Bridge, user);
-                assert!(
-                    bridge
-                        .deposit(user.address())
-                        .value(amount)
-                        .send()
-                        .await
-                        .is_err()
-                );
+            if let Some(receiver) = receiver {
+                query = query.receiver(receiver);
+            }
+            if let Some(token) = token {
+                query = query.token(token);
             }
+            if eth {
+                query = query.eth();
+            }
+            if let Some(after) = after {
+                query = query.after(after);
+            }
+            history(user, db, query).await
+        }
+        Command::PendingWithdrawals { db, from_block } => {
+            pending_withdrawals(user, db, from_block).await
         }
+        Command::Relayer {
+            credits,
+            state,
+            from_block,
+        } => relayer(user, credits, state, from_block).await,
+        Command::Executor {
+            intents,
+            state,
+            interval,
+        } => executor(user, intents, state, interval).await,
+    }
+}
+
+async fn keys(command: KeysCommand) -> Result<()> {
+    let keystore = || match &config::profile()?.keys {
+        KeySource::Keystore(path) => KeyStore::open(path),
+        KeySource::Env(var) => bail!("Ключи профиля берутся из переменной окружения {var}"),
+    };
 
-        mod withdraw {
-            use alloy::{
-                consensus::constants::ETH_TO_WEI, network::TransactionBuilder, primitives::U256,
-                providers::Provider, rpc::types::TransactionRequest,
-            };
-
-            use tracing::{debug, info};
-            use tracing_test::traced_test;
-
-            use crate::{
-                contract_provider,
-                contracts::Bridge::{self},
-                init, provider,
-            };
-
-            #[tokio::test]
-            #[traced_test]
-            async fn withdraw() {
-                let acc = init().await.unwrap();
-
-                // Запрос на вывод отправляется от owner
-                let owner = acc[0].clone();
-                let owner_bridge = contract_provider!(Bridge, owner);
-                let owner_provider = provider!(owner);
-
-                // Проверка достаточно ли баланса на bridge для вывода
-
-                let bridge_address = *owner_bridge.address();
-                let bridge_balance = owner_provider.get_balance(bridge_address).await.unwrap();
-                let need_balance = U256::from(10).pow(U256::from(10)) * U256::from(2);
-                if bridge_balance < need_balance {
-                    dbg!(owner_provider.get_balance(owner.address()).await.unwrap());
-
-                    debug!("Не достаточно средств на мосте {bridge_balance} < {need_balance}");
-
-                    info!("Пополняем баланс");
-
-                    let tx = owner_provider
-                        .send_transaction(
-                            TransactionRequest::default()
-                                .with_from(owner.address())
-                                .with_to(bridge_address)
-                                .with_value(U256::from(ETH_TO_WEI)),
-                        )
-                        .await
-                        .unwrap()
-                        .watch()
-                        .await
-                        .unwrap();
-                    info!("Баланс моста пополнен на 1 eth. tx:{tx:#?} ");
-                }
-
-                for user in acc.iter().skip(1).take(2) {
-                    let address = user.address();
-                    let old_balance = owner_provider.get_balance(address).await.unwrap();
-
-                    let bridge = contract_provider!(Bridge, user);
-                    let old_bridge_balance =
-                        owner_provider.get_balance(bridge_address).await.unwrap();
-
-                    let amount = U256::from(10_u64.pow(14)); // 0.0001 ETH
-
-                    let old_withdraw_amount = bridge.available_to_withdraw().call().await.unwrap();
-                    info!("Разрешаем на вывод 0.00000001 ETH для {address}");
-                    let tx = owner_bridge
-                        .apply_withdrawal_request(address, 10000) // 1 = 0.0001 ETH
-                        .send()
-                        .await
-                        .unwrap()
-                        .watch()
-                        .await
-                        .unwrap();
-                    info!("tx: {tx:#?}");
-
-                    // Баланс моста не должен измениться
-                    assert_eq!(
-                        old_bridge_balance,
-                        owner_provider.get_balance(bridge_address).await.unwrap()
-                    );
-
-                    let new_withdraw_amount = bridge.available_to_withdraw().call().await.unwrap();
-                    assert_eq!(
-                        new_withdraw_amount - old_withdraw_amount,
-                        amount,
-                        "old: {old_withdraw_amount}\n\
-                        new: {new_withdraw_amount}"
-                    );
-
-                    let tx = bridge
-                        .withdraw()
-                        .send()
-                        .await
-                        .unwrap()
-                        .watch()
-                        .await
-                        .unwrap();
-                    info!("tx: {tx:#?}");
-
-                    assert_eq!(
-                        bridge.available_to_withdraw().call().await.unwrap(),
-                        U256::ZERO
-                    );
-
-                    let new_bridge_balance =
-                        owner_provider.get_balance(bridge_address).await.unwrap();
-                    assert_eq!(
-                        old_bridge_balance - amount,
-                        new_bridge_balance,
-                        "old: {old_bridge_balance}\n\
-                        new: {new_bridge_balance}"
-                    );
-                    let new_balance = owner_provider.get_balance(address).await.unwrap();
-                    assert!(
-                        old_balance - amount < new_balance,
-                        "old: {old_balance}\n\
-                        new: {new_balance}"
-                    );
-                }
+    match command {
+        KeysCommand::List => {
+            for (index, signer) in accounts::read_accounts().await?.iter().enumerate() {
+                println!("{index}: {}", signer.address());
             }
+        }
+        KeysCommand::New => {
+            let signer = keystore()?.create()?;
+            println!("Создан ключ {}", signer.address());
+        }
+        KeysCommand::Import => {
+            let key = Zeroizing::new(
+                rpassword::prompt_password("Приватный ключ: ")
+                    .context("Неудалось прочитать ключ")?,
+            );
+            let mut key = B256::from_str(key.trim()).context("Ожидается 32 байта в hex")?;
+            let signer = keystore()?.import(&key);
+            key.0.zeroize();
+            let signer = signer?;
+            println!("Импортирован ключ {}", signer.address());
+        }
+    }
+    Ok(())
+}
 
-            #[tokio::test]
-            #[traced_test]
-            async fn withdraw_err_not_owner() {
-                let acc = init().await.unwrap();
+async fn deploy() -> Result<()> {
+    let accounts = init().await?;
+    let owner = Deployer::connect(&accounts[0]).await?;
+
+    println!("Bridge: {}", bridge_address(&owner).await?);
+    for (name, token) in [
+        ("DemoERC20", Token::Demo),
+        ("TestERC20", Token::Test),
+        ("ExmERC20", Token::Exm),
+    ] {
+        println!("{name}: {}", token.address(&owner).await?);
+    }
+    Ok(())
+}
 
-                for user in acc.into_iter().skip(1).take(2) {
-                    let bridge = contract_provider!(Bridge, user);
+async fn create_bridge(user: Signer, token: Token) -> Result<()> {
+    let token_address = token.address(&Deployer::connect(&user).await?).await?;
+    let client = BridgeClient::connect(user).await?;
 
-                    let res = bridge
-                        .apply_withdrawal_request(user.address(), 1)
-                        .call()
-                        .await;
-                    assert!(res.is_err(), "{res:#?}");
-                }
-            }
+    if client.exist_bridge_erc20(token_address).await? {
+        bail!("Мост для токена {token_address} уже существует");
+    }
 
-            #[tokio::test]
-            #[traced_test]
-            async fn withdraw_err_without_approval() {
-                let acc = init().await.unwrap();
+    let outcome = client.create_bridge(token_address).await?;
+    println!("Мост для {token_address} создан. {}", describe(&outcome));
+    Ok(())
+}
 
-                for user in acc.into_iter().skip(1).take(2) {
-                    let bridge = contract_provider!(Bridge, user);
+async fn deposit(user: Signer, amount: String, to: Option<Address>) -> Result<()> {
+    let units = TokenUnits::eth();
+    let amount = units.parse(&amount)?;
+    let to = to.unwrap_or(user.address());
+    let client = BridgeClient::connect(user).await?;
+
+    let outcome = client.deposit(to, amount.value()).await?;
+    println!(
+        "Перевод {} l1=>l2 на {to}. {}",
+        units.format(amount),
+        describe(&outcome)
+    );
+    Ok(())
+}
 
-                    if bridge.available_to_withdraw().call().await.unwrap() != U256::ZERO {
-                        continue;
-                    }
+async fn deposit_erc20(
+    user: Signer,
+    token: Token,
+    amount: String,
+    to: Option<Address>,
+) -> Result<()> {
+    let deployer = Deployer::connect(&user).await?;
+    let token_address = token.address(&deployer).await?;
+    let units = TokenUnits::fetch(token_address, deployer.provider()).await?;
+    let amount = units.parse(&amount)?;
+    units.to_l2(amount)?;
+    let to = to.unwrap_or(user.address());
+    let client = BridgeClient::connect(user).await?;
+
+    let outcome = client
+        .deposit_erc20(token_address, to, amount.value())
+        .await?;
+    if let Some(approval) = &outcome.approval {
+        println!("Одобрение перевода на мост. {}", describe(approval));
+    }
+    println!(
+        "Перевод {} l1=>l2 на {to}. {}",
+        units.format(amount),
+        describe(&outcome.deposit)
+    );
+    Ok(())
+}
 
-                    let res = bridge.withdraw().send().await;
-                    assert!(res.is_err(), "{res:#?}");
-                }
-            }
+async fn apply_withdrawal(
+    user: Signer,
+    to: Address,
+    amount: String,
+    token: Option<Token>,
+) -> Result<()> {
+    let (token_address, units) = match token {
+        Some(token) => {
+            let deployer = Deployer::connect(&user).await?;
+            let token_address = token.address(&deployer).await?;
+            let units = TokenUnits::fetch(token_address, deployer.provider()).await?;
+            (Some(token_address), units)
         }
-    }
+        None => (None, TokenUnits::eth()),
+    };
+    let l2_amount = units.to_l2(units.parse(&amount)?)?;
+    let amount = l2_amount.value().to::<u64>();
+    let client = BridgeClient::connect(user).await?;
+
+    let outcome = match token_address {
+        Some(token_address) => {
+            client
+                .apply_withdrawal_erc20(token_address, to, amount)
+                .await?
+        }
+        None => client.apply_withdrawal(to, amount).await?,
+    };
+    println!(
+        "Заявка на вывод {} для {to} создана. {}",
+        units.format(l2_amount),
+        describe(&outcome)
+    );
+    Ok(())
+}
 
-    /// ERC20
-    mod tests_erc {
-        use alloy::primitives::U256;
+async fn withdraw(user: Signer) -> Result<()> {
+    let client = BridgeClient::connect(user).await?;
 
-        fn calc_min_amount(decimal: u8) -> U256 {
-            if decimal > 8 {
-                U256::from(10_u64.pow(decimal as u32 - 8))
-            } else {
-                U256::from(1)
-            }
-        }
+    let amount = TokenUnits::eth().l1(client.available_to_withdraw().await?);
+    let outcome = client.withdraw().await?;
+    println!("Выведено {amount} ETH. {}", describe(&outcome));
+    Ok(())
+}
 
-        /// Создание моста для (ERC20)
-        mod tests_create_bridge {
-            use alloy::{
-                consensus::constants::ETH_TO_WEI,
-                primitives::{Address, U256},
-                providers::{Provider, ProviderBuilder},
-            };
-            use rand::random;
-            use tracing::{info, warn};
-            use tracing_test::traced_test;
-
-            use crate::{
-                RPC_URL, contract_provider,
-                contracts::{Bridge, DemoERC20, ExmERC20, TestERC20},
-                init,
-            };
-
-            // (ERC20) попытка подключить без комиссии или с недостаточной комиссией
-            #[tokio::test]
-            #[traced_test]
-            async fn err_commission() {
-                let acc = init().await.unwrap();
-
-                let owner = &acc[1];
-                let provider = ProviderBuilder::new()
-                    .wallet(owner.clone())
-                    .connect(RPC_URL)
-                    .await
-                    .unwrap();
-
-                let new_token = DemoERC20::deploy(provider).await.unwrap();
-                let new_token_address = *new_token.address();
-                let bridge = contract_provider!(Bridge, owner);
-
-                if bridge
-                    .exist_bridge_erc20(new_token_address)
-                    .call()
-                    .await
-                    .unwrap()
-                {
-                    warn!("Контракт существует {new_token_address}");
-                    return;
-                }
-
-                assert!(
-                    bridge
-                        .create_bridge_erc20(new_token_address)
-                        .send()
-                        .await
-                        .is_err()
-                );
+async fn withdraw_erc20(user: Signer, token: Token) -> Result<()> {
+    let deployer = Deployer::connect(&user).await?;
+    let token_address = token.address(&deployer).await?;
+    let units = TokenUnits::fetch(token_address, deployer.provider()).await?;
+    let client = BridgeClient::connect(user).await?;
 
-                assert!(
-                    bridge
-                        .create_bridge_erc20(new_token_address)
-                        .value(U256::from(ETH_TO_WEI - 1))
-                        .send()
-                        .await
-                        .is_err()
-                );
-            }
+    let amount = units.l1(client.available_to_withdraw_erc20(token_address).await?);
+    let outcome = client.withdraw_erc20(token_address).await?;
+    println!("Выведено {}. {}", units.format(amount), describe(&outcome));
+    Ok(())
+}
 
-            // (ERC20) попытка подключить несуществующего токен
-            #[tokio::test]
-            #[traced_test]
-            async fn err_invalid_token() {
-                let acc = init().await.unwrap();
-
-                let token_address = Address::from_slice(&random::<[u8; 20]>());
-
-                for user in &acc[..2] {
-                    let bridge = contract_provider!(Bridge, user);
-                    let exi = bridge
-                        .exist_bridge_erc20(token_address)
-                        .call()
-                        .await
-                        .unwrap();
-                    if exi {
-                        continue;
-                    }
-
-                    assert!(
-                        bridge
-                            .create_bridge_erc20(token_address)
-                            .value(U256::from(ETH_TO_WEI))
-                            .send()
-                            .await
-                            .is_err()
-                    );
-                }
-            }
+/// Адрес `Bridge` из профиля или реестра
+async fn bridge_address(deployer: &Deployer) -> Result<Address> {
+    Ok(deployer
+        .find::<BridgeInstance<WalletProvider>>()
+        .await?
+        .address())
+}
+
+fn describe(outcome: &TxOutcome) -> String {
+    format!(
+        "Tx: {}, блок {}, газ {}",
+        outcome.tx_hash, outcome.block_number, outcome.gas_used
+    )
+}
+
+async fn balances(user: Signer, address: Option<Address>) -> Result<()> {
+    let deployer = Deployer::connect(&user).await?;
+    let provider = deployer.provider();
+    let bridge = deployer.find::<BridgeInstance<WalletProvider>>().await?;
+    let address = address.unwrap_or(user.address());
+
+    let eth = TokenUnits::eth();
+    println!("Адрес: {address}");
+    println!(
+        "{}",
+        eth.format(eth.l1(provider.get_balance(address).await?))
+    );
+
+    let mut tokens = Vec::new();
+    for token in [Token::Demo, Token::Test, Token::Exm] {
+        let token_address = token.address(&deployer).await?;
+        let units = TokenUnits::fetch(token_address, provider).await?;
+        tokens.push((token_address, units));
+    }
+
+    let token_addresses = tokens.iter().map(|(v, _)| *v).collect::<Vec<_>>();
+    let balances = token_balances(provider, address, &token_addresses).await?;
+    for ((_, units), balance) in tokens.iter().zip(balances) {
+        println!("{}", units.format(units.l1(balance)));
+    }
 
-            // (ERC20) Успешное подключение ERC20 токена
-            #[tokio::test]
-            #[traced_test]
-            async fn success() {
-                let acc = init().await.unwrap();
-
-                let [owner, alice, bob] = acc[..3].to_vec().try_into().unwrap();
-
-                for (user, token_address) in [
-                    (
-                        owner.clone(),
-                        *contract_provider!(DemoERC20, owner).address(),
-                    ),
-                    (
-                        alice.clone(),
-                        *contract_provider!(TestERC20, alice).address(),
-                    ),
-                    (bob.clone(), *contract_provider!(ExmERC20, bob).address()),
-                ] {
-                    let bridge = contract_provider!(Bridge, user);
-                    let bridge_address = *bridge.address();
-                    let provider = ProviderBuilder::new()
-                        .wallet(user.clone())
-                        .connect(RPC_URL)
-                        .await
-                        .unwrap();
-
-                    if bridge
-                        .exist_bridge_erc20(token_address)
-                        .call()
-                        .await
-                        .unwrap()
-                    {
-                        info!("Моста для токена {token_address} существует ");
-                        continue;
-                    }
-
-                    let amount = U256::from(ETH_TO_WEI);
-                    let old_bridge_balance = provider.get_balance(bridge_address).await.unwrap();
-                    let old_user_balance = provider.get_balance(user.address()).await.unwrap();
-
-                    let tx = bridge
-                        .create_bridge_erc20(token_address)
-                        .value(amount)
-                        .send()
-                        .await
-                        .unwrap()
-                        .watch()
-                        .await
-                        .unwrap();
-                    info!("Мост для {token_address} создан  Tx {tx:?}");
-
-                    assert!(
-                        bridge
-                            .exist_bridge_erc20(token_address)
-                            .call()
-                            .await
-                            .unwrap()
-                    );
-
-                    let new_bridge_balance = provider.get_balance(bridge_address).await.unwrap();
-                    let new_user_balance = provider.get_balance(user.address()).await.unwrap();
-
-                    assert_eq!(old_bridge_balance + amount, new_bridge_balance);
-                    assert!(old_user_balance > new_user_balance - amount);
-                }
+    if address == user.address() {
+        println!(
+            "Доступно к выводу: {}",
+            eth.format(eth.l1(bridge.available_to_withdraw().call().await?))
+        );
+        for (token_address, units) in &tokens {
+            if !bridge.exist_bridge_erc20(*token_address).call().await? {
+                continue;
             }
+            let amount = bridge
+                .available_to_withdraw_erc20(*token_address)
+                .call()
+                .await?;
+            println!("Доступно к выводу: {}", units.format(units.l1(amount)));
+        }
+    }
+    Ok(())
+}
+
+async fn status(user: Signer, token: Option<Token>) -> Result<()> {
+    let deployer = Deployer::connect(&user).await?;
+    let provider = deployer.provider();
+    let bridge = deployer.find::<BridgeInstance<WalletProvider>>().await?;
+    let bridge_address = bridge.address();
+
+    let eth = TokenUnits::eth();
+    println!("Bridge: {bridge_address}");
+    println!(
+        "{}",
+        eth.format(eth.l1(provider.get_balance(bridge_address).await?))
+    );
+
+    let tokens = match token {
+        Some(token) => vec![token],
+        None => vec![Token::Demo, Token::Test, Token::Exm],
+    };
+    for token in tokens {
+        let token_address = token.address(&deployer).await?;
+        let info = bridge.status_bridge_erc20(token_address).call().await?;
+        if !info.turn {
+            println!("{token_address}: мост не создан");
+            continue;
         }
+        println!(
+            "{token_address}: {} ({}) decimals l1: {} l2: {}",
+            info.name, info.symbol, info.base_decimals, info.decimals
+        );
+    }
+    Ok(())
+}
 
-        /// (ERC20) Пополнение баланса на l2
-        mod deposit {
-            use alloy::{consensus::constants::ETH_TO_WEI, primitives::U256};
-
-            use tokio::sync::mpsc;
-            use tracing::{debug, info};
-            use tracing_test::traced_test;
-
-            use crate::{
-                console::{self, watch_deposit_erc20_event},
-                contract_provider,
-                contracts::{Bridge, DemoERC20, ExmERC20, TestERC20},
-                init,
-                tests::tests_erc::calc_min_amount,
-                tokens_balance,
-            };
-
-            #[tokio::test]
-            #[traced_test]
-            async fn deposit() {
-                let acc = init().await.unwrap();
-
-                let console_handle = console::watch_logs(acc[0].clone()).await.unwrap();
-
-                let owner = acc[0].clone();
-                let owner_bridge = contract_provider!(Bridge, owner);
-                let bridge_address = *owner_bridge.address();
-                let demo_token = contract_provider!(DemoERC20, owner);
-                let test_token = contract_provider!(TestERC20, owner);
-                let exm_token = contract_provider!(ExmERC20, owner);
-
-                let tokens = [
-                    *demo_token.address(),
-                    *test_token.address(),
-                    *exm_token.address(),
-                ];
-                for token_address in &tokens {
-                    if owner_bridge
-                        .exist_bridge_erc20(*token_address)
-                        .call()
-                        .await
-                        .unwrap()
-                    {
-                        info!("Мост уже существует");
-                        continue;
-                    }
-
-                    info!("Создание моста для токена {token_address}");
-                    let tx = owner_bridge
-                        .create_bridge_erc20(*token_address)
-                        .value(U256::from(ETH_TO_WEI))
-                        .send()
-                        .await
-                        .unwrap()
-                        .watch()
-                        .await
-                        .unwrap();
-                    info!("Tx {tx:?}");
-                }
-
-                let min_amount = [
-                    calc_min_amount(demo_token.decimals().call().await.unwrap()),
-                    calc_min_amount(test_token.decimals().call().await.unwrap()),
-                    calc_min_amount(exm_token.decimals().call().await.unwrap()),
-                ];
-
-                // Мониторинг событий пополнения депозита
-                let (sender, mut rec_deposit_events) = mpsc::channel(10);
-                let deposit_handle = watch_deposit_erc20_event(owner, sender).await.unwrap();
-
-                for user in acc.into_iter().skip(1).take(2) {
-                    let user_address = user.address();
-                    let user_bridge = contract_provider!(Bridge, user);
-                    let user_demo_token = contract_provider!(DemoERC20, user);
-                    let user_test_token = contract_provider!(TestERC20, user);
-                    let user_exm_token = contract_provider!(ExmERC20, user);
-
-                    info!("Одобрение перевода на кошелёк");
-                    user_demo_token
-                        .approve(bridge_address, min_amount[0])
-                        .send()
-                        .await
-                        .unwrap()
-                        .watch()
-                        .await
-                        .unwrap();
-                    user_test_token
-                        .approve(bridge_address, min_amount[1])
-                        .send()
-                        .await
-                        .unwrap()
-                        .watch()
-                        .await
-                        .unwrap();
-                    user_exm_token
-                        .approve(bridge_address, min_amount[2])
-                        .send()
-                        .await
-                        .unwrap()
-                        .watch()
-                        .await
-                        .unwrap();
-
-                    let old_user_balance = tokens_balance!(
-                        user_address,
-                        user_demo_token,
-                        user_test_token,
-                        user_exm_token
-                    );
-                    let old_bridge_balance = tokens_balance!(
-                        bridge_address,
-                        user_demo_token,
-                        user_test_token,
-                        user_exm_token
-                    );
-
-                    for (token_address, amount) in tokens.iter().zip(min_amount) {
-                        user_bridge
-                            .deposit_erc20(*token_address, user_address, amount)
-                            .send()
-                            .await
-                            .unwrap()
-                            .watch()
-                            .await
-                            .unwrap();
-                    }
-
-                    for (tx, token) in [
-                        rec_deposit_events.recv().await.unwrap(),
-                        rec_deposit_events.recv().await.unwrap(),
-                        rec_deposit_events.recv().await.unwrap(),
-                    ]
-                    .iter()
-                    .zip(tokens)
-                    {
-                        assert_eq!(tx.token_address, token);
-                        assert_eq!(tx.from, user_address);
-                        assert_eq!(tx.to, user_address);
-                        assert_eq!(tx.value, U256::from(1));
-                    }
-
-                    let new_user_balance = tokens_balance!(
-                        user_address,
-                        user_demo_token,
-                        user_test_token,
-                        user_exm_token
-                    );
-                    let new_bridge_balance = tokens_balance!(
-                        bridge_address,
-                        user_demo_token,
-                        user_test_token,
-                        user_exm_token
-                    );
-
-                    for ((old, new), amount) in old_user_balance
-                        .iter()
-                        .zip(&new_user_balance)
-                        .zip(&min_amount)
-                    {
-                        assert_eq!(
-                            old - new,
-                            *amount,
-                            "new: {new}, old: {old}, amount: {amount}"
-                        );
-                    }
-
-                    for ((old, new), amount) in old_bridge_balance
-                        .iter()
-                        .zip(&new_bridge_balance)
-                        .zip(&min_amount)
-                    {
-                        assert_eq!(
-                            new - old,
-                            *amount,
-                            "new: {new}, old: {old}, amount: {amount}"
-                        );
-                    }
-                }
-                console_handle.abort();
-                deposit_handle.abort();
-            }
+async fn storage(
+    user: Signer,
+    block: Option<u64>,
+    tokens: Vec<Token>,
+    address: Option<Address>,
+) -> Result<()> {
+    let deployer = Deployer::connect(&user).await?;
+    let bridge = bridge_address(&deployer).await?;
+    let mut storage = BridgeStorage::new(deployer.provider(), bridge);
+    if let Some(block) = block {
+        storage = storage.at(block);
+    }
 
-            #[tokio::test]
-            #[traced_test]
-            async fn deposit_err_min_decimal() {
-                let acc = init().await.unwrap();
-                let alice = acc[1].to_owned();
-
-                let demo_token = contract_provider!(DemoERC20, alice);
-                let demo_address = *demo_token.address();
-
-                let bridge = contract_provider!(Bridge, alice);
-                let bridge_address = *bridge.address();
-
-                if !bridge
-                    .exist_bridge_erc20(demo_address)
-                    .call()
-                    .await
-                    .unwrap()
-                {
-                    bridge
-                        .create_bridge_erc20(demo_address)
-                        .value(U256::from(ETH_TO_WEI))
-                        .send()
-                        .await
-                        .unwrap()
-                        .watch()
-                        .await
-                        .unwrap();
-                }
-
-                let decimals = demo_token.decimals().call().await.unwrap();
-                info!("Decimals: {decimals}");
-                assert!(decimals > 8);
-
-                let min = U256::from(10_u64.pow(decimals as u32 - 8) + 1);
-                demo_token
-                    .approve(bridge_address, min)
-                    .send()
-                    .await
-                    .unwrap()
-                    .watch()
-                    .await
-                    .unwrap();
-                let res = bridge
-                    .deposit_erc20(demo_address, alice.address(), min)
-                    .send()
-                    .await;
-                debug!("{res:#?}");
-                assert!(res.is_err(), "{res:#?}");
-            }
+    println!("Bridge: {bridge}");
+    println!("owner: {}", storage.owner().await?);
+    if let Some(address) = address {
+        let eth = TokenUnits::eth();
+        let amount = storage.withdraw_request(address).await?;
+        println!("{address} к выводу: {}", eth.format(eth.l1(amount)));
+    }
 
-            #[tokio::test]
-            #[traced_test]
-            async fn deposit_err_without_approve() {
-                let acc = init().await.unwrap();
-                let alice = acc[1].to_owned();
-
-                let demo_token = contract_provider!(DemoERC20, alice);
-                let demo_address = *demo_token.address();
-
-                let bridge = contract_provider!(Bridge, alice);
-                let bridge_address = *bridge.address();
-
-                if !bridge
-                    .exist_bridge_erc20(demo_address)
-                    .call()
-                    .await
-                    .unwrap()
-                {
-                    bridge
-                        .create_bridge_erc20(demo_address)
-                        .value(U256::from(ETH_TO_WEI))
-                        .send()
-                        .await
-                        .unwrap()
-                        .watch()
-                        .await
-                        .unwrap();
-                }
-
-                let decimals = demo_token.decimals().call().await.unwrap();
-                info!("Decimals: {decimals}");
-                assert!(decimals > 8);
-
-                let min = U256::from(10_u64.pow(decimals as u32 - 8));
-                demo_token
-                    .approve(bridge_address, min)
-                    .send()
-                    .await
-                    .unwrap()
-                    .watch()
-                    .await
-                    .unwrap();
-                let res = bridge
-                    .deposit_erc20(demo_address, alice.address(), min * U256::from(2))
-                    .send()
-                    .await;
-                debug!("{res:#?}");
-                assert!(res.is_err(), "{res:#?}");
-            }
+    let tokens = if tokens.is_empty() {
+        vec![Token::Demo, Token::Test, Token::Exm]
+    } else {
+        tokens
+    };
+    for token in tokens {
+        let token_address = token.address(&deployer).await?;
+        let info = storage.bridge_token(token_address).await?;
+        if !info.turn {
+            println!("{token_address}: мост не создан");
+            continue;
         }
+        println!(
+            "{token_address}: {} ({}) decimals l1: {} l2: {}",
+            info.name, info.symbol, info.base_decimals, info.decimals
+        );
+        if let Some(address) = address {
+            let units = TokenUnits::from_info(&info);
+            let amount = storage
+                .withdraw_request_erc20(address, token_address)
+                .await?;
+            println!("  {address} к выводу: {}", units.format(units.l1(amount)));
+        }
+    }
+    Ok(())
+}
 
-        mod withdraw {
-            use alloy::{consensus::constants::ETH_TO_WEI, primitives::U256};
-            use tracing::{debug, info};
-            use tracing_test::traced_test;
-
-            use crate::{
-                console::{self},
-                contract_provider,
-                contracts::{Bridge, DemoERC20, ExmERC20, TestERC20},
-                init,
-                tests::tests_erc::calc_min_amount,
-                token_fund, tokens_balance,
-            };
-
-            #[tokio::test]
-            #[traced_test]
-            async fn withdraw() {
-                let acc = init().await.unwrap();
-
-                let console_handle = console::watch_logs(acc[0].clone()).await.unwrap();
-
-                let owner = acc[0].clone();
-                let owner_address = owner.address();
-
-                let alice = acc[1].clone();
-                let alice_address = alice.address();
-                debug!("alice: {alice_address:?}");
-
-                let bob = acc[2].clone();
-                let bob_address = bob.address();
-                debug!("bob: {bob_address:?}");
-
-                let owner_bridge = contract_provider!(Bridge, owner);
-                let bridge_address = *owner_bridge.address();
-                debug!("bridge address: {bridge_address:?}");
-
-                let demo_token = contract_provider!(DemoERC20, owner);
-                let test_token = contract_provider!(TestERC20, alice);
-                let exm_token = contract_provider!(ExmERC20, bob);
-
-                // Пополнение баланса моста
-                {
-                    let bridge_address = [bridge_address];
-                    token_fund!(demo_token, bridge_address);
-                    token_fund!(test_token, bridge_address);
-                    token_fund!(exm_token, bridge_address);
-                }
-
-                let tokens = [
-                    *demo_token.address(),
-                    *test_token.address(),
-                    *exm_token.address(),
-                ];
-
-                // Проверка существования и создание мостов
-                for token_address in &tokens {
-                    if owner_bridge
-                        .exist_bridge_erc20(*token_address)
-                        .call()
-                        .await
-                        .unwrap()
-                    {
-                        info!("Мост уже существует");
-                        continue;
-                    }
-
-                    info!("Создание моста для токена {token_address}");
-                    let tx = owner_bridge
-                        .create_bridge_erc20(*token_address)
-                        .value(U256::from(ETH_TO_WEI))
-                        .send()
-                        .await
-                        .unwrap()
-                        .watch()
-                        .await
-                        .unwrap();
-                    info!("Tx {tx:?}");
-                }
-
-                let min_amount = [
-                    calc_min_amount(demo_token.decimals().call().await.unwrap()),
-                    calc_min_amount(test_token.decimals().call().await.unwrap()),
-                    calc_min_amount(exm_token.decimals().call().await.unwrap()),
-                ];
-
-                let old_owner_balance =
-                    tokens_balance!(owner_address, demo_token, test_token, exm_token);
-                let old_alice_balance =
-                    tokens_balance!(alice_address, demo_token, test_token, exm_token);
-                let old_bob_balance =
-                    tokens_balance!(bob_address, demo_token, test_token, exm_token);
-                let old_bridge_balance =
-                    tokens_balance!(bridge_address, demo_token, test_token, exm_token);
-
-                for token_address in tokens {
-                    for user in acc.iter().skip(1).take(2) {
-                        let user_address = user.address();
-                        info!(
-                            "Owner создаёт заявки на вывод токена {token_address:?} на адрес {user_address}"
-                        );
-                        let tx = owner_bridge
-                            .apply_withdrawal_request_erc20(token_address, user.address(), 1)
-                            .send()
-                            .await
-                            .unwrap()
-                            .watch()
-                            .await
-                            .unwrap();
-                        info!("Tx {tx:?}");
-
-                        let bridge = contract_provider!(Bridge, user);
-                        let withdraw_balance = bridge
-                            .available_to_withdraw_erc20(token_address)
-                            .call()
-                            .await
-                            .unwrap();
-                        assert_ne!(withdraw_balance, U256::ZERO);
-
-                        info!("Выводим токены {token_address:?} из моста на адрес {user_address}");
-                        let tx = bridge
-                            .withdraw_erc20(token_address)
-                            .send()
-                            .await
-                            .unwrap()
-                            .watch()
-                            .await
-                            .unwrap();
-                        info!("Tx {tx:?}");
-                    }
-                }
-
-                let new_owner_balance =
-                    tokens_balance!(owner_address, demo_token, test_token, exm_token);
-                let new_alice_balance =
-                    tokens_balance!(alice_address, demo_token, test_token, exm_token);
-                let new_bob_balance =
-                    tokens_balance!(bob_address, demo_token, test_token, exm_token);
-                let new_bridge_balance =
-                    tokens_balance!(bridge_address, demo_token, test_token, exm_token);
-
-                for (old, new) in [
-                    (old_alice_balance, new_alice_balance),
-                    (old_bob_balance, new_bob_balance),
-                ] {
-                    for ((old, new), amount) in old.iter().zip(new).zip(min_amount) {
-                        assert_eq!(
-                            new - *old,
-                            amount,
-                            "new: {new}, old: {old}, expected: {amount}"
-                        );
-                    }
-                }
-
-                assert_eq!(old_owner_balance, new_owner_balance);
-
-                dbg!(&old_bridge_balance, &new_bridge_balance);
-
-                for ((old, new), amount) in old_bridge_balance
-                    .iter()
-                    .zip(new_bridge_balance)
-                    .zip(min_amount)
-                {
-                    let amount_x2 = amount * U256::from(2);
-                    assert_eq!(
-                        old - new,
-                        amount_x2,
-                        "new: {new}, old: {old}, expected: {amount_x2}"
-                    );
-                }
-
-                console_handle.abort();
+async fn audit(user: Signer, from_block: u64, to_block: Option<u64>) -> Result<()> {
+    let deployer = Deployer::connect(&user).await?;
+    let provider = deployer.provider();
+    let bridge = bridge_address(&deployer).await?;
+    let to_block = match to_block {
+        Some(to_block) => to_block,
+        None => provider.get_block_number().await?,
+    };
+
+    let report = audit::audit_deposits(provider, bridge, from_block, to_block).await?;
+    let eth = TokenUnits::eth();
+    for finding in &report.findings {
+        let meta = finding.meta;
+        let details = match &finding.discrepancy {
+            Discrepancy::Truncated { expected } => {
+                format!("зачислено {}, ожидалось {}", finding.event.value, expected)
             }
+            Discrepancy::Unconvertible(err) => err.to_string(),
+            Discrepancy::IndirectCall { to } => format!("вызов через контракт {to:?}"),
+        };
+        println!(
+            "#{} {} [{}] {} => {}: {}: {details}",
+            meta.block_number,
+            meta.tx_hash,
+            meta.log_index,
+            finding.event.from,
+            finding.event.to,
+            eth.format(eth.l1(finding.msg_value)),
+        );
+    }
+
+    println!(
+        "Блоки {from_block}..={to_block}: проверено депозитов {}, расхождений {}",
+        report.checked,
+        report.findings.len()
+    );
+    if !report.findings.is_empty() {
+        bail!("Найдены депозиты с расхождениями");
+    }
+    Ok(())
+}
 
-            #[tokio::test]
-            #[traced_test]
-            async fn withdraw_err_not_owner() {
-                let acc = init().await.unwrap();
-
-                let alice = acc[1].to_owned();
-                let demo_token = contract_provider!(DemoERC20, alice);
-                let bridge = contract_provider!(Bridge, alice);
-
-                let res = bridge
-                    .apply_withdrawal_request_erc20(*demo_token.address(), alice.address(), 1)
-                    .send()
-                    .await;
-                debug!("{res:#?}");
-                assert!(res.is_err(), "{res:#?}");
+async fn watch(
+    user: Signer,
+    addresses: Vec<Address>,
+    kinds: Vec<EventKind>,
+    from_block: Option<u64>,
+) -> Result<()> {
+    let deployer = Deployer::connect(&user).await?;
+    let provider = deployer.provider();
+    let filter = EventFilter { addresses, kinds };
+    let mut events = match from_block {
+        Some(from_block) => events::subscribe_from(provider, &filter, from_block)
+            .await?
+            .boxed(),
+        None => events::subscribe(provider, &filter).await?.map(Ok).boxed(),
+    };
+
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+            event = events.next() => {
+                let Some(event) = event else {
+                    return Ok(());
+                };
+                let EventLog { meta, event } = event?;
+                println!(
+                    "#{} {} [{}] {}: {event:?}",
+                    meta.block_number, meta.tx_hash, meta.log_index, meta.address
+                );
             }
         }
     }
+}
 
-    #[tokio::test]
-    #[traced_test]
-    async fn convert_decimals() {
-        let owner = init().await.unwrap()[0].to_owned();
-        let bridge = contract_provider!(Bridge, owner);
-
-        let _handle = watch_logs(owner.clone()).await.unwrap();
-
-        for (input, output) in [
-            ((1_234, 0, 0), 1_234),
-            ((1_234, 3, 3), 1_234),
-            ((1_234, 0, 1), 12_340),
-            ((1_234, 0, 3), 1_234_000),
-            ((1_234, 1, 3), 123_400),
-            ((1_234, 1, 3), 123_400),
-            ((1_000, 3, 0), 1),
-            ((1_000, 2, 0), 10),
-            ((1_000, 2, 1), 100),
-            ((1_230, 1, 0), 123),
-            ((1_230, 3, 2), 123),
-        ] {
-            debug!("input: {input:#?}, output: {output}");
-            assert_eq!(
-                bridge
-                    .convert_amount(U256::from(input.0), input.1, input.2)
-                    .call()
-                    .await
-                    .unwrap(),
-                U256::from(output),
-                "input: {input:#?}, output: {output}"
-            );
+async fn watch_confirmed(
+    user: Signer,
+    addresses: Vec<Address>,
+    kinds: Vec<EventKind>,
+    from_block: Option<u64>,
+    checkpoint: PathBuf,
+) -> Result<()> {
+    let filter = EventFilter { addresses, kinds };
+    let bus = EventBus::new();
+    let mut updates = bus.subscribe(16, Overflow::Block)?;
+    let mut tasks = TaskSupervisor::default();
+    console::watch_events(&mut tasks, user, filter, Some(&checkpoint), from_block, bus).await?;
+    let shutdown = tokio::spawn(tasks.run_until_shutdown());
+
+    // После SIGINT наблюдатель останавливается, и поток заканчивается на последнем
+    // полученном событии
+    while let Some(update) = updates.recv().await {
+        match update {
+            WatchUpdate::Confirmed(EventLog { meta, event }) => println!(
+                "+ #{} {} [{}] {}: {event:?}",
+                meta.block_number, meta.tx_hash, meta.log_index, meta.address
+            ),
+            WatchUpdate::Retracted(meta) => println!(
+                "- #{} {} [{}] {}",
+                meta.block_number, meta.tx_hash, meta.log_index, meta.address
+            ),
+            WatchUpdate::Processed(_) => (),
         }
+    }
+    shutdown.await?;
+    Ok(())
+}
 
-        for input in [(1_230, 3, 0), (1_230, 2, 0)] {
-            let r = bridge
-                .convert_amount(U256::from(input.0), input.1, input.2)
-                .call()
-                .await;
-            debug!("{r:#?}");
-            assert!(r.is_err(), "{r:#?}");
-        }
+async fn index(user: Signer, db: PathBuf, from_block: u64) -> Result<()> {
+    let deployer = Deployer::connect(&user).await?;
+    let provider = deployer.provider();
+    let mut indexer = Indexer::open(&db, bridge_address(&deployer).await?)?;
+    let to_block = provider
+        .get_block_number()
+        .await?
+        .saturating_sub(config::profile()?.confirmations);
+
+    let count = indexer.sync(provider, from_block, to_block).await?;
+    println!("Записано операций: {count}, проиндексировано до блока {to_block}");
+    Ok(())
+}
+
+async fn pending_withdrawals(user: Signer, db: PathBuf, from_block: u64) -> Result<()> {
+    let deployer = Deployer::connect(&user).await?;
+    let provider = deployer.provider();
+    let bridge = bridge_address(&deployer).await?;
+    let mut indexer = Indexer::open(&db, bridge)?;
+    let to_block = provider
+        .get_block_number()
+        .await?
+        .saturating_sub(config::profile()?.confirmations);
+    indexer.sync(provider, from_block, to_block).await?;
+
+    let pending = withdrawals::reconstruct(provider, &indexer).await?;
+    for PendingWithdrawal {
+        user,
+        token,
+        amount,
+    } in pending.pending()
+    {
+        let token = token.map_or("ETH".to_string(), |v| v.to_string());
+        println!("{user} {token}: {amount}");
+    }
+
+    let block_number = indexer
+        .next_block()?
+        .unwrap_or(from_block)
+        .saturating_sub(1);
+    let mismatches = pending.verify(provider, bridge, block_number).await?;
+    for Mismatch {
+        user,
+        token,
+        expected,
+        actual,
+    } in &mismatches
+    {
+        let token = token.map_or("ETH".to_string(), |v| v.to_string());
+        println!("Расхождение {user} {token}: по истории {expected}, в хранилище {actual}");
+    }
+    if !mismatches.is_empty() {
+        bail!("Суммы не совпадают с хранилищем моста в блоке {block_number}");
+    }
+    println!("Суммы совпадают с хранилищем моста в блоке {block_number}");
+    Ok(())
+}
+
+async fn history(user: Signer, db: PathBuf, query: RecordQuery) -> Result<()> {
+    let deployer = Deployer::connect(&user).await?;
+    let indexer = Indexer::open(&db, bridge_address(&deployer).await?)?;
+
+    let page = indexer.query(&query)?;
+    for record in &page.records {
+        let meta = record.meta;
+        let receiver = record
+            .receiver
+            .map(|v| format!(" => {v}"))
+            .unwrap_or_default();
+        let token = record.token.map_or("ETH".to_string(), |v| v.to_string());
+        let amount = record.amount.map(|v| format!(" {v}")).unwrap_or_default();
+        println!(
+            "#{} {} {}: {}{receiver}{amount} {token}",
+            meta.block_number, meta.tx_hash, record.kind, record.sender
+        );
+    }
+    if let Some(next) = page.next {
+        println!("Следующая страница: --after {next}");
     }
+    Ok(())
+}
+
+async fn relayer(user: Signer, credits: PathBuf, state: PathBuf, from_block: u64) -> Result<()> {
+    let deployer = Deployer::connect(&user).await?;
+    let bridge_address = bridge_address(&deployer).await?;
+    let mut relayer = Relayer::new(
+        bridge_address,
+        FileSink::open(&credits)?,
+        &state,
+        from_block,
+    )?;
+
+    // Состояние наблюдателя хранится рядом с прогрессом ретранслятора
+    let checkpoint = state.with_extension("watcher.json");
+    relayer.resume_watcher(&checkpoint).await?;
+    let bus = EventBus::new();
+    let mut updates = bus.subscribe(16, Overflow::Block)?;
+    let mut tasks = TaskSupervisor::default();
+    console::watch_events(
+        &mut tasks,
+        user,
+        Relayer::<FileSink>::filter(bridge_address),
+        Some(&checkpoint),
+        Some(relayer.state().next_block),
+        bus,
+    )
+    .await?;
+    let token = tasks.token().clone();
+    let shutdown = tokio::spawn(tasks.run_until_shutdown());
+
+    relayer.run(&mut updates, token.cancelled()).await?;
+    shutdown.await?;
+    Ok(())
+}
+
+async fn executor(user: Signer, intents: PathBuf, state: PathBuf, interval: u64) -> Result<()> {
+    let bridge = Deployer::connect(&user)
+        .await?
+        .find::<BridgeInstance<WalletProvider>>()
+        .await?
+        .into_inner();
+
+    let mut executor = WithdrawalExecutor::new(bridge, FileSource::new(&intents), &state)?;
+    executor
+        .run(Duration::from_secs(interval), async {
+            tokio::signal::ctrl_c().await.ok();
+        })
+        .await
 }
@@ -0,0 +1,287 @@
+//! Локальный узел для тестов
+//!
+//! [`LocalNode`] запускает `anvil` или `geth --dev` на свободных портах с отдельной временной
+//! директорией, дожидается готовности RPC и при `Drop` останавливает узел и удаляет директорию.
+//! Узел, который хранится в `static` и не освобождается, останавливается, а его директория
+//! удаляется при завершении процесса.
+//!
+//! Тип узла выбирается переменной `BRIDGE_TEST_NODE`: `anvil`, `geth` или `external` (узел из
+//! профиля, например запущенный `make geth__run`). По умолчанию берётся первый найденный в `PATH`,
+//! если не найден ни один, тесты с узлом завершаются ошибкой.
+
+use std::{
+    env, fs,
+    net::TcpListener,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    str::FromStr,
+    sync::{Mutex, Once},
+    time::{Duration, Instant},
+};
+
+use alloy::{
+    network::TransactionBuilder,
+    primitives::{Address, U256},
+    providers::{Provider, ProviderBuilder},
+    rpc::types::TransactionRequest,
+};
+use eyre::{Context, ContextCompat, Result, bail};
+use tracing::{debug, info};
+
+use crate::config::Profile;
+
+/// Переменная окружения с типом тестового узла
+pub const NODE_ENV: &str = "BRIDGE_TEST_NODE";
+/// Время ожидания готовности RPC
+pub const START_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// PID и директории запущенных узлов, которые нужно остановить и удалить при выходе из процесса
+static RUNNING: Mutex<Vec<(u32, PathBuf)>> = Mutex::new(Vec::new());
+static ATEXIT: Once = Once::new();
+
+extern "C" fn kill_running() {
+    let Ok(running) = RUNNING.lock() else {
+        return;
+    };
+    for (pid, dir) in running.iter() {
+        // SAFETY: kill и waitpid не обращаются к памяти процесса
+        unsafe {
+            libc::kill(*pid as libc::pid_t, libc::SIGKILL);
+            libc::waitpid(*pid as libc::pid_t, std::ptr::null_mut(), 0);
+        }
+        let _ = fs::remove_dir_all(dir);
+    }
+}
+
+/// Тип тестового узла
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Anvil,
+    Geth,
+    /// Узел из профиля, запускать ничего не нужно
+    External,
+}
+
+impl FromStr for NodeKind {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_lowercase().as_str() {
+            "anvil" => NodeKind::Anvil,
+            "geth" => NodeKind::Geth,
+            "external" => NodeKind::External,
+            _ => bail!("{NODE_ENV}: ожидается anvil, geth или external, указано {s}"),
+        })
+    }
+}
+
+impl NodeKind {
+    fn binary(self) -> Option<&'static str> {
+        match self {
+            NodeKind::Anvil => Some("anvil"),
+            NodeKind::Geth => Some("geth"),
+            NodeKind::External => None,
+        }
+    }
+
+    /// Тип из `BRIDGE_TEST_NODE`, иначе первый найденный в `PATH`.
+    /// Узел из профиля используется только по явному `BRIDGE_TEST_NODE=external`
+    pub fn detect() -> Result<Self> {
+        if let Ok(kind) = env::var(NODE_ENV) {
+            return kind.parse();
+        }
+        [NodeKind::Anvil, NodeKind::Geth]
+            .into_iter()
+            .find(|v| v.binary().is_some_and(in_path))
+            .with_context(|| {
+                format!(
+                    "anvil/geth не найдены в PATH. Установите один из них \
+                     или задайте {NODE_ENV}=external для узла из профиля"
+                )
+            })
+    }
+}
+
+fn in_path(binary: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|paths| env::split_paths(&paths).any(|v| v.join(binary).is_file()))
+}
+
+/// Свободный порт. Между проверкой и запуском узла порт может занять другой процесс,
+/// тогда узел не запустится и это будет видно по логу
+fn free_port() -> Result<u16> {
+    let listener = TcpListener::bind("127.0.0.1:0").context("Нет свободного порта")?;
+    Ok(listener.local_addr()?.port())
+}
+
+/// Запущенный локальный узел
+#[derive(Debug)]
+pub struct LocalNode {
+    child: Child,
+    /// Временная директория узла: данные, лог, реестр контрактов
+    dir: PathBuf,
+    http_url: String,
+    ws_url: String,
+    chain_id: u64,
+}
+
+impl LocalNode {
+    /// Запуск узла и ожидание готовности RPC
+    pub async fn spawn(kind: NodeKind) -> Result<Self> {
+        let binary = kind.binary().context("Внешний узел не запускается")?;
+        let dir = env::temp_dir().join(format!("bridge-node-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).context("Не удалось создать директорию узла")?;
+        let log = fs::File::create(dir.join("node.log")).context("Не удалось создать лог узла")?;
+
+        let http_port = free_port()?;
+        let mut command = Command::new(binary);
+        let ws_port = match kind {
+            NodeKind::Anvil => {
+                command.args(["--host", "127.0.0.1", "--port", &http_port.to_string()]);
+                http_port
+            }
+            NodeKind::Geth => {
+                let ws_port = free_port()?;
+                command
+                    .arg("--dev")
+                    .args(["--datadir".as_ref(), dir.join("data").as_os_str()])
+                    .args(["--http", "--http.addr", "127.0.0.1"])
+                    .args(["--http.port", &http_port.to_string()])
+                    .args(["--http.api", "eth,web3,net"])
+                    .args(["--ws", "--ws.addr", "127.0.0.1"])
+                    .args(["--ws.port", &ws_port.to_string()])
+                    .args(["--ws.api", "eth,web3,net"])
+                    .args(["--authrpc.port", &free_port()?.to_string()])
+                    .args([
+                        "--port",
+                        "0",
+                        "--nodiscover",
+                        "--maxpeers",
+                        "0",
+                        "--ipcdisable",
+                    ])
+                    .args(["--gcmode", "archive"]);
+                ws_port
+            }
+            NodeKind::External => unreachable!(),
+        };
+
+        let child = command
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .spawn()
+            .with_context(|| format!("Не удалось запустить {binary}"))?;
+        ATEXIT.call_once(|| {
+            // SAFETY: обработчик не паникует и только отправляет сигналы
+            unsafe {
+                libc::atexit(kill_running);
+            }
+        });
+        RUNNING.lock().unwrap().push((child.id(), dir.clone()));
+
+        let mut node = LocalNode {
+            child,
+            dir,
+            http_url: format!("http://127.0.0.1:{http_port}"),
+            ws_url: format!("ws://127.0.0.1:{ws_port}"),
+            chain_id: 0,
+        };
+        node.chain_id = node.wait_ready().await?;
+        info!(
+            "Запущен {binary} {} (chain id {}), лог {}",
+            node.http_url,
+            node.chain_id,
+            node.log_path().display()
+        );
+        Ok(node)
+    }
+
+    /// Ожидание ответа RPC. Возвращает chain id
+    async fn wait_ready(&mut self) -> Result<u64> {
+        let provider = ProviderBuilder::new().connect_http(self.http_url.parse()?);
+        let started = Instant::now();
+        loop {
+            if let Some(status) = self.child.try_wait()? {
+                bail!(
+                    "Узел завершился с кодом {status}, лог {}",
+                    self.log_path().display()
+                );
+            }
+            match provider.get_chain_id().await {
+                Ok(chain_id) => return Ok(chain_id),
+                Err(err) if started.elapsed() < START_TIMEOUT => {
+                    debug!("Узел ещё не готов: {err}");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+                Err(err) => {
+                    return Err(err).with_context(|| {
+                        format!(
+                            "Узел не ответил за {START_TIMEOUT:?}, лог {}",
+                            self.log_path().display()
+                        )
+                    });
+                }
+            }
+        }
+    }
+
+    pub fn log_path(&self) -> PathBuf {
+        self.dir.join("node.log")
+    }
+
+    /// Профиль для этого узла на основе `base`: адреса, chain id, отдельные реестр контрактов
+    /// и файлы прогресса
+    pub fn profile(&self, base: &Profile) -> Profile {
+        Profile {
+            http_url: self.http_url.clone(),
+            ws_url: Some(self.ws_url.clone()),
+            chain_id: self.chain_id,
+            contracts: Default::default(),
+            registry: self.dir.join("deployments.json"),
+            checkpoints: self.dir.join("checkpoints"),
+            ..base.clone()
+        }
+    }
+
+    /// Перевод `amount` wei каждому адресу с разблокированного аккаунта разработчика.
+    /// Nonce и газ заполняет узел, поэтому вызовы из параллельных тестов не конфликтуют
+    pub async fn fund(&self, addresses: &[Address], amount: U256) -> Result<()> {
+        let provider = ProviderBuilder::new()
+            .disable_recommended_fillers()
+            .connect_http(self.http_url.parse()?);
+        let dev = *provider
+            .get_accounts()
+            .await
+            .context("Не удалось получить аккаунты узла")?
+            .first()
+            .context("У узла нет аккаунта разработчика")?;
+
+        for &address in addresses {
+            let request = TransactionRequest::default()
+                .with_from(dev)
+                .with_to(address)
+                .with_value(amount);
+            provider
+                .send_transaction(request)
+                .await
+                .with_context(|| format!("Не удалось пополнить {address}"))?
+                .get_receipt()
+                .await
+                .with_context(|| format!("Не удалось пополнить {address}"))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for LocalNode {
+    fn drop(&mut self) {
+        let pid = self.child.id();
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        if let Ok(mut running) = RUNNING.lock() {
+            running.retain(|(v, _)| *v != pid);
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
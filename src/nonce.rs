@@ -0,0 +1,190 @@
+//! Общий счётчик nonce для всех провайдеров процесса
+//!
+//! `Deployer::connect` создаёт новый провайдер на каждый вызов, поэтому nonce, запрошенные у узла
+//! параллельными задачами, совпадали. [`Nonces`] выдаёт nonce локально под блокировкой аккаунта и
+//! сверяется с узлом при каждой выдаче:
+//! - если узел знает больше транзакций (отправлены в обход счётчика), счёт продолжается от узла;
+//! - если выданный nonce потерян, он выдаётся повторно, иначе следующие транзакции ждали бы вечно.
+//!   Nonce потерян, если транзакция была в пуле узла и пропала из него, не попав в блок, или если
+//!   она так и не появилась в пуле, а nonce аккаунта в блоках не менялся [`NONCE_GAP_TIMEOUT`].
+//!   Медленная транзакция, которая уже в пуле, повторно свой nonce не отдаёт.
+//!
+//! [`NonceManagerFiller`] выдаёт nonce только после оценки газа: транзакция, откатившаяся при
+//! `eth_estimateGas`, не занимает nonce.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use alloy::{
+    network::{Network, TransactionBuilder},
+    primitives::Address,
+    providers::{
+        Provider, SendableTx,
+        fillers::{FillerControlFlow, TxFiller},
+    },
+    transports::TransportResult,
+};
+use tracing::{debug, warn};
+
+/// Время, после которого выданный, но так и не появившийся в пуле узла nonce считается
+/// потерянным, если за это время у аккаунта не было новых транзакций в блоках
+pub const NONCE_GAP_TIMEOUT: Duration = Duration::from_secs(10);
+
+static GLOBAL: LazyLock<Nonces> = LazyLock::new(Nonces::default);
+
+/// Выданный nonce
+#[derive(Debug, Clone, Copy)]
+struct Issued {
+    at: Instant,
+    /// Узел видел транзакцию с этим nonce в пуле
+    seen: bool,
+}
+
+/// Выданные nonce одного аккаунта
+#[derive(Debug, Default)]
+struct AccountNonces {
+    /// Следующий nonce
+    next: u64,
+    /// Выданные nonce, ещё не попавшие в блок
+    issued: BTreeMap<u64, Issued>,
+    /// Количество транзакций аккаунта в блоках и время, когда оно изменилось
+    mined: Option<(u64, Instant)>,
+}
+
+/// Счётчики nonce по аккаунтам. Клоны разделяют состояние
+#[derive(Debug, Clone, Default)]
+pub struct Nonces {
+    accounts: Arc<Mutex<HashMap<Address, Arc<tokio::sync::Mutex<AccountNonces>>>>>,
+    gap_timeout: Option<Duration>,
+}
+
+impl Nonces {
+    /// Счётчики процесса, через них работают все провайдеры `Deployer`
+    pub fn global() -> Self {
+        GLOBAL.clone()
+    }
+
+    /// Время ожидания nonce, который не появился в пуле. По умолчанию [`NONCE_GAP_TIMEOUT`]
+    pub fn gap_timeout(mut self, timeout: Duration) -> Self {
+        self.gap_timeout = Some(timeout);
+        self
+    }
+
+    fn account(&self, address: Address) -> Arc<tokio::sync::Mutex<AccountNonces>> {
+        self.accounts
+            .lock()
+            .unwrap()
+            .entry(address)
+            .or_default()
+            .clone()
+    }
+
+    /// Следующий nonce аккаунта
+    pub async fn next<P: Provider<N>, N: Network>(
+        &self,
+        provider: &P,
+        address: Address,
+    ) -> TransportResult<u64> {
+        let account = self.account(address);
+        let mut account = account.lock().await;
+        let known = provider.get_transaction_count(address).pending().await?;
+        let mined = provider.get_transaction_count(address).latest().await?;
+        if account.mined.is_none_or(|(count, _)| count != mined) {
+            account.mined = Some((mined, Instant::now()));
+        }
+
+        // Nonce ниже `mined` уже в блоке, ниже `known` - в пуле узла
+        account.issued = account.issued.split_off(&mined);
+        for issued in account.issued.range_mut(..known).map(|(_, v)| v) {
+            issued.seen = true;
+        }
+        if account.next < known {
+            account.next = known;
+        }
+
+        let timeout = self.gap_timeout.unwrap_or(NONCE_GAP_TIMEOUT);
+        let stalled = account
+            .mined
+            .is_some_and(|(_, changed_at)| changed_at.elapsed() >= timeout);
+        let lost = account
+            .issued
+            .get(&known)
+            .is_some_and(|issued| issued.seen || (stalled && issued.at.elapsed() >= timeout));
+        let nonce = if lost {
+            warn!("{address}: транзакция с nonce {known} потеряна, nonce выдаётся повторно");
+            known
+        } else {
+            account.next += 1;
+            account.next - 1
+        };
+        account.issued.insert(
+            nonce,
+            Issued {
+                at: Instant::now(),
+                seen: false,
+            },
+        );
+        debug!("{address}: nonce {nonce}");
+        Ok(nonce)
+    }
+
+    /// Забыть выданные nonce аккаунта. Следующий nonce будет взят у узла
+    pub async fn reset(&self, address: Address) {
+        let account = self.account(address);
+        *account.lock().await = AccountNonces::default();
+    }
+}
+
+/// Заполнение nonce из [`Nonces`] после оценки газа
+#[derive(Debug, Clone, Default)]
+pub struct NonceManagerFiller {
+    nonces: Nonces,
+}
+
+impl NonceManagerFiller {
+    pub fn new(nonces: Nonces) -> Self {
+        NonceManagerFiller { nonces }
+    }
+}
+
+impl<N: Network> TxFiller<N> for NonceManagerFiller {
+    type Fillable = u64;
+
+    fn status(&self, tx: &N::TransactionRequest) -> FillerControlFlow {
+        if tx.nonce().is_some() {
+            return FillerControlFlow::Finished;
+        }
+        if tx.from().is_none() {
+            return FillerControlFlow::missing("NonceManagerFiller", vec!["from"]);
+        }
+        if tx.gas_limit().is_none() {
+            return FillerControlFlow::missing("NonceManagerFiller", vec!["gas_limit"]);
+        }
+        FillerControlFlow::Ready
+    }
+
+    fn fill_sync(&self, _tx: &mut SendableTx<N>) {}
+
+    async fn prepare<P: Provider<N>>(
+        &self,
+        provider: &P,
+        tx: &N::TransactionRequest,
+    ) -> TransportResult<Self::Fillable> {
+        let from = tx.from().expect("checked by status()");
+        self.nonces.next(provider, from).await
+    }
+
+    async fn fill(
+        &self,
+        nonce: Self::Fillable,
+        mut tx: SendableTx<N>,
+    ) -> TransportResult<SendableTx<N>> {
+        if let Some(builder) = tx.as_mut_builder() {
+            builder.set_nonce(nonce);
+        }
+        Ok(tx)
+    }
+}
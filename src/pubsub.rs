@@ -0,0 +1,163 @@
+//! Поток событий через WebSocket
+//!
+//! Подписка `eth_subscribe("logs")` теряет события, опубликованные, пока соединение
+//! восстанавливается, а alloy переподключается и переподписывается незаметно для подписчика.
+//! Поэтому подписка на новые блоки (`newHeads`) служит только сигналом: события читаются
+//! `eth_getLogs` от последнего прочитанного блока до нового, и пропуск после переподключения
+//! закрывается следующим диапазоном. Если alloy не смог восстановить соединение, оно
+//! открывается заново с нарастающей задержкой.
+
+use std::time::Duration;
+
+use alloy::providers::{Provider, ProviderBuilder, WsConnect};
+use eyre::{Context, ContextCompat, Result};
+use futures_util::{Stream, StreamExt, stream};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::{
+    config::{self, Profile},
+    events::{Backfill, EventFilter, EventLog},
+};
+
+/// Задержка перед первой попыткой переподключения
+pub const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// Максимальная задержка между попытками переподключения
+pub const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+/// Попытки переподключения внутри alloy до открытия нового соединения
+const WS_RETRIES: u32 = 3;
+/// Размер очереди событий, ещё не прочитанных подписчиком
+const BUFFER: usize = 256;
+
+/// Подключение к узлу сети профиля `profile`
+async fn connect(url: &str, profile: &Profile) -> Result<impl Provider + use<>> {
+    let provider = ProviderBuilder::new()
+        .disable_recommended_fillers()
+        .connect_ws(WsConnect::new(url).with_max_retries(WS_RETRIES))
+        .await
+        .with_context(|| format!("Не удалось подключиться к {url}"))?;
+    config::check_chain_id(&provider, profile)
+        .await
+        .with_context(|| format!("Узел {url} не подходит"))?;
+    Ok(provider)
+}
+
+/// Поток событий с текущей вершины сети через WebSocket `ws_url` профиля.
+/// Ошибка возвращается, только если первое подключение не удалось
+pub async fn subscribe_ws(
+    profile: &Profile,
+    filter: &EventFilter,
+) -> Result<impl Stream<Item = EventLog> + Send + 'static> {
+    let url = profile
+        .ws_url
+        .as_deref()
+        .with_context(|| format!("В профиле {} не задан ws_url", profile.name))?;
+    let provider = connect(url, profile).await?;
+    let next_block = provider
+        .get_block_number()
+        .await
+        .context("Не удалось получить номер блока")?
+        + 1;
+    info!("Подписка на события через {url} с блока {next_block}");
+
+    let (sender, receiver) = mpsc::channel(BUFFER);
+    let (url, profile) = (url.to_string(), profile.clone());
+    let filter = filter.clone();
+    tokio::spawn(async move {
+        let mut next_block = next_block;
+        let mut delay = RECONNECT_DELAY;
+        let mut provider = Some(provider);
+        loop {
+            let current = match provider.take() {
+                Some(provider) => provider,
+                None => match connect(&url, &profile).await {
+                    Ok(provider) => provider,
+                    Err(err) => {
+                        warn!("{err:#}. Повтор через {delay:?}");
+                        tokio::time::sleep(delay).await;
+                        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                        continue;
+                    }
+                },
+            };
+
+            let started_at = next_block;
+            match follow(&current, &filter, &mut next_block, &sender).await {
+                Ok(()) => return,
+                Err(err) => warn!("Подписка через {url} прервана: {err:#}"),
+            }
+            if next_block > started_at {
+                delay = RECONNECT_DELAY;
+            }
+            if sender.is_closed() {
+                return;
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    });
+
+    Ok(stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|event| (event, receiver))
+    }))
+}
+
+/// Чтение событий по сигналам о новых блоках. `Ok` - подписчик закрыл поток
+async fn follow<P: Provider>(
+    provider: &P,
+    filter: &EventFilter,
+    next_block: &mut u64,
+    sender: &mpsc::Sender<EventLog>,
+) -> Result<()> {
+    let mut heads = provider
+        .subscribe_blocks()
+        .await
+        .context("Не удалось подписаться на новые блоки")?
+        .into_stream();
+
+    // Блоки, вышедшие до подписки
+    let head = provider
+        .get_block_number()
+        .await
+        .context("Не удалось получить номер блока")?;
+    if !send_range(provider, filter, next_block, head, sender).await? {
+        return Ok(());
+    }
+
+    loop {
+        tokio::select! {
+            _ = sender.closed() => return Ok(()),
+            header = heads.next() => {
+                let header = header.context("Подписка на новые блоки закрыта")?;
+                if !send_range(provider, filter, next_block, header.number, sender).await? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Отправка событий блоков `next_block..=head`. `false` - подписчик закрыл поток
+async fn send_range<P: Provider>(
+    provider: &P,
+    filter: &EventFilter,
+    next_block: &mut u64,
+    head: u64,
+    sender: &mpsc::Sender<EventLog>,
+) -> Result<bool> {
+    if head < *next_block {
+        return Ok(true);
+    }
+
+    let mut backfill = Backfill::new(filter.clone(), *next_block, head);
+    while let Some(chunk) = backfill.next_chunk(provider).await? {
+        for event in chunk {
+            if sender.send(event).await.is_err() {
+                return Ok(false);
+            }
+        }
+        *next_block = backfill.next_block();
+    }
+    debug!("События прочитаны до блока {head}");
+    Ok(true)
+}
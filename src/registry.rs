@@ -0,0 +1,133 @@
+use std::{fs, path::Path};
+
+use alloy::{
+    eips::BlockNumberOrTag,
+    primitives::{Address, B256, Bytes, keccak256},
+    providers::Provider,
+};
+use eyre::{Context, ContextCompat, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::config;
+
+/// Защита от одновременного развёртывания одного контракта из нескольких задач
+pub static DEPLOY_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Ключ развёртывания: сеть, контракт и версия его байткода
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeploymentKey {
+    pub chain_id: u64,
+    pub genesis_hash: B256,
+    pub contract: String,
+    /// keccak256 от байткода создания контракта
+    pub bytecode_hash: B256,
+}
+
+/// Запись о развёрнутом контракте
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Deployment {
+    #[serde(flatten)]
+    pub key: DeploymentKey,
+    pub address: Address,
+    /// keccak256 от кода контракта в сети (`eth_getCode`)
+    pub code_hash: B256,
+}
+
+/// Реестр развёрнутых контрактов в файле проекта
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Registry {
+    pub deployments: Vec<Deployment>,
+}
+
+impl DeploymentKey {
+    /// Ключ для контракта в сети, к которой подключён `provider`
+    pub async fn new<P: Provider>(provider: &P, contract: &str, bytecode: &Bytes) -> Result<Self> {
+        let chain_id = provider
+            .get_chain_id()
+            .await
+            .context("Не удалось получить chain id")?;
+        let genesis = provider
+            .get_block_by_number(BlockNumberOrTag::Number(0))
+            .await
+            .context("Не удалось получить genesis блок")?
+            .context("Genesis блок не найден")?;
+
+        Ok(DeploymentKey {
+            chain_id,
+            genesis_hash: genesis.header.hash,
+            contract: contract.to_string(),
+            bytecode_hash: keccak256(bytecode),
+        })
+    }
+}
+
+impl Registry {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Registry::default());
+        }
+        let content = fs::read_to_string(path).context("Не удалось прочитать реестр контрактов")?;
+        serde_json::from_str(&content).context("Ошибка в файле реестра контрактов")
+    }
+
+    /// Запись через временный файл, чтобы не оставить реестр недописанным
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content =
+            serde_json::to_string_pretty(self).context("Неудалось преобразовать реестр в JSON")?;
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, content).context("Ошибка при записи реестра контрактов")?;
+        fs::rename(&tmp_path, path).context("Ошибка при записи реестра контрактов")
+    }
+
+    pub fn get(&self, key: &DeploymentKey) -> Option<&Deployment> {
+        self.deployments.iter().find(|v| &v.key == key)
+    }
+
+    /// Добавление записи с заменой старой по тому же ключу
+    pub fn insert(&mut self, deployment: Deployment) {
+        self.deployments.retain(|v| v.key != deployment.key);
+        self.deployments.push(deployment);
+    }
+}
+
+/// Адрес контракта из реестра, если код по этому адресу не изменился
+pub async fn lookup<P: Provider>(provider: &P, key: &DeploymentKey) -> Result<Option<Address>> {
+    let registry = Registry::load(&config::profile()?.registry)?;
+    let Some(deployment) = registry.get(key) else {
+        return Ok(None);
+    };
+
+    let code = provider
+        .get_code_at(deployment.address)
+        .await
+        .context("Не удалось получить код контракта")?;
+    if code.is_empty() || keccak256(&code) != deployment.code_hash {
+        warn!(
+            "Контракт {} по адресу {} не найден в сети. Требуется новое развёртывание",
+            key.contract, deployment.address
+        );
+        return Ok(None);
+    }
+
+    debug!("{} из реестра: {}", key.contract, deployment.address);
+    Ok(Some(deployment.address))
+}
+
+/// Запись развёрнутого контракта в реестр
+pub async fn record<P: Provider>(provider: &P, key: DeploymentKey, address: Address) -> Result<()> {
+    let code = provider
+        .get_code_at(address)
+        .await
+        .context("Не удалось получить код контракта")?;
+
+    let path = &config::profile()?.registry;
+    let mut registry = Registry::load(path)?;
+    registry.insert(Deployment {
+        key,
+        address,
+        code_hash: keccak256(&code),
+    });
+    registry.save(path)
+}
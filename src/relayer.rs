@@ -0,0 +1,441 @@
+//! Ретранслятор депозитов l1 => l2
+//!
+//! Получает события `EventDeposit` и `EventDepositRC20` моста из [`EventBus`], куда их публикует
+//! наблюдатель [`Watcher`], и зачисляет средства получателю на l2 через [`L2Sink`]. Наблюдатель
+//! выдаёт событие только после нужного количества подтверждений, а депозит из брошенного при
+//! реорганизации блока отменяется. Прогресс (следующий необработанный блок) сохраняется в файл
+//! по [`WatchUpdate::Processed`], поэтому после перезапуска наблюдение нужно начинать с
+//! [`RelayerState::next_block`].
+//!
+//! Состояние наблюдателя тоже сохраняется в файл, иначе после перезапуска он не знает, какие
+//! события уже выданы, и не отменит депозит из блока, брошенного позже. Перед запуском
+//! [`Relayer::resume_watcher`] согласует это состояние с ретранслятором.
+//!
+//! [`EventBus`]: crate::bus::EventBus
+//! [`Watcher`]: crate::watcher::Watcher
+//!
+//! Каждое зачисление имеет уникальный [`DepositId`] (хэш транзакции и номер лога в блоке).
+//! Если процесс упал после зачисления, но до сохранения прогресса, блок будет обработан повторно,
+//! поэтому [`L2Sink`] обязан игнорировать уже зачисленные идентификаторы.
+
+use std::{
+    collections::HashSet,
+    fs::{self, OpenOptions},
+    future::Future,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use alloy::primitives::{Address, B256, U256};
+use eyre::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::{
+    bus::Subscriber,
+    events::{BridgeEvent, EventFilter, EventKind, EventLog, LogMeta},
+    tasks::{MAX_RESTART_DELAY, RESTART_DELAY},
+    watcher::{REORG_DEPTH, WatchUpdate, WatcherState},
+};
+
+/// Уникальный идентификатор депозита
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DepositId {
+    pub tx_hash: B256,
+    pub log_index: u64,
+}
+
+impl DepositId {
+    pub fn from_meta(meta: &LogMeta) -> Self {
+        DepositId {
+            tx_hash: meta.tx_hash,
+            log_index: meta.log_index,
+        }
+    }
+}
+
+/// Депозит, который нужно зачислить на l2
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Deposit {
+    /// ETH, сумма в единицах l2 (8 знаков)
+    Eth {
+        from: Address,
+        to: Address,
+        value: u64,
+    },
+    /// ERC20, сумма в единицах l2
+    Erc20 {
+        token: Address,
+        from: Address,
+        to: Address,
+        value: U256,
+    },
+}
+
+/// Зачисление на l2
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Credit {
+    pub id: DepositId,
+    pub block_number: u64,
+    pub block_hash: B256,
+    pub deposit: Deposit,
+}
+
+impl Credit {
+    /// Зачисление для события депозита. Для прочих событий возвращает `None`
+    pub fn from_event(event: &EventLog) -> Option<Self> {
+        let deposit = match &event.event {
+            BridgeEvent::Deposit(data) => Deposit::Eth {
+                from: data.from,
+                to: data.to,
+                value: data.value,
+            },
+            BridgeEvent::DepositErc20(data) => Deposit::Erc20 {
+                token: data.token_address,
+                from: data.from,
+                to: data.to,
+                value: data.value,
+            },
+            _ => return None,
+        };
+
+        Some(Credit {
+            id: DepositId::from_meta(&event.meta),
+            block_number: event.meta.block_number,
+            block_hash: event.meta.block_hash,
+            deposit,
+        })
+    }
+}
+
+/// Получатель зачислений на стороне l2
+pub trait L2Sink {
+    /// Зачисление депозита. Повторный вызов с тем же `credit.id` не должен зачислять средства
+    /// второй раз. Возвращает `false`, если депозит уже был зачислен
+    fn credit(&mut self, credit: &Credit) -> impl Future<Output = Result<bool>> + Send;
+
+    /// Отмена зачисления депозита из брошенного блока. Возвращает `false`, если депозит
+    /// не был зачислен
+    fn retract(&mut self, id: &DepositId) -> impl Future<Output = Result<bool>> + Send;
+
+    /// Действующие зачисления из блоков начиная с `block_number`
+    fn credits_since(&self, block_number: u64) -> impl Future<Output = Result<Vec<Credit>>> + Send;
+}
+
+/// Зачисления в памяти, для тестов
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    pub credits: Vec<Credit>,
+    ids: HashSet<DepositId>,
+}
+
+impl L2Sink for MemorySink {
+    async fn credit(&mut self, credit: &Credit) -> Result<bool> {
+        if !self.ids.insert(credit.id) {
+            return Ok(false);
+        }
+        self.credits.push(credit.clone());
+        Ok(true)
+    }
+
+    async fn retract(&mut self, id: &DepositId) -> Result<bool> {
+        if !self.ids.remove(id) {
+            return Ok(false);
+        }
+        self.credits.retain(|v| v.id != *id);
+        Ok(true)
+    }
+
+    async fn credits_since(&self, block_number: u64) -> Result<Vec<Credit>> {
+        Ok(self
+            .credits
+            .iter()
+            .filter(|v| v.block_number >= block_number)
+            .cloned()
+            .collect())
+    }
+}
+
+/// Строка файла зачислений
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Credit(Credit),
+    Retracted { retracted: DepositId },
+}
+
+/// Зачисления в файле, по одному JSON на строку. Отмена зачисления дописывается
+/// отдельной строкой
+#[derive(Debug)]
+pub struct FileSink {
+    path: PathBuf,
+    ids: HashSet<DepositId>,
+}
+
+impl FileSink {
+    /// Недописанная последняя строка, оставшаяся после падения во время записи, отбрасывается
+    pub fn open(path: &Path) -> Result<Self> {
+        let (credits, complete) = Self::read_complete(path)?;
+        if path.exists() && fs::metadata(path)?.len() > complete {
+            warn!(
+                "Отброшена недописанная строка в конце файла зачислений {}",
+                path.display()
+            );
+            OpenOptions::new()
+                .write(true)
+                .open(path)
+                .and_then(|file| file.set_len(complete))
+                .context("Не удалось исправить файл зачислений")?;
+        }
+
+        Ok(FileSink {
+            path: path.to_path_buf(),
+            ids: credits.into_iter().map(|v| v.id).collect(),
+        })
+    }
+
+    /// Действующие зачисления из файла. Недописанная последняя строка пропускается
+    pub fn read(path: &Path) -> Result<Vec<Credit>> {
+        Ok(Self::read_complete(path)?.0)
+    }
+
+    /// Зачисления и длина файла без недописанной последней строки
+    fn read_complete(path: &Path) -> Result<(Vec<Credit>, u64)> {
+        if !path.exists() {
+            return Ok((Vec::new(), 0));
+        }
+        let content = fs::read_to_string(path).context("Не удалось прочитать файл зачислений")?;
+        let complete = match content.rfind('\n') {
+            Some(end) => &content[..=end],
+            None => "",
+        };
+
+        let mut credits = Vec::new();
+        for line in complete.lines().filter(|v| !v.trim().is_empty()) {
+            match serde_json::from_str(line).context("Ошибка в файле зачислений")?
+            {
+                Entry::Credit(credit) => credits.push(credit),
+                Entry::Retracted { retracted } => credits.retain(|v| v.id != retracted),
+            }
+        }
+        Ok((credits, complete.len() as u64))
+    }
+
+    fn append(&self, entry: &Entry) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context("Не удалось открыть файл зачислений")?;
+        let line = serde_json::to_string(entry).context("Неудалось преобразовать в JSON")?;
+        writeln!(file, "{line}").context("Ошибка при записи зачисления")?;
+        file.sync_data().context("Ошибка при записи зачисления")
+    }
+}
+
+impl L2Sink for FileSink {
+    async fn credit(&mut self, credit: &Credit) -> Result<bool> {
+        if self.ids.contains(&credit.id) {
+            return Ok(false);
+        }
+        self.append(&Entry::Credit(credit.clone()))?;
+        self.ids.insert(credit.id);
+        Ok(true)
+    }
+
+    async fn retract(&mut self, id: &DepositId) -> Result<bool> {
+        if !self.ids.contains(id) {
+            return Ok(false);
+        }
+        self.append(&Entry::Retracted { retracted: *id })?;
+        self.ids.remove(id);
+        Ok(true)
+    }
+
+    async fn credits_since(&self, block_number: u64) -> Result<Vec<Credit>> {
+        let mut credits = Self::read(&self.path)?;
+        credits.retain(|v| v.block_number >= block_number);
+        Ok(credits)
+    }
+}
+
+/// Сохраняемый прогресс ретранслятора
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelayerState {
+    /// Первый ещё не обработанный блок
+    pub next_block: u64,
+}
+
+impl RelayerState {
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path).context("Не удалось прочитать состояние")?;
+        serde_json::from_str(&content)
+            .map(Some)
+            .context("Ошибка в файле состояния")
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string(self).context("Неудалось преобразовать в JSON")?;
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, content).context("Ошибка при записи состояния")?;
+        fs::rename(&tmp_path, path).context("Ошибка при записи состояния")
+    }
+}
+
+/// Ретранслятор депозитов
+pub struct Relayer<S> {
+    bridge: Address,
+    sink: S,
+    state_path: PathBuf,
+    state: RelayerState,
+}
+
+impl<S: L2Sink> Relayer<S> {
+    /// Состояние читается из `state_path`. Если файла нет, обработка начинается с `start_block`
+    pub fn new(bridge: Address, sink: S, state_path: &Path, start_block: u64) -> Result<Self> {
+        let state = RelayerState::load(state_path)?.unwrap_or(RelayerState {
+            next_block: start_block,
+        });
+        info!(
+            "Ретранслятор {bridge} начинает с блока {}",
+            state.next_block
+        );
+
+        Ok(Relayer {
+            bridge,
+            sink,
+            state_path: state_path.to_path_buf(),
+            state,
+        })
+    }
+
+    pub fn state(&self) -> RelayerState {
+        self.state
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// Согласование сохранённого в `path` состояния наблюдателя перед запуском. Наблюдатель
+    /// сохраняет прогресс после публикации в шину, а ретранслятор после обработки, поэтому
+    /// наблюдение продолжается с меньшего из блоков. Выданные события окна [`REORG_DEPTH`]
+    /// берутся из зачислений: необработанные события выдаются снова, а зачисленные будут
+    /// отменены при реорганизации
+    pub async fn resume_watcher(&self, path: &Path) -> Result<()> {
+        let Some(mut state) = WatcherState::load(path)? else {
+            return Ok(());
+        };
+        state.next_block = state.next_block.min(self.state.next_block);
+        let window_start = state
+            .checkpoint
+            .map_or(state.next_block, |v| v.block_number + 1)
+            .saturating_sub(REORG_DEPTH);
+        state.recent = self
+            .sink
+            .credits_since(window_start)
+            .await?
+            .into_iter()
+            .map(|credit| LogMeta {
+                address: self.bridge,
+                block_number: credit.block_number,
+                block_hash: credit.block_hash,
+                tx_hash: credit.id.tx_hash,
+                log_index: credit.id.log_index,
+            })
+            .collect();
+        debug!(
+            "Наблюдатель продолжит с блока {}, выданных событий: {}",
+            state.next_block,
+            state.recent.len()
+        );
+        state.save(path)
+    }
+
+    /// Фильтр событий депозита моста
+    pub fn filter(bridge: Address) -> EventFilter {
+        EventFilter::new()
+            .address(bridge)
+            .kinds([EventKind::Deposit, EventKind::DepositErc20])
+    }
+
+    /// Зачисление подтверждённого депозита, отмена депозита из брошенного блока или
+    /// сохранение прогресса. Возвращает `true`, если состояние на l2 изменилось
+    pub async fn handle(&mut self, update: &WatchUpdate) -> Result<bool> {
+        match update {
+            WatchUpdate::Confirmed(event) if event.meta.address == self.bridge => {
+                let Some(credit) = Credit::from_event(event) else {
+                    return Ok(false);
+                };
+                let credited = self.sink.credit(&credit).await?;
+                if credited {
+                    info!("Зачисление {:?}", credit);
+                } else {
+                    debug!("Депозит {:?} уже зачислен", credit.id);
+                }
+                Ok(credited)
+            }
+            WatchUpdate::Retracted(meta) if meta.address == self.bridge => {
+                let id = DepositId::from_meta(meta);
+                let retracted = self.sink.retract(&id).await?;
+                if retracted {
+                    warn!(
+                        "Зачисление {id:?} отменено: блок {} брошен",
+                        meta.block_number
+                    );
+                }
+                Ok(retracted)
+            }
+            WatchUpdate::Processed(block_number) => {
+                self.state.next_block = block_number + 1;
+                self.state.save(&self.state_path)?;
+                Ok(false)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Обработка изменений из шины до закрытия шины или завершения `shutdown`. Ошибка
+    /// [`L2Sink`] не останавливает ретранслятор: изменение обрабатывается повторно с растущей
+    /// задержкой. Возвращает количество изменений на l2
+    pub async fn run(
+        &mut self,
+        updates: &mut Subscriber<WatchUpdate>,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<usize> {
+        tokio::pin!(shutdown);
+        let mut changed = 0;
+        loop {
+            let update = tokio::select! {
+                _ = &mut shutdown => return Ok(changed),
+                update = updates.recv() => update,
+            };
+            let Some(update) = update else {
+                debug!("Шина событий закрыта");
+                return Ok(changed);
+            };
+
+            let mut backoff = RESTART_DELAY;
+            loop {
+                match self.handle(&update).await {
+                    Ok(v) => {
+                        changed += v as usize;
+                        break;
+                    }
+                    Err(err) => {
+                        warn!("Ошибка ретранслятора: {err:#}. Повтор через {backoff:?}");
+                        tokio::select! {
+                            _ = &mut shutdown => return Ok(changed),
+                            _ = tokio::time::sleep(backoff) => (),
+                        }
+                        backoff = (backoff * 2).min(MAX_RESTART_DELAY);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Чтение хранилища `Bridge` напрямую
+//!
+//! Слоты вычисляются по раскладке `Bridge.sol`: `owner` (0), `BridgeTokens` (1),
+//! `WithdrawRequest` (2), `WithdrawRequestErc20` (3); константы слотов не занимают.
+//! Ячейка `mapping` с ключом `key` в слоте `slot` - `keccak256(key . slot)`, вложенный
+//! `mapping` хэширует второй ключ с ячейкой первого.
+//!
+//! `BridgeTokenInfo` занимает 4 слота: `turn`, `name`, `symbol` и упакованные в один слот
+//! `base_decimals` и `decimals`. Строка до 31 байта хранится в своём слоте вместе с длиной,
+//! длинная - в слотах начиная с `keccak256(slot)`.
+//!
+//! [`BridgeStorage`] читает значения через `eth_getStorageAt` в любом блоке, поэтому не зависит
+//! от `msg.sender`, как `available_to_withdraw*`. Старые блоки доступны только на архивном узле.
+
+use alloy::{
+    eips::BlockId,
+    primitives::{Address, B256, U256, keccak256},
+    providers::Provider,
+};
+use eyre::{Context, ContextCompat, Result, ensure};
+
+use crate::contracts::Bridge::BridgeTokenInfo;
+
+/// Слот `owner`
+pub const OWNER_SLOT: u64 = 0;
+/// Слот `BridgeTokens`
+pub const BRIDGE_TOKENS_SLOT: u64 = 1;
+/// Слот `WithdrawRequest`
+pub const WITHDRAW_REQUEST_SLOT: u64 = 2;
+/// Слот `WithdrawRequestErc20`
+pub const WITHDRAW_REQUEST_ERC20_SLOT: u64 = 3;
+/// Предел длины строки, чтобы повреждённый слот не превращался в тысячи запросов
+const MAX_STRING_LEN: usize = 64 * 1024;
+
+fn slot(index: u64) -> B256 {
+    B256::from(U256::from(index))
+}
+
+/// Сдвиг слота: поле структуры или элемент строки
+fn offset(slot: B256, offset: u64) -> B256 {
+    B256::from(U256::from_be_bytes(slot.0).wrapping_add(U256::from(offset)))
+}
+
+/// Ячейка `mapping(address => ...)` с ключом `key` в слоте `slot`
+pub fn mapping_slot(key: Address, slot: B256) -> B256 {
+    let mut data = [0u8; 64];
+    data[12..32].copy_from_slice(key.as_slice());
+    data[32..].copy_from_slice(slot.as_slice());
+    keccak256(data)
+}
+
+/// Первый слот `BridgeTokens[token]`
+pub fn bridge_token_slot(token: Address) -> B256 {
+    mapping_slot(token, slot(BRIDGE_TOKENS_SLOT))
+}
+
+/// Ячейка `WithdrawRequest[user]`
+pub fn withdraw_request_slot(user: Address) -> B256 {
+    mapping_slot(user, slot(WITHDRAW_REQUEST_SLOT))
+}
+
+/// Ячейка `WithdrawRequestErc20[user][token]`
+pub fn withdraw_request_erc20_slot(user: Address, token: Address) -> B256 {
+    mapping_slot(token, mapping_slot(user, slot(WITHDRAW_REQUEST_ERC20_SLOT)))
+}
+
+/// Ячейка невыведенной суммы `user`. `token` - `None` для ETH
+pub fn pending_slot(user: Address, token: Option<Address>) -> B256 {
+    match token {
+        Some(token) => withdraw_request_erc20_slot(user, token),
+        None => withdraw_request_slot(user),
+    }
+}
+
+/// Хранилище моста в одном блоке
+#[derive(Debug, Clone)]
+pub struct BridgeStorage<P> {
+    provider: P,
+    bridge: Address,
+    block: BlockId,
+}
+
+impl<P: Provider> BridgeStorage<P> {
+    /// Хранилище в последнем блоке
+    pub fn new(provider: P, bridge: Address) -> Self {
+        BridgeStorage {
+            provider,
+            bridge,
+            block: BlockId::latest(),
+        }
+    }
+
+    /// Хранилище в блоке `block_number`
+    pub fn at(mut self, block_number: u64) -> Self {
+        self.block = BlockId::number(block_number);
+        self
+    }
+
+    /// Значение слота
+    pub async fn read(&self, slot: B256) -> Result<U256> {
+        self.provider
+            .get_storage_at(self.bridge, slot.into())
+            .block_id(self.block)
+            .await
+            .with_context(|| format!("Не удалось прочитать слот {slot} моста {}", self.bridge))
+    }
+
+    pub async fn owner(&self) -> Result<Address> {
+        let word = self.read(slot(OWNER_SLOT)).await?;
+        Ok(Address::from_word(word.into()))
+    }
+
+    /// `BridgeTokens[token]`. Для токена без моста все поля пустые, `turn` - `false`
+    pub async fn bridge_token(&self, token: Address) -> Result<BridgeTokenInfo> {
+        let base = bridge_token_slot(token);
+        let turn = self.read(base).await?.byte(0) != 0;
+        let name = self
+            .string(offset(base, 1))
+            .await
+            .context("Ошибка в BridgeTokenInfo.name")?;
+        let symbol = self
+            .string(offset(base, 2))
+            .await
+            .context("Ошибка в BridgeTokenInfo.symbol")?;
+        // Упакованы от младших байт: base_decimals, затем decimals
+        let decimals = self.read(offset(base, 3)).await?;
+        Ok(BridgeTokenInfo {
+            turn,
+            name,
+            symbol,
+            base_decimals: decimals.byte(0),
+            decimals: decimals.byte(1),
+        })
+    }
+
+    /// `WithdrawRequest[user]` в wei
+    pub async fn withdraw_request(&self, user: Address) -> Result<U256> {
+        self.read(withdraw_request_slot(user)).await
+    }
+
+    /// `WithdrawRequestErc20[user][token]` в точности токена
+    pub async fn withdraw_request_erc20(&self, user: Address, token: Address) -> Result<U256> {
+        self.read(withdraw_request_erc20_slot(user, token)).await
+    }
+
+    /// Невыведенная сумма `user` в точности l1. `token` - `None` для ETH
+    pub async fn pending(&self, user: Address, token: Option<Address>) -> Result<U256> {
+        self.read(pending_slot(user, token)).await
+    }
+
+    /// Строка `string` в слоте `slot`
+    pub async fn string(&self, slot: B256) -> Result<String> {
+        let word = self.read(slot).await?;
+        let bytes = if word.bit(0) {
+            // Длинная строка: в слоте 2 * длина + 1, данные начиная с keccak256(slot)
+            let len = usize::try_from((word - U256::ONE) >> 1)
+                .ok()
+                .filter(|v| *v <= MAX_STRING_LEN)
+                .with_context(|| format!("Слишком длинная строка в слоте {slot}"))?;
+            let data = keccak256(slot);
+            let mut bytes = Vec::with_capacity(len.next_multiple_of(32));
+            for index in 0..len.div_ceil(32) {
+                let word = self.read(offset(data, index as u64)).await?;
+                bytes.extend_from_slice(&word.to_be_bytes::<32>());
+            }
+            bytes.truncate(len);
+            bytes
+        } else {
+            // Короткая строка: данные в старших байтах, 2 * длина в младшем
+            let bytes = word.to_be_bytes::<32>();
+            let len = bytes[31] as usize / 2;
+            ensure!(len < 32, "Неверная длина строки в слоте {slot}");
+            bytes[..len].to_vec()
+        };
+        String::from_utf8(bytes).with_context(|| format!("Строка в слоте {slot} не UTF-8"))
+    }
+}
@@ -0,0 +1,308 @@
+//! Сопровождение транзакции до включения в блок
+//!
+//! Транзакция с низкой ценой газа или выброшенная из пула не попадёт в блок, и ожидание
+//! квитанции длится вечно. [`TxSupervisor`] следит за всеми отправленными вариантами транзакции и,
+//! если ни один не включён в блок за `timeout`, повторяет её с тем же nonce и ценой газа, поднятой
+//! на [`FEE_BUMP_PERCENT`]. Транзакцию можно отменить: на её nonce отправляется перевод 0 ETH
+//! самому себе.
+
+use std::time::{Duration, Instant};
+
+use alloy::{
+    consensus::{Transaction as _, TxEnvelope},
+    network::TransactionBuilder,
+    primitives::{Address, B256, U256},
+    providers::{Provider, WalletProvider as _},
+    rpc::types::{TransactionReceipt, TransactionRequest},
+};
+use eyre::{Context, Result, bail};
+use tracing::{debug, info, warn};
+
+use crate::contracts::WalletProvider;
+
+/// Время ожидания включения в блок до повышения цены газа по умолчанию
+pub const STUCK_TIMEOUT: Duration = Duration::from_secs(60);
+/// Повышение цены газа, %. Узлы принимают замену, если цена выросла хотя бы на 10%
+pub const FEE_BUMP_PERCENT: u128 = 15;
+/// Максимальное число повышений цены газа по умолчанию
+pub const MAX_BUMPS: usize = 5;
+
+/// Итог сопровождения транзакции
+#[derive(Debug, Clone)]
+pub enum TxResult {
+    /// Транзакция (один из её вариантов) включена в блок. Статус квитанции может быть неуспешным
+    Mined(TransactionReceipt),
+    /// Включён в блок перевод-отмена
+    Cancelled(TransactionReceipt),
+    /// Nonce занят транзакцией, отправленной в обход сопровождения
+    Replaced,
+    /// Цена поднималась `max_bumps` раз, транзакция всё ещё не в блоке
+    Stuck,
+}
+
+/// Отправленная транзакция и все её варианты с тем же nonce
+#[derive(Debug, Clone)]
+pub struct PendingTx {
+    request: TransactionRequest,
+    from: Address,
+    nonce: u64,
+    max_fee_per_gas: u128,
+    max_priority_fee_per_gas: u128,
+    /// Хэши вариантов в порядке отправки
+    hashes: Vec<B256>,
+    /// Хэши вариантов отмены
+    cancels: Vec<B256>,
+    bumps: usize,
+    sent_at: Instant,
+}
+
+impl PendingTx {
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    /// Хэш последнего отправленного варианта
+    pub fn tx_hash(&self) -> B256 {
+        *self.hashes.last().expect("at least one broadcast")
+    }
+
+    pub fn hashes(&self) -> &[B256] {
+        &self.hashes
+    }
+
+    pub fn max_fee_per_gas(&self) -> u128 {
+        self.max_fee_per_gas
+    }
+
+    pub fn max_priority_fee_per_gas(&self) -> u128 {
+        self.max_priority_fee_per_gas
+    }
+
+    /// Сколько раз поднималась цена газа
+    pub fn bumps(&self) -> usize {
+        self.bumps
+    }
+
+    pub fn is_cancelling(&self) -> bool {
+        !self.cancels.is_empty()
+    }
+
+    /// Запрос с заполненными nonce, газом и ценой: заполнители провайдера только подпишут его
+    fn fixed(&self, request: TransactionRequest) -> TransactionRequest {
+        request
+            .with_from(self.from)
+            .with_nonce(self.nonce)
+            .with_max_fee_per_gas(self.max_fee_per_gas)
+            .with_max_priority_fee_per_gas(self.max_priority_fee_per_gas)
+    }
+
+    /// Перевод 0 ETH самому себе на тот же nonce
+    fn cancel_request(&self) -> TransactionRequest {
+        self.fixed(
+            TransactionRequest::default()
+                .with_to(self.from)
+                .with_value(U256::ZERO)
+                .with_gas_limit(21_000)
+                .with_chain_id(self.request.chain_id.unwrap_or_default()),
+        )
+    }
+
+    /// Запрос следующего варианта
+    fn next_request(&self) -> TransactionRequest {
+        if self.is_cancelling() {
+            self.cancel_request()
+        } else {
+            self.fixed(self.request.clone())
+        }
+    }
+
+    fn push(&mut self, tx_hash: B256, cancel: bool) {
+        if cancel {
+            self.cancels.push(tx_hash);
+        }
+        self.hashes.push(tx_hash);
+        self.sent_at = Instant::now();
+    }
+
+    fn bump(&mut self) {
+        self.max_fee_per_gas = bumped(self.max_fee_per_gas);
+        self.max_priority_fee_per_gas = bumped(self.max_priority_fee_per_gas);
+        self.bumps += 1;
+    }
+}
+
+fn bumped(fee: u128) -> u128 {
+    (fee * (100 + FEE_BUMP_PERCENT)).div_ceil(100).max(fee + 1)
+}
+
+/// Отправка транзакций с повышением цены газа и отменой
+#[derive(Debug, Clone)]
+pub struct TxSupervisor {
+    provider: WalletProvider,
+    timeout: Duration,
+    poll_interval: Duration,
+    max_bumps: usize,
+}
+
+impl TxSupervisor {
+    pub fn new(provider: WalletProvider) -> Self {
+        TxSupervisor {
+            provider,
+            timeout: STUCK_TIMEOUT,
+            poll_interval: Duration::from_secs(1),
+            max_bumps: MAX_BUMPS,
+        }
+    }
+
+    /// Время ожидания до повышения цены газа
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    pub fn max_bumps(mut self, max_bumps: usize) -> Self {
+        self.max_bumps = max_bumps;
+        self
+    }
+
+    pub fn provider(&self) -> &WalletProvider {
+        &self.provider
+    }
+
+    /// Подписание и отправка. Ошибка заполнения (например, откат при оценке газа)
+    /// означает, что транзакция не отправлена
+    async fn broadcast(&self, request: TransactionRequest) -> Result<(TxEnvelope, B256)> {
+        let envelope = self
+            .provider
+            .fill(request)
+            .await?
+            .try_into_envelope()
+            .context("Транзакция не подписана")?;
+        let tx_hash = *self
+            .provider
+            .send_tx_envelope(envelope.clone())
+            .await?
+            .tx_hash();
+        Ok((envelope, tx_hash))
+    }
+
+    /// Повторная отправка с тем же nonce. Отказ узла не прерывает сопровождение:
+    /// например, предыдущий вариант мог уже попасть в блок
+    async fn rebroadcast(&self, request: TransactionRequest) -> Option<B256> {
+        match self.broadcast(request).await {
+            Ok((_, tx_hash)) => Some(tx_hash),
+            Err(err) => {
+                warn!("Повторная отправка отклонена: {err:#}");
+                None
+            }
+        }
+    }
+
+    /// Отправка транзакции EIP-1559
+    pub async fn send(&self, request: TransactionRequest) -> Result<PendingTx> {
+        let from = request
+            .from
+            .unwrap_or_else(|| self.provider.default_signer_address());
+        let (envelope, tx_hash) = self.broadcast(request.clone().with_from(from)).await?;
+        let Some(max_priority_fee_per_gas) = envelope.max_priority_fee_per_gas() else {
+            bail!("Сопровождаются только транзакции EIP-1559");
+        };
+        debug!("Отправлена {tx_hash}, nonce {}", envelope.nonce());
+
+        Ok(PendingTx {
+            request: request
+                .with_from(from)
+                .with_gas_limit(envelope.gas_limit())
+                .with_chain_id(envelope.chain_id().unwrap_or_default()),
+            from,
+            nonce: envelope.nonce(),
+            max_fee_per_gas: envelope.max_fee_per_gas(),
+            max_priority_fee_per_gas,
+            hashes: vec![tx_hash],
+            cancels: Vec::new(),
+            bumps: 0,
+            sent_at: Instant::now(),
+        })
+    }
+
+    /// Отмена: перевод 0 ETH самому себе с тем же nonce и повышенной ценой газа.
+    /// Итог по-прежнему ожидается через [`TxSupervisor::wait`]
+    pub async fn cancel(&self, pending: &mut PendingTx) -> Result<B256> {
+        pending.bump();
+        let (_, tx_hash) = self.broadcast(pending.cancel_request()).await?;
+        info!("Отмена nonce {}: {tx_hash}", pending.nonce);
+        pending.push(tx_hash, true);
+        Ok(tx_hash)
+    }
+
+    /// Квитанция одного из вариантов
+    async fn receipt(&self, pending: &PendingTx) -> Result<Option<TxResult>> {
+        for &tx_hash in &pending.hashes {
+            let receipt = self
+                .provider
+                .get_transaction_receipt(tx_hash)
+                .await
+                .context("Не удалось получить квитанцию")?;
+            if let Some(receipt) = receipt {
+                return Ok(Some(if pending.cancels.contains(&tx_hash) {
+                    TxResult::Cancelled(receipt)
+                } else {
+                    TxResult::Mined(receipt)
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Итог, если nonce уже использован
+    async fn check(&self, pending: &PendingTx) -> Result<Option<TxResult>> {
+        if let Some(result) = self.receipt(pending).await? {
+            return Ok(Some(result));
+        }
+
+        let mined = self
+            .provider
+            .get_transaction_count(pending.from)
+            .latest()
+            .await
+            .context("Не удалось получить nonce")?;
+        if mined <= pending.nonce {
+            return Ok(None);
+        }
+        // Вариант мог попасть в блок между запросами квитанции и nonce
+        Ok(Some(
+            self.receipt(pending).await?.unwrap_or(TxResult::Replaced),
+        ))
+    }
+
+    /// Ожидание итога. Пока транзакция не в блоке, цена газа поднимается каждые `timeout`
+    pub async fn wait(&self, pending: &mut PendingTx) -> Result<TxResult> {
+        loop {
+            if let Some(result) = self.check(pending).await? {
+                return Ok(result);
+            }
+
+            if pending.sent_at.elapsed() >= self.timeout {
+                if pending.bumps >= self.max_bumps {
+                    return Ok(TxResult::Stuck);
+                }
+                pending.bump();
+                info!(
+                    "Nonce {} не в блоке {:?}, цена газа поднята до {}",
+                    pending.nonce, self.timeout, pending.max_fee_per_gas
+                );
+                match self.rebroadcast(pending.next_request()).await {
+                    Some(tx_hash) => pending.push(tx_hash, pending.is_cancelling()),
+                    None => pending.sent_at = Instant::now(),
+                }
+            }
+
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+}
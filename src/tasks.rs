@@ -0,0 +1,171 @@
+//! Фоновые задачи наблюдения с перезапуском
+//!
+//! [`TaskSupervisor`] владеет задачами и перезапускает задачу, завершившуюся ошибкой или паникой,
+//! с экспоненциально растущей задержкой. Задача получает [`CancellationToken`] и при отмене
+//! завершается сама: отправляет уже полученные события и сохраняет прогресс, а не обрывается
+//! посередине, как при `JoinHandle::abort`.
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use eyre::{Result, eyre};
+use tokio::task::JoinHandle;
+pub use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+/// Задержка перед первым перезапуском
+pub const RESTART_DELAY: Duration = Duration::from_secs(1);
+/// Максимальная задержка между перезапусками
+pub const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+/// Состояние задачи
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskStatus {
+    Running,
+    /// Задача завершилась ошибкой и ждёт перезапуска
+    Restarting {
+        restarts: u32,
+        error: String,
+    },
+    /// Задача завершилась сама или остановлена отменой
+    Stopped,
+}
+
+/// Владелец фоновых задач
+#[derive(Debug)]
+pub struct TaskSupervisor {
+    token: CancellationToken,
+    tasks: Vec<JoinHandle<()>>,
+    health: Arc<Mutex<BTreeMap<String, TaskStatus>>>,
+    restart_delay: Duration,
+    max_restart_delay: Duration,
+}
+
+impl Default for TaskSupervisor {
+    fn default() -> Self {
+        TaskSupervisor::new(CancellationToken::new())
+    }
+}
+
+impl TaskSupervisor {
+    /// Задачи останавливаются при отмене `token`
+    pub fn new(token: CancellationToken) -> Self {
+        TaskSupervisor {
+            token,
+            tasks: Vec::new(),
+            health: Default::default(),
+            restart_delay: RESTART_DELAY,
+            max_restart_delay: MAX_RESTART_DELAY,
+        }
+    }
+
+    /// Задержка перед первым перезапуском и её предел. Задержка удваивается после каждой ошибки
+    /// и сбрасывается, если задача проработала дольше предела
+    pub fn restart_delay(mut self, delay: Duration, max_delay: Duration) -> Self {
+        self.restart_delay = delay;
+        self.max_restart_delay = max_delay.max(delay);
+        self
+    }
+
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    /// Запуск задачи `name`. `task` вызывается заново при каждом перезапуске.
+    /// `Ok` означает штатное завершение, после него задача не перезапускается.
+    /// К повторяющемуся имени добавляется номер задачи
+    pub fn spawn<F, Fut>(&mut self, name: impl Into<String>, mut task: F)
+    where
+        F: FnMut(CancellationToken) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let mut name = name.into();
+        if self.health.lock().unwrap().contains_key(&name) {
+            name = format!("{name} #{}", self.tasks.len());
+        }
+        let token = self.token.clone();
+        let health = self.health.clone();
+        let (initial_delay, max_delay) = (self.restart_delay, self.max_restart_delay);
+        set_status(&health, &name, TaskStatus::Running);
+
+        self.tasks.push(tokio::spawn(async move {
+            let mut delay = initial_delay;
+            let mut restarts = 0;
+            loop {
+                let started_at = Instant::now();
+                // Отдельная задача, чтобы паника не остановила перезапуски
+                let error = match tokio::spawn(task(token.clone())).await {
+                    Ok(Ok(())) => break,
+                    Ok(Err(err)) => err,
+                    Err(err) if err.is_panic() => eyre!("паника в задаче"),
+                    Err(err) => eyre!(err),
+                };
+                if token.is_cancelled() {
+                    warn!("{name}: {error:#}");
+                    break;
+                }
+
+                if started_at.elapsed() > max_delay {
+                    delay = initial_delay;
+                }
+                restarts += 1;
+                warn!("{name}: {error:#}. Перезапуск №{restarts} через {delay:?}");
+                set_status(
+                    &health,
+                    &name,
+                    TaskStatus::Restarting {
+                        restarts,
+                        error: format!("{error:#}"),
+                    },
+                );
+                tokio::select! {
+                    _ = token.cancelled() => break,
+                    _ = tokio::time::sleep(delay) => (),
+                }
+                delay = (delay * 2).min(max_delay);
+                set_status(&health, &name, TaskStatus::Running);
+            }
+            debug!("{name}: остановлена");
+            set_status(&health, &name, TaskStatus::Stopped);
+        }));
+    }
+
+    /// Состояние всех задач по именам
+    pub fn health(&self) -> BTreeMap<String, TaskStatus> {
+        self.health.lock().unwrap().clone()
+    }
+
+    /// Ни одна задача не ждёт перезапуска
+    pub fn is_healthy(&self) -> bool {
+        self.health
+            .lock()
+            .unwrap()
+            .values()
+            .all(|v| !matches!(v, TaskStatus::Restarting { .. }))
+    }
+
+    /// Отмена и ожидание завершения всех задач
+    pub async fn shutdown(self) {
+        self.token.cancel();
+        for task in self.tasks {
+            let _ = task.await;
+        }
+    }
+
+    /// Работа до SIGINT или отмены токена, затем [`TaskSupervisor::shutdown`]
+    pub async fn run_until_shutdown(self) {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => info!("Получен SIGINT, остановка задач"),
+            _ = self.token.cancelled() => (),
+        }
+        self.shutdown().await;
+    }
+}
+
+fn set_status(health: &Mutex<BTreeMap<String, TaskStatus>>, name: &str, status: TaskStatus) {
+    health.lock().unwrap().insert(name.to_string(), status);
+}
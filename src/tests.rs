@@ -0,0 +1,3840 @@
+use alloy::primitives::U256;
+use tracing::debug;
+use tracing_test::traced_test;
+
+use alloy::{
+    consensus::constants::ETH_TO_WEI,
+    network::EthereumWallet,
+    primitives::Address,
+    providers::{Provider, ProviderBuilder},
+    signers::local::PrivateKeySigner,
+    transports::mock::Asserter,
+};
+use tokio::sync::OnceCell;
+
+use crate::{
+    accounts::{self, Signer},
+    config::Profile,
+    console::watch_logs,
+    contracts::{
+        Bridge::BridgeInstance,
+        DemoERC20::{self, DemoERC20Instance},
+        ExmERC20::ExmERC20Instance,
+        TestERC20::TestERC20Instance,
+        WalletProvider, fillers,
+    },
+    deployer::{Deployer, Funder},
+    errors::BridgeError,
+    node::{LocalNode, NodeKind},
+    nonce::Nonces,
+    tasks::TaskSupervisor,
+};
+
+/// Количество тестовых аккаунтов на локальном узле
+const FIXTURE_ACCOUNTS: usize = 5;
+/// Баланс тестового аккаунта, ETH
+const FIXTURE_BALANCE: u64 = 100;
+
+/// Локальный узел, общий для всех тестов процесса. `None` - узел из профиля
+static NODE: OnceCell<Option<LocalNode>> = OnceCell::const_new();
+
+/// ETH на каждом аккаунте [`Fixture`]
+const FIXTURE_ETH: u64 = 10;
+
+/// Локальный узел и аккаунты профиля, без развёртывания общих контрактов
+async fn setup() -> eyre::Result<()> {
+    NODE.get_or_try_init(|| async {
+        let kind = NodeKind::detect()?;
+        if kind == NodeKind::External {
+            return Ok(None);
+        }
+
+        let node = LocalNode::spawn(kind).await?;
+        crate::config::set(node.profile(&Profile::load(None)?))?;
+        let signers = (0..FIXTURE_ACCOUNTS)
+            .map(|_| Signer::random())
+            .collect::<Vec<_>>();
+        let addresses = signers.iter().map(|v| v.address()).collect::<Vec<_>>();
+        node.fund(
+            &addresses,
+            U256::from(FIXTURE_BALANCE) * U256::from(ETH_TO_WEI),
+        )
+        .await?;
+        accounts::set_accounts(signers)?;
+        Ok::<_, eyre::Report>(Some(node))
+    })
+    .await?;
+    Ok(())
+}
+
+/// Локальный узел с пополненными аккаунтами и развёрнутыми контрактами.
+/// С `BRIDGE_TEST_NODE=external` тесты работают с узлом из профиля, без `anvil` и `geth`
+/// завершаются ошибкой
+async fn init() -> eyre::Result<Vec<Signer>> {
+    setup().await?;
+    crate::init().await
+}
+
+/// Пополнение ETH с аккаунта разработчика локального узла или с первого аккаунта профиля
+async fn fund_eth(addresses: &[Address], amount: U256) -> eyre::Result<()> {
+    if let Some(Some(node)) = NODE.get() {
+        return node.fund(addresses, amount).await;
+    }
+
+    let funder = &accounts::read_accounts().await?[0];
+    Funder::new(Deployer::connect(funder).await?)
+        .fund_eth(addresses, amount)
+        .await
+}
+
+/// Новые аккаунты и контракты для одного теста
+///
+/// `owner`, `alice` и `bob` создаются случайными и пополняются ETH, `Bridge` и токены
+/// разворачиваются заново от имени `owner`, `alice` и `bob` получают по 10 токенов каждого вида.
+/// Состояние, оставленное другими тестами, не влияет на результат
+struct Fixture {
+    owner: Signer,
+    alice: Signer,
+    bob: Signer,
+    bridge: Address,
+    /// DemoERC20, TestERC20, ExmERC20
+    tokens: [Address; 3],
+}
+
+impl Fixture {
+    async fn new() -> eyre::Result<Self> {
+        setup().await?;
+        let [owner, alice, bob] = [(); 3].map(|_| Signer::random());
+        fund_eth(
+            &[owner.address(), alice.address(), bob.address()],
+            U256::from(FIXTURE_ETH) * U256::from(ETH_TO_WEI),
+        )
+        .await?;
+
+        let deployer = Deployer::connect(&owner).await?;
+        let bridge = deployer
+            .deploy::<BridgeInstance<WalletProvider>>()
+            .await?
+            .address();
+        let tokens = [
+            deployer
+                .deploy::<DemoERC20Instance<WalletProvider>>()
+                .await?
+                .address(),
+            deployer
+                .deploy::<TestERC20Instance<WalletProvider>>()
+                .await?
+                .address(),
+            deployer
+                .deploy::<ExmERC20Instance<WalletProvider>>()
+                .await?
+                .address(),
+        ];
+
+        let funder = Funder::new(deployer);
+        for token in tokens {
+            funder
+                .fund_token(token, &[alice.address(), bob.address()])
+                .await?;
+        }
+
+        Ok(Fixture {
+            owner,
+            alice,
+            bob,
+            bridge,
+            tokens,
+        })
+    }
+
+    /// Пользователи без прав owner
+    fn users(&self) -> [&Signer; 2] {
+        [&self.alice, &self.bob]
+    }
+
+    /// `Bridge` от имени `user`
+    async fn bridge(&self, user: &Signer) -> BridgeInstance<WalletProvider> {
+        Deployer::connect(user)
+            .await
+            .unwrap()
+            .at::<BridgeInstance<WalletProvider>>(self.bridge)
+            .into_inner()
+    }
+
+    /// Токен от имени `user`. У всех тестовых токенов одинаковый ABI
+    async fn token(&self, token: Address, user: &Signer) -> DemoERC20Instance<WalletProvider> {
+        DemoERC20::new(
+            token,
+            Deployer::connect(user).await.unwrap().provider().clone(),
+        )
+    }
+
+    /// Создание мостов для всех токенов от имени owner
+    async fn create_bridges(&self) -> eyre::Result<()> {
+        let bridge = self.bridge(&self.owner).await;
+        for token in self.tokens {
+            bridge
+                .create_bridge_erc20(token)
+                .value(U256::from(ETH_TO_WEI))
+                .send()
+                .await?
+                .watch()
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Провайдер без узла: ответы на запросы берутся из `asserter` по порядку
+fn mock_provider(asserter: &Asserter) -> impl Provider + Clone + use<> {
+    ProviderBuilder::new().connect_mocked_client(asserter.clone())
+}
+
+/// Провайдер без узла со случайным ключом. Газ и nonce заполняются как у [`Deployer`],
+/// ключ доступен через [`WalletProvider::wallet`](alloy::providers::WalletProvider::wallet)
+fn mock_wallet_provider(asserter: &Asserter) -> WalletProvider {
+    ProviderBuilder::new()
+        .disable_recommended_fillers()
+        .filler(fillers(Nonces::default()))
+        .wallet(EthereumWallet::from(PrivateKeySigner::random()))
+        .connect_mocked_client(asserter.clone())
+}
+
+/// Причина отката вызова контракта
+fn revert_reason<T>(res: &Result<T, alloy::contract::Error>) -> Option<BridgeError> {
+    res.as_ref()
+        .err()
+        .and_then(BridgeError::from_contract_error)
+}
+
+/// ETH
+mod test_eth {
+
+    mod deposit {
+        use alloy::{primitives::U256, providers::Provider};
+        use tracing::info;
+        use tracing_test::traced_test;
+
+        use crate::{
+            bus::{EventBus, Overflow},
+            console::{watch_bridge_event, watch_logs},
+            deployer::Deployer,
+            errors::BridgeError,
+            events::{BridgeEvent, EventKind, EventLog},
+            tasks::TaskSupervisor,
+            tests::{Fixture, revert_reason},
+            watcher::WatchUpdate,
+        };
+
+        #[tokio::test]
+        #[traced_test]
+        async fn deposit() {
+            let fixture = Fixture::new().await.unwrap();
+
+            let mut tasks = TaskSupervisor::default();
+            watch_logs(&mut tasks, fixture.owner.clone());
+
+            // Мониторинг событий пополнения депозита
+            let bus = EventBus::new();
+            let mut rec_deposit_events = bus.subscribe(10, Overflow::Block).unwrap();
+            watch_bridge_event(
+                &mut tasks,
+                fixture.owner.clone(),
+                fixture.bridge,
+                EventKind::Deposit,
+                None,
+                None,
+                bus,
+            )
+            .await
+            .unwrap();
+            let deposit = |update| match update {
+                WatchUpdate::Confirmed(EventLog {
+                    event: BridgeEvent::Deposit(data),
+                    ..
+                }) => Some(data),
+                _ => None,
+            };
+            let min_amount = U256::from(10).pow(U256::from(10));
+
+            for user in fixture.users() {
+                let user_address = user.address();
+                let provider = Deployer::connect(user).await.unwrap().provider().clone();
+
+                let bridge = fixture.bridge(user).await;
+                let bridge_address = *bridge.address();
+
+                let old_balance = provider.get_balance(user_address).await.unwrap();
+                let old_bridge_balance = provider.get_balance(bridge_address).await.unwrap();
+                info!("user balance: {old_balance}");
+                info!("bridge balance: {old_bridge_balance}");
+
+                info!("Перевод l1=>L2 {min_amount}");
+                let tx = bridge
+                    .deposit(user_address)
+                    .value(min_amount)
+                    .send()
+                    .await
+                    .unwrap()
+                    .watch()
+                    .await
+                    .unwrap();
+                info!("tx: {:?}", tx);
+
+                let new_balance = provider.get_balance(user_address).await.unwrap();
+                info!(
+                    "user {user_address}\n\
+                    old: {old_balance}\n\
+                    new: {new_balance}"
+                );
+                let new_bridge_balance = provider.get_balance(bridge_address).await.unwrap();
+                info!(
+                    "bridge {bridge_address}\n\
+                    old: {old_bridge_balance}\n\
+                    new: {new_bridge_balance}"
+                );
+
+                assert!(old_balance - new_balance > min_amount);
+                // переведённые деньги должны осесть на балансе моста
+                assert_eq!(new_bridge_balance - old_bridge_balance, min_amount);
+            }
+
+            for user in fixture.users() {
+                let tx = rec_deposit_events.recv_map(deposit).await.unwrap();
+                assert_eq!(tx.from, user.address());
+                assert_eq!(tx.to, user.address());
+                assert_eq!(tx.value, 1);
+            }
+
+            tasks.shutdown().await;
+        }
+
+        #[tokio::test]
+        #[traced_test]
+        async fn deposit_err_min_decimal() {
+            let fixture = Fixture::new().await.unwrap();
+            let amount = U256::from(10).pow(U256::from(10)) - U256::from(1);
+
+            let user = &fixture.alice;
+            let bridge = fixture.bridge(user).await;
+            let res = bridge.deposit(user.address()).value(amount).send().await;
+            assert!(res.is_err());
+            assert_eq!(revert_reason(&res), Some(BridgeError::Precision(8)));
+        }
+    }
+
+    mod withdraw {
+        use alloy::{
+            consensus::constants::ETH_TO_WEI, network::TransactionBuilder, primitives::U256,
+            providers::Provider, rpc::types::TransactionRequest,
+        };
+
+        use tracing::info;
+        use tracing_test::traced_test;
+
+        use crate::{
+            deployer::Deployer,
+            errors::BridgeError,
+            tests::{Fixture, revert_reason},
+        };
+
+        #[tokio::test]
+        #[traced_test]
+        async fn withdraw() {
+            let fixture = Fixture::new().await.unwrap();
+
+            // Запрос на вывод отправляется от owner
+            let owner = &fixture.owner;
+            let owner_bridge = fixture.bridge(owner).await;
+            let owner_provider = Deployer::connect(owner).await.unwrap().provider().clone();
+            let bridge_address = *owner_bridge.address();
+
+            info!("Пополняем баланс моста");
+            let tx = owner_provider
+                .send_transaction(
+                    TransactionRequest::default()
+                        .with_from(owner.address())
+                        .with_to(bridge_address)
+                        .with_value(U256::from(ETH_TO_WEI)),
+                )
+                .await
+                .unwrap()
+                .watch()
+                .await
+                .unwrap();
+            info!("Баланс моста пополнен на 1 eth. tx:{tx:#?} ");
+
+            for user in fixture.users() {
+                let address = user.address();
+                let old_balance = owner_provider.get_balance(address).await.unwrap();
+
+                let bridge = fixture.bridge(user).await;
+                let old_bridge_balance = owner_provider.get_balance(bridge_address).await.unwrap();
+
+                let amount = U256::from(10_u64.pow(14)); // 0.0001 ETH
+
+                assert_eq!(
+                    bridge.available_to_withdraw().call().await.unwrap(),
+                    U256::ZERO
+                );
+                info!("Разрешаем на вывод 0.0001 ETH для {address}");
+                let tx = owner_bridge
+                    .apply_withdrawal_request(address, 10000) // 1 = 0.00000001 ETH
+                    .send()
+                    .await
+                    .unwrap()
+                    .watch()
+                    .await
+                    .unwrap();
+                info!("tx: {tx:#?}");
+
+                // Баланс моста не должен измениться
+                assert_eq!(
+                    old_bridge_balance,
+                    owner_provider.get_balance(bridge_address).await.unwrap()
+                );
+
+                assert_eq!(bridge.available_to_withdraw().call().await.unwrap(), amount);
+
+                let tx = bridge
+                    .withdraw()
+                    .send()
+                    .await
+                    .unwrap()
+                    .watch()
+                    .await
+                    .unwrap();
+                info!("tx: {tx:#?}");
+
+                assert_eq!(
+                    bridge.available_to_withdraw().call().await.unwrap(),
+                    U256::ZERO
+                );
+
+                let new_bridge_balance = owner_provider.get_balance(bridge_address).await.unwrap();
+                assert_eq!(
+                    old_bridge_balance - amount,
+                    new_bridge_balance,
+                    "old: {old_bridge_balance}\n\
+                    new: {new_bridge_balance}"
+                );
+                let new_balance = owner_provider.get_balance(address).await.unwrap();
+                assert!(
+                    old_balance - amount < new_balance,
+                    "old: {old_balance}\n\
+                    new: {new_balance}"
+                );
+            }
+        }
+
+        #[tokio::test]
+        #[traced_test]
+        async fn withdraw_err_not_owner() {
+            let fixture = Fixture::new().await.unwrap();
+
+            for user in fixture.users() {
+                let bridge = fixture.bridge(user).await;
+
+                let res = bridge
+                    .apply_withdrawal_request(user.address(), 1)
+                    .call()
+                    .await;
+                assert!(res.is_err(), "{res:#?}");
+                assert_eq!(revert_reason(&res), Some(BridgeError::NotOwner));
+            }
+        }
+
+        #[tokio::test]
+        #[traced_test]
+        async fn withdraw_err_without_approval() {
+            let fixture = Fixture::new().await.unwrap();
+
+            for user in fixture.users() {
+                let bridge = fixture.bridge(user).await;
+                assert_eq!(
+                    bridge.available_to_withdraw().call().await.unwrap(),
+                    U256::ZERO
+                );
+
+                let res = bridge.withdraw().send().await;
+                assert!(res.is_err(), "{res:#?}");
+                assert_eq!(revert_reason(&res), Some(BridgeError::NoFundsForWithdrawal));
+            }
+        }
+    }
+}
+
+/// ERC20
+mod tests_erc {
+    use alloy::primitives::U256;
+
+    use crate::amount::{TokenAmount, l2_decimals};
+
+    /// Минимальная единица l2 в единицах токена
+    fn calc_min_amount(decimal: u8) -> U256 {
+        TokenAmount::new(U256::from(1), l2_decimals(decimal))
+            .convert(decimal)
+            .unwrap()
+            .value()
+    }
+
+    /// Создание моста для (ERC20)
+    mod tests_create_bridge {
+        use alloy::{
+            consensus::constants::ETH_TO_WEI,
+            primitives::{Address, U256},
+            providers::Provider,
+        };
+        use rand::random;
+        use tracing::info;
+        use tracing_test::traced_test;
+
+        use crate::{
+            deployer::Deployer,
+            errors::BridgeError,
+            tests::{Fixture, revert_reason},
+        };
+
+        // (ERC20) попытка подключить без комиссии или с недостаточной комиссией
+        #[tokio::test]
+        #[traced_test]
+        async fn err_commission() {
+            let fixture = Fixture::new().await.unwrap();
+            let bridge = fixture.bridge(&fixture.alice).await;
+            let token_address = fixture.tokens[0];
+
+            let res = bridge.create_bridge_erc20(token_address).send().await;
+            assert!(res.is_err());
+            assert_eq!(revert_reason(&res), Some(BridgeError::CreationCommission));
+
+            let res = bridge
+                .create_bridge_erc20(token_address)
+                .value(U256::from(ETH_TO_WEI - 1))
+                .send()
+                .await;
+            assert!(res.is_err());
+            assert_eq!(revert_reason(&res), Some(BridgeError::CreationCommission));
+        }
+
+        // (ERC20) попытка подключить несуществующего токен
+        #[tokio::test]
+        #[traced_test]
+        async fn err_invalid_token() {
+            let fixture = Fixture::new().await.unwrap();
+
+            let token_address = Address::from_slice(&random::<[u8; 20]>());
+
+            for user in [&fixture.owner, &fixture.alice] {
+                let bridge = fixture.bridge(user).await;
+                assert!(
+                    !bridge
+                        .exist_bridge_erc20(token_address)
+                        .call()
+                        .await
+                        .unwrap()
+                );
+
+                assert!(
+                    bridge
+                        .create_bridge_erc20(token_address)
+                        .value(U256::from(ETH_TO_WEI))
+                        .send()
+                        .await
+                        .is_err()
+                );
+            }
+        }
+
+        // (ERC20) Успешное подключение ERC20 токена
+        #[tokio::test]
+        #[traced_test]
+        async fn success() {
+            let fixture = Fixture::new().await.unwrap();
+
+            for (user, token_address) in [&fixture.owner, &fixture.alice, &fixture.bob]
+                .into_iter()
+                .zip(fixture.tokens)
+            {
+                let bridge = fixture.bridge(user).await;
+                let bridge_address = *bridge.address();
+                let provider = Deployer::connect(user).await.unwrap().provider().clone();
+
+                assert!(
+                    !bridge
+                        .exist_bridge_erc20(token_address)
+                        .call()
+                        .await
+                        .unwrap()
+                );
+
+                let amount = U256::from(ETH_TO_WEI);
+                let old_bridge_balance = provider.get_balance(bridge_address).await.unwrap();
+                let old_user_balance = provider.get_balance(user.address()).await.unwrap();
+
+                let tx = bridge
+                    .create_bridge_erc20(token_address)
+                    .value(amount)
+                    .send()
+                    .await
+                    .unwrap()
+                    .watch()
+                    .await
+                    .unwrap();
+                info!("Мост для {token_address} создан  Tx {tx:?}");
+
+                assert!(
+                    bridge
+                        .exist_bridge_erc20(token_address)
+                        .call()
+                        .await
+                        .unwrap()
+                );
+
+                let new_bridge_balance = provider.get_balance(bridge_address).await.unwrap();
+                let new_user_balance = provider.get_balance(user.address()).await.unwrap();
+
+                assert_eq!(old_bridge_balance + amount, new_bridge_balance);
+                assert!(old_user_balance > new_user_balance - amount);
+            }
+        }
+    }
+
+    /// (ERC20) Пополнение баланса на l2
+    mod deposit {
+        use alloy::primitives::U256;
+
+        use tracing::{debug, info};
+        use tracing_test::traced_test;
+
+        use crate::{
+            bus::{EventBus, Overflow},
+            console::{self, watch_bridge_event},
+            deployer::token_balances,
+            errors::BridgeError,
+            events::{BridgeEvent, EventKind, EventLog},
+            tasks::TaskSupervisor,
+            tests::{Fixture, revert_reason, tests_erc::calc_min_amount},
+            watcher::WatchUpdate,
+        };
+
+        #[tokio::test]
+        #[traced_test]
+        async fn deposit() {
+            let fixture = Fixture::new().await.unwrap();
+
+            let mut tasks = TaskSupervisor::default();
+            console::watch_logs(&mut tasks, fixture.owner.clone());
+
+            let owner = &fixture.owner;
+            let bridge_address = fixture.bridge;
+            let [demo, test, exm] = fixture.tokens;
+            let demo_token = fixture.token(demo, owner).await;
+            let test_token = fixture.token(test, owner).await;
+            let exm_token = fixture.token(exm, owner).await;
+            let tokens = fixture.tokens;
+
+            info!("Создание мостов для токенов {tokens:?}");
+            fixture.create_bridges().await.unwrap();
+
+            let min_amount = [
+                calc_min_amount(demo_token.decimals().call().await.unwrap()),
+                calc_min_amount(test_token.decimals().call().await.unwrap()),
+                calc_min_amount(exm_token.decimals().call().await.unwrap()),
+            ];
+
+            // Мониторинг событий пополнения депозита
+            let bus = EventBus::new();
+            let mut rec_deposit_events = bus.subscribe(10, Overflow::Block).unwrap();
+            watch_bridge_event(
+                &mut tasks,
+                owner.clone(),
+                bridge_address,
+                EventKind::DepositErc20,
+                None,
+                None,
+                bus,
+            )
+            .await
+            .unwrap();
+            let deposit = |update| match update {
+                WatchUpdate::Confirmed(EventLog {
+                    event: BridgeEvent::DepositErc20(data),
+                    ..
+                }) => Some(data),
+                _ => None,
+            };
+
+            for user in fixture.users() {
+                let user_address = user.address();
+                let user_bridge = fixture.bridge(user).await;
+                let user_demo_token = fixture.token(demo, user).await;
+                let user_test_token = fixture.token(test, user).await;
+                let user_exm_token = fixture.token(exm, user).await;
+
+                info!("Одобрение перевода на кошелёк");
+                for (token, amount) in [&user_demo_token, &user_test_token, &user_exm_token]
+                    .into_iter()
+                    .zip(min_amount)
+                {
+                    token
+                        .approve(bridge_address, amount)
+                        .send()
+                        .await
+                        .unwrap()
+                        .watch()
+                        .await
+                        .unwrap();
+                }
+
+                let old_user_balance =
+                    token_balances(user_bridge.provider(), user_address, &tokens)
+                        .await
+                        .unwrap();
+                let old_bridge_balance =
+                    token_balances(user_bridge.provider(), bridge_address, &tokens)
+                        .await
+                        .unwrap();
+
+                for (token_address, amount) in tokens.iter().zip(min_amount) {
+                    user_bridge
+                        .deposit_erc20(*token_address, user_address, amount)
+                        .send()
+                        .await
+                        .unwrap()
+                        .watch()
+                        .await
+                        .unwrap();
+                }
+
+                for (tx, token) in [
+                    rec_deposit_events.recv_map(deposit).await.unwrap(),
+                    rec_deposit_events.recv_map(deposit).await.unwrap(),
+                    rec_deposit_events.recv_map(deposit).await.unwrap(),
+                ]
+                .iter()
+                .zip(tokens)
+                {
+                    assert_eq!(tx.token_address, token);
+                    assert_eq!(tx.from, user_address);
+                    assert_eq!(tx.to, user_address);
+                    assert_eq!(tx.value, U256::from(1));
+                }
+
+                let new_user_balance =
+                    token_balances(user_bridge.provider(), user_address, &tokens)
+                        .await
+                        .unwrap();
+                let new_bridge_balance =
+                    token_balances(user_bridge.provider(), bridge_address, &tokens)
+                        .await
+                        .unwrap();
+
+                for ((old, new), amount) in old_user_balance
+                    .iter()
+                    .zip(&new_user_balance)
+                    .zip(&min_amount)
+                {
+                    assert_eq!(
+                        old - new,
+                        *amount,
+                        "new: {new}, old: {old}, amount: {amount}"
+                    );
+                }
+
+                for ((old, new), amount) in old_bridge_balance
+                    .iter()
+                    .zip(&new_bridge_balance)
+                    .zip(&min_amount)
+                {
+                    assert_eq!(
+                        new - old,
+                        *amount,
+                        "new: {new}, old: {old}, amount: {amount}"
+                    );
+                }
+            }
+            tasks.shutdown().await;
+        }
+
+        #[tokio::test]
+        #[traced_test]
+        async fn deposit_err_min_decimal() {
+            let fixture = Fixture::new().await.unwrap();
+            fixture.create_bridges().await.unwrap();
+            let alice = &fixture.alice;
+
+            let demo_address = fixture.tokens[0];
+            let demo_token = fixture.token(demo_address, alice).await;
+            let bridge = fixture.bridge(alice).await;
+            let bridge_address = *bridge.address();
+
+            let decimals = demo_token.decimals().call().await.unwrap();
+            info!("Decimals: {decimals}");
+            assert!(decimals > 8);
+
+            let min = U256::from(10_u64.pow(decimals as u32 - 8) + 1);
+            demo_token
+                .approve(bridge_address, min)
+                .send()
+                .await
+                .unwrap()
+                .watch()
+                .await
+                .unwrap();
+            let res = bridge
+                .deposit_erc20(demo_address, alice.address(), min)
+                .send()
+                .await;
+            debug!("{res:#?}");
+            assert!(res.is_err(), "{res:#?}");
+            assert_eq!(revert_reason(&res), Some(BridgeError::Precision(8)));
+        }
+
+        #[tokio::test]
+        #[traced_test]
+        async fn deposit_err_without_approve() {
+            let fixture = Fixture::new().await.unwrap();
+            fixture.create_bridges().await.unwrap();
+            let alice = &fixture.alice;
+
+            let demo_address = fixture.tokens[0];
+            let demo_token = fixture.token(demo_address, alice).await;
+            let bridge = fixture.bridge(alice).await;
+            let bridge_address = *bridge.address();
+
+            let decimals = demo_token.decimals().call().await.unwrap();
+            info!("Decimals: {decimals}");
+            assert!(decimals > 8);
+
+            let min = U256::from(10_u64.pow(decimals as u32 - 8));
+            demo_token
+                .approve(bridge_address, min)
+                .send()
+                .await
+                .unwrap()
+                .watch()
+                .await
+                .unwrap();
+            let res = bridge
+                .deposit_erc20(demo_address, alice.address(), min * U256::from(2))
+                .send()
+                .await;
+            debug!("{res:#?}");
+            assert!(res.is_err(), "{res:#?}");
+            assert_eq!(revert_reason(&res), Some(BridgeError::TransferNotApproved));
+        }
+    }
+
+    mod withdraw {
+        use alloy::primitives::U256;
+        use tracing::{debug, info};
+        use tracing_test::traced_test;
+
+        use crate::{
+            console::{self},
+            deployer::{Deployer, Funder, token_balances},
+            errors::BridgeError,
+            tasks::TaskSupervisor,
+            tests::{Fixture, revert_reason, tests_erc::calc_min_amount},
+        };
+
+        #[tokio::test]
+        #[traced_test]
+        async fn withdraw() {
+            let fixture = Fixture::new().await.unwrap();
+
+            let mut tasks = TaskSupervisor::default();
+            console::watch_logs(&mut tasks, fixture.owner.clone());
+
+            let owner = &fixture.owner;
+            let owner_address = owner.address();
+
+            let alice_address = fixture.alice.address();
+            debug!("alice: {alice_address:?}");
+
+            let bob_address = fixture.bob.address();
+            debug!("bob: {bob_address:?}");
+
+            let owner_bridge = fixture.bridge(owner).await;
+            let bridge_address = *owner_bridge.address();
+            debug!("bridge address: {bridge_address:?}");
+
+            let tokens = fixture.tokens;
+            let [demo, test, exm] = tokens;
+            let demo_token = fixture.token(demo, owner).await;
+            let test_token = fixture.token(test, owner).await;
+            let exm_token = fixture.token(exm, owner).await;
+
+            // Пополнение баланса моста
+            let funder = Funder::new(Deployer::connect(owner).await.unwrap());
+            for token in tokens {
+                funder.fund_token(token, &[bridge_address]).await.unwrap();
+            }
+
+            info!("Создание мостов для токенов {tokens:?}");
+            fixture.create_bridges().await.unwrap();
+
+            let min_amount = [
+                calc_min_amount(demo_token.decimals().call().await.unwrap()),
+                calc_min_amount(test_token.decimals().call().await.unwrap()),
+                calc_min_amount(exm_token.decimals().call().await.unwrap()),
+            ];
+
+            let old_owner_balance = token_balances(owner_bridge.provider(), owner_address, &tokens)
+                .await
+                .unwrap();
+            let old_alice_balance = token_balances(owner_bridge.provider(), alice_address, &tokens)
+                .await
+                .unwrap();
+            let old_bob_balance = token_balances(owner_bridge.provider(), bob_address, &tokens)
+                .await
+                .unwrap();
+            let old_bridge_balance =
+                token_balances(owner_bridge.provider(), bridge_address, &tokens)
+                    .await
+                    .unwrap();
+
+            for token_address in tokens {
+                for user in fixture.users() {
+                    let user_address = user.address();
+                    info!(
+                        "Owner создаёт заявки на вывод токена {token_address:?} на адрес {user_address}"
+                    );
+                    let tx = owner_bridge
+                        .apply_withdrawal_request_erc20(token_address, user.address(), 1)
+                        .send()
+                        .await
+                        .unwrap()
+                        .watch()
+                        .await
+                        .unwrap();
+                    info!("Tx {tx:?}");
+
+                    let bridge = fixture.bridge(user).await;
+                    let withdraw_balance = bridge
+                        .available_to_withdraw_erc20(token_address)
+                        .call()
+                        .await
+                        .unwrap();
+                    assert_ne!(withdraw_balance, U256::ZERO);
+
+                    info!("Выводим токены {token_address:?} из моста на адрес {user_address}");
+                    let tx = bridge
+                        .withdraw_erc20(token_address)
+                        .send()
+                        .await
+                        .unwrap()
+                        .watch()
+                        .await
+                        .unwrap();
+                    info!("Tx {tx:?}");
+                }
+            }
+
+            let new_owner_balance = token_balances(owner_bridge.provider(), owner_address, &tokens)
+                .await
+                .unwrap();
+            let new_alice_balance = token_balances(owner_bridge.provider(), alice_address, &tokens)
+                .await
+                .unwrap();
+            let new_bob_balance = token_balances(owner_bridge.provider(), bob_address, &tokens)
+                .await
+                .unwrap();
+            let new_bridge_balance =
+                token_balances(owner_bridge.provider(), bridge_address, &tokens)
+                    .await
+                    .unwrap();
+
+            for (old, new) in [
+                (old_alice_balance, new_alice_balance),
+                (old_bob_balance, new_bob_balance),
+            ] {
+                for ((old, new), amount) in old.iter().zip(new).zip(min_amount) {
+                    assert_eq!(
+                        new - *old,
+                        amount,
+                        "new: {new}, old: {old}, expected: {amount}"
+                    );
+                }
+            }
+
+            assert_eq!(old_owner_balance, new_owner_balance);
+
+            for ((old, new), amount) in old_bridge_balance
+                .iter()
+                .zip(new_bridge_balance)
+                .zip(min_amount)
+            {
+                let amount_x2 = amount * U256::from(2);
+                assert_eq!(
+                    old - new,
+                    amount_x2,
+                    "new: {new}, old: {old}, expected: {amount_x2}"
+                );
+            }
+
+            tasks.shutdown().await;
+        }
+
+        #[tokio::test]
+        #[traced_test]
+        async fn withdraw_err_not_owner() {
+            let fixture = Fixture::new().await.unwrap();
+
+            let alice = &fixture.alice;
+            let bridge = fixture.bridge(alice).await;
+
+            let res = bridge
+                .apply_withdrawal_request_erc20(fixture.tokens[0], alice.address(), 1)
+                .send()
+                .await;
+            debug!("{res:#?}");
+            assert!(res.is_err(), "{res:#?}");
+            assert_eq!(revert_reason(&res), Some(BridgeError::NotOwner));
+        }
+    }
+}
+
+#[tokio::test]
+#[traced_test]
+async fn convert_decimals() {
+    let owner = init().await.unwrap()[0].to_owned();
+    let bridge = Deployer::connect(&owner)
+        .await
+        .unwrap()
+        .contract::<BridgeInstance<WalletProvider>>()
+        .await
+        .unwrap();
+
+    let mut tasks = TaskSupervisor::default();
+    watch_logs(&mut tasks, owner.clone());
+
+    for (input, output) in [
+        ((1_234, 0, 0), 1_234),
+        ((1_234, 3, 3), 1_234),
+        ((1_234, 0, 1), 12_340),
+        ((1_234, 0, 3), 1_234_000),
+        ((1_234, 1, 3), 123_400),
+        ((1_234, 1, 3), 123_400),
+        ((1_000, 3, 0), 1),
+        ((1_000, 2, 0), 10),
+        ((1_000, 2, 1), 100),
+        ((1_230, 1, 0), 123),
+        ((1_230, 3, 2), 123),
+    ] {
+        debug!("input: {input:#?}, output: {output}");
+        assert_eq!(
+            bridge
+                .convert_amount(U256::from(input.0), input.1, input.2)
+                .call()
+                .await
+                .unwrap(),
+            U256::from(output),
+            "input: {input:#?}, output: {output}"
+        );
+    }
+
+    for input in [(1_230, 3, 0), (1_230, 2, 0)] {
+        let r = bridge
+            .convert_amount(U256::from(input.0), input.1, input.2)
+            .call()
+            .await;
+        debug!("{r:#?}");
+        assert!(r.is_err(), "{r:#?}");
+        assert_eq!(revert_reason(&r), Some(BridgeError::Precision(0)));
+    }
+    tasks.shutdown().await;
+}
+
+/// Профили сетей
+mod config {
+    use alloy::primitives::address;
+
+    use crate::config::{CONFIG_PATH, Config, KeySource};
+
+    #[test]
+    fn parse_profiles() {
+        let config = Config::parse(&std::fs::read_to_string(CONFIG_PATH).unwrap()).unwrap();
+
+        let dev = config.profile(None).unwrap();
+        assert_eq!(dev.name, "dev");
+        assert_eq!(dev.chain_id, 1337);
+        assert!(matches!(dev.keys, KeySource::Keystore(_)));
+
+        let anvil = config.profile(Some("anvil")).unwrap();
+        assert_eq!(anvil.chain_id, 31337);
+        assert!(matches!(anvil.keys, KeySource::Env(_)));
+
+        assert!(config.profile(Some("unknown")).is_err());
+    }
+
+    #[test]
+    fn contract_addresses() {
+        let config = Config::parse(
+            r#"
+            [profiles.dev]
+            http_url = "http://localhost:8545"
+            chain_id = 1337
+            keys = { env = "KEYS" }
+
+            [profiles.dev.contracts]
+            Bridge = "0x5f57e12c0e8be10c6e77ce060c48f52a4fb636e7"
+            "#,
+        )
+        .unwrap();
+
+        let dev = config.profile(None).unwrap();
+        assert_eq!(
+            dev.contract("Bridge"),
+            Some(address!("0x5f57e12c0e8be10c6e77ce060c48f52a4fb636e7"))
+        );
+        assert_eq!(dev.contract("DemoERC20"), None);
+    }
+}
+
+/// Реестр развёрнутых контрактов
+mod registry {
+    use alloy::primitives::{Address, B256};
+
+    use crate::registry::{Deployment, DeploymentKey, Registry};
+
+    fn deployment(chain_id: u64, address: Address) -> Deployment {
+        Deployment {
+            key: DeploymentKey {
+                chain_id,
+                genesis_hash: B256::repeat_byte(1),
+                contract: "Bridge".to_string(),
+                bytecode_hash: B256::repeat_byte(2),
+            },
+            address,
+            code_hash: B256::repeat_byte(3),
+        }
+    }
+
+    #[test]
+    fn insert_and_reload() {
+        let path = std::env::temp_dir().join(format!("registry-{}.json", rand::random::<u64>()));
+
+        let mut registry = Registry::default();
+        registry.insert(deployment(1337, Address::repeat_byte(1)));
+        registry.insert(deployment(31337, Address::repeat_byte(2)));
+        // Повторное развёртывание в той же сети заменяет запись
+        registry.insert(deployment(1337, Address::repeat_byte(3)));
+        registry.save(&path).unwrap();
+
+        let registry = Registry::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(registry.deployments.len(), 2);
+        let key = deployment(1337, Address::ZERO).key;
+        assert_eq!(registry.get(&key).unwrap().address, Address::repeat_byte(3));
+
+        let mut other_genesis = key.clone();
+        other_genesis.genesis_hash = B256::ZERO;
+        assert!(registry.get(&other_genesis).is_none());
+    }
+}
+
+/// Зашифрованное хранилище ключей
+mod keystore {
+    use alloy::primitives::B256;
+    use zeroize::Zeroizing;
+
+    use crate::accounts::KeyStore;
+
+    #[test]
+    fn import_and_decrypt() {
+        let path = std::env::temp_dir().join(format!("keystore-{}", rand::random::<u64>()));
+        std::fs::create_dir(&path).unwrap();
+
+        let keystore = KeyStore::new(&path, Zeroizing::new("password".to_string()));
+        let imported = keystore.import(&B256::repeat_byte(7)).unwrap();
+        let created = keystore.create().unwrap();
+
+        // На диске ключ не хранится в открытом виде
+        for file in path.read_dir().unwrap() {
+            let content = std::fs::read_to_string(file.unwrap().path()).unwrap();
+            assert!(!content.contains(&hex::encode(B256::repeat_byte(7))));
+        }
+
+        let mut addresses: Vec<_> = keystore
+            .signers()
+            .unwrap()
+            .iter()
+            .map(|v| v.address())
+            .collect();
+        addresses.sort();
+        let mut expected = vec![imported.address(), created.address()];
+        expected.sort();
+        assert_eq!(addresses, expected);
+
+        let wrong_password = KeyStore::new(&path, Zeroizing::new("wrong".to_string()));
+        assert!(wrong_password.signers().is_err());
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
+
+/// Лог события моста в блоке `block_number`
+fn event_log<E: alloy_sol_types::SolEvent>(
+    address: alloy::primitives::Address,
+    event: &E,
+    block_number: u64,
+    log_index: u64,
+) -> alloy::rpc::types::Log {
+    use alloy::primitives::{B256, keccak256};
+
+    alloy::rpc::types::Log {
+        inner: alloy::primitives::Log {
+            address,
+            data: event.encode_log_data(),
+        },
+        block_hash: Some(keccak256(block_number.to_be_bytes())),
+        block_number: Some(block_number),
+        transaction_hash: Some(B256::with_last_byte(block_number as u8)),
+        log_index: Some(log_index),
+        ..Default::default()
+    }
+}
+
+/// Разбор событий моста, фильтр подписки и чтение истории диапазонами
+mod events {
+    use alloy::{
+        primitives::{Address, U256},
+        rpc::json_rpc::ErrorPayload,
+        transports::mock::Asserter,
+    };
+
+    use super::{event_log, mock_provider};
+    use crate::{
+        contracts::{Bridge, DemoERC20},
+        events::{Backfill, BridgeEvent, EventFilter, EventKind, EventLog, decode_logs},
+    };
+
+    #[test]
+    fn decode_with_meta() {
+        let bridge = Address::repeat_byte(0xb);
+        let event = Bridge::EventDepositRC20 {
+            token_address: Address::repeat_byte(0xe),
+            from: Address::repeat_byte(0xa),
+            to: Address::repeat_byte(0xa),
+            value: U256::from(7),
+        };
+        let log = event_log(bridge, &event, 9, 3);
+
+        let decoded = EventLog::from_log(&log).unwrap().unwrap();
+        assert_eq!(decoded.event, BridgeEvent::DepositErc20(event));
+        assert_eq!(decoded.event.kind(), EventKind::DepositErc20);
+        assert_eq!(decoded.meta.address, bridge);
+        assert_eq!(decoded.meta.block_number, 9);
+        assert_eq!(decoded.meta.log_index, 3);
+        assert_eq!(Some(decoded.meta.tx_hash), log.transaction_hash);
+    }
+
+    #[test]
+    fn filter() {
+        let bridge = Address::repeat_byte(0xb);
+        let token = Address::repeat_byte(0xe);
+        let alice = Address::repeat_byte(0xa);
+        let events = decode_logs(vec![
+            event_log(
+                bridge,
+                &Bridge::EventDeposit {
+                    from: alice,
+                    to: alice,
+                    value: 1,
+                },
+                1,
+                0,
+            ),
+            event_log(
+                token,
+                &DemoERC20::Transfer {
+                    from: alice,
+                    to: bridge,
+                    value: U256::from(1),
+                },
+                1,
+                1,
+            ),
+        ]);
+        assert_eq!(events.len(), 2);
+
+        let all = EventFilter::new();
+        assert!(events.iter().all(|v| all.matches(v)));
+
+        let by_address = EventFilter::new().address(token);
+        assert!(!by_address.matches(&events[0]));
+        assert!(by_address.matches(&events[1]));
+
+        let by_kind = EventFilter::new().kind(EventKind::Deposit);
+        assert!(by_kind.matches(&events[0]));
+        assert!(!by_kind.matches(&events[1]));
+        assert!(by_kind.to_filter().topics[0].matches(&EventKind::Deposit.signature_hash()));
+    }
+
+    #[test]
+    fn kind_from_str() {
+        assert_eq!(
+            "deposit-erc20".parse::<EventKind>().unwrap(),
+            EventKind::DepositErc20
+        );
+        assert_eq!(
+            "create_bridge".parse::<EventKind>().unwrap(),
+            EventKind::CreateBridge
+        );
+        assert!("unknown".parse::<EventKind>().is_err());
+        for kind in EventKind::ALL {
+            assert_eq!(
+                EventKind::from_signature_hash(&kind.signature_hash()),
+                Some(kind)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn backfill_split_range() {
+        let asserter = Asserter::new();
+        let provider = mock_provider(&asserter);
+        let bridge = Address::repeat_byte(0xb);
+        let alice = Address::repeat_byte(0xa);
+        let deposit = |block| {
+            event_log(
+                bridge,
+                &Bridge::EventDeposit {
+                    from: alice,
+                    to: alice,
+                    value: block,
+                },
+                block,
+                0,
+            )
+        };
+
+        // Первый запрос отклонён, история читается двумя половинами
+        asserter.push_failure_msg("block range is too large");
+        asserter.push_success(&vec![deposit(10)]);
+        asserter.push_success(&vec![deposit(60)]);
+        let mut backfill = Backfill::new(EventFilter::new().address(bridge), 0, 99).max_range(100);
+        let mut events = Vec::new();
+        while let Some(chunk) = backfill.next_chunk(&provider).await.unwrap() {
+            events.extend(chunk);
+        }
+        assert_eq!(backfill.range(), 50);
+        assert_eq!(backfill.next_block(), 100);
+        assert_eq!(
+            events
+                .iter()
+                .map(|v| v.meta.block_number)
+                .collect::<Vec<_>>(),
+            [10, 60]
+        );
+
+        // Превышение лимита запросов: тот же диапазон запрашивается повторно
+        asserter.push_failure(ErrorPayload {
+            code: -32005,
+            message: "rate limit exceeded".into(),
+            data: None,
+        });
+        asserter.push_success(&vec![deposit(10)]);
+        let mut backfill = Backfill::new(EventFilter::new().address(bridge), 0, 99).max_range(100);
+        assert_eq!(
+            backfill.next_chunk(&provider).await.unwrap().unwrap().len(),
+            1
+        );
+        assert_eq!(backfill.range(), 100);
+
+        // Прочие ошибки не приводят к делению диапазона
+        asserter.push_failure_msg("boom");
+        let backfill = Backfill::new(EventFilter::new(), 0, 99).max_range(100);
+        assert!(backfill.collect(&provider).await.is_err());
+    }
+}
+
+/// Ретранслятор депозитов
+mod relayer {
+    use std::io::Write;
+
+    use alloy::{
+        primitives::{Address, B256, U256},
+        rpc::types::Block,
+        transports::mock::Asserter,
+    };
+
+    use super::{event_log, mock_provider};
+    use crate::{
+        bus::{EventBus, Overflow},
+        contracts::Bridge,
+        events::EventLog,
+        relayer::{
+            Credit, Deposit, DepositId, FileSink, L2Sink, MemorySink, Relayer, RelayerState,
+        },
+        watcher::{Polled, WatchUpdate, Watcher},
+    };
+
+    fn deposit_logs(bridge: Address) -> Vec<alloy::rpc::types::Log> {
+        let alice = Address::repeat_byte(0xa);
+        let token = Address::repeat_byte(0xe);
+        vec![
+            event_log(
+                bridge,
+                &Bridge::EventDeposit {
+                    from: alice,
+                    to: alice,
+                    value: 1,
+                },
+                5,
+                0,
+            ),
+            // События других контрактов не зачисляются
+            event_log(
+                Address::repeat_byte(0xff),
+                &Bridge::EventDeposit {
+                    from: alice,
+                    to: alice,
+                    value: 100,
+                },
+                5,
+                1,
+            ),
+            event_log(
+                bridge,
+                &Bridge::EventDepositRC20 {
+                    token_address: token,
+                    from: alice,
+                    to: alice,
+                    value: U256::from(2),
+                },
+                6,
+                0,
+            ),
+        ]
+    }
+
+    fn deposits(bridge: Address) -> Vec<WatchUpdate> {
+        deposit_logs(bridge)
+            .iter()
+            .map(|v| WatchUpdate::Confirmed(EventLog::from_log(v).unwrap().unwrap()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn credit_once_across_restarts() {
+        let bridge = Address::repeat_byte(0xb);
+        let dir = std::env::temp_dir().join(format!("relayer-{}", rand::random::<u64>()));
+        std::fs::create_dir(&dir).unwrap();
+        let state_path = dir.join("state.json");
+        let credits_path = dir.join("credits.jsonl");
+        let relayer = || {
+            Relayer::new(
+                bridge,
+                FileSink::open(&credits_path).unwrap(),
+                &state_path,
+                0,
+            )
+            .unwrap()
+        };
+
+        let mut first = relayer();
+        let mut credited = 0;
+        for update in deposits(bridge) {
+            credited += first.handle(&update).await.unwrap() as usize;
+        }
+        assert_eq!(credited, 2);
+
+        // Перезапуск: уже зачисленные депозиты читаются из файла и не зачисляются повторно
+        let mut second = relayer();
+        for update in deposits(bridge) {
+            assert!(!second.handle(&update).await.unwrap());
+        }
+
+        let credits = FileSink::read(&credits_path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(credits.len(), 2);
+        assert!(matches!(credits[0].deposit, Deposit::Eth { value: 1, .. }));
+        assert!(
+            matches!(credits[1].deposit, Deposit::Erc20 { value, .. } if value == U256::from(2))
+        );
+    }
+
+    #[tokio::test]
+    async fn retract() {
+        let bridge = Address::repeat_byte(0xb);
+        let path = std::env::temp_dir().join(format!("credits-{}.jsonl", rand::random::<u64>()));
+        let state_path = path.with_extension("json");
+        let mut relayer =
+            Relayer::new(bridge, FileSink::open(&path).unwrap(), &state_path, 0).unwrap();
+
+        let updates = deposits(bridge);
+        let WatchUpdate::Confirmed(event) = &updates[0] else {
+            unreachable!()
+        };
+        assert!(relayer.handle(&updates[0]).await.unwrap());
+        assert!(
+            relayer
+                .handle(&WatchUpdate::Retracted(event.meta))
+                .await
+                .unwrap()
+        );
+        assert!(FileSink::read(&path).unwrap().is_empty());
+
+        // Та же транзакция в новом блоке зачисляется снова
+        assert!(relayer.handle(&updates[0]).await.unwrap());
+        let credits = FileSink::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(credits.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn partial_line() {
+        let path = std::env::temp_dir().join(format!("credits-{}.jsonl", rand::random::<u64>()));
+        let updates = deposits(Address::repeat_byte(0xb));
+        let credit = |update: &WatchUpdate| match update {
+            WatchUpdate::Confirmed(event) => Credit::from_event(event).unwrap(),
+            _ => unreachable!(),
+        };
+
+        // Падение во время записи второй строки
+        let mut file = std::fs::File::create(&path).unwrap();
+        writeln!(
+            file,
+            "{}",
+            serde_json::to_string(&credit(&updates[0])).unwrap()
+        )
+        .unwrap();
+        write!(file, r#"{{"id":{{"tx_hash":"#).unwrap();
+        assert_eq!(FileSink::read(&path).unwrap().len(), 1);
+
+        let mut sink = FileSink::open(&path).unwrap();
+        assert!(sink.credit(&credit(&updates[2])).await.unwrap());
+        let credits = FileSink::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(credits.len(), 2);
+    }
+
+    /// Депозит, зачисленный до перезапуска, отменяется при реорганизации после него
+    #[tokio::test]
+    async fn retract_after_restart() {
+        let asserter = Asserter::new();
+        let provider = mock_provider(&asserter);
+        let bridge = Address::repeat_byte(0xb);
+        let dir = std::env::temp_dir().join(format!("relayer-{}", rand::random::<u64>()));
+        std::fs::create_dir(&dir).unwrap();
+        let state_path = dir.join("relayer.json");
+        let credits_path = dir.join("credits.jsonl");
+        let checkpoint = dir.join("watcher.json");
+        let relayer = || {
+            Relayer::new(
+                bridge,
+                FileSink::open(&credits_path).unwrap(),
+                &state_path,
+                5,
+            )
+            .unwrap()
+        };
+        let block = |hash| {
+            let mut block: Block = Block::default();
+            block.header.hash = hash;
+            block
+        };
+
+        // Вершина 10, одно подтверждение: депозит в блоке 7 зачислен, но процесс упал
+        // до обработки отметки о прогрессе
+        let mut first = relayer();
+        let mut watcher =
+            Watcher::new(Relayer::<FileSink>::filter(bridge), 1, Some(&checkpoint), 5).unwrap();
+        let mut log = deposit_logs(bridge).remove(0);
+        log.block_number = Some(7);
+        log.block_hash = Some(B256::with_last_byte(1));
+        asserter.push_success(&10_u64);
+        asserter.push_success(&block(B256::with_last_byte(9)));
+        asserter.push_success(&vec![log]);
+        asserter.push_success(&block(B256::with_last_byte(9)));
+        let Polled { updates, state } = watcher.poll(&provider).await.unwrap();
+        assert!(first.handle(&updates[0]).await.unwrap());
+        watcher.commit(state).unwrap();
+        drop((first, watcher));
+
+        // Перезапуск: наблюдатель продолжает с блока ретранслятора и помнит зачисление
+        let mut second = relayer();
+        assert_eq!(second.state().next_block, 5);
+        second.resume_watcher(&checkpoint).await.unwrap();
+        let watcher = Watcher::new(
+            Relayer::<FileSink>::filter(bridge),
+            1,
+            Some(&checkpoint),
+            second.state().next_block,
+        )
+        .unwrap();
+        assert_eq!(watcher.state().next_block, 5);
+        assert_eq!(watcher.state().recent.len(), 1);
+
+        // Блок 9 заменён, блок 7 тоже
+        asserter.push_success(&11_u64);
+        asserter.push_success(&block(B256::with_last_byte(0x99)));
+        asserter.push_success(&block(B256::with_last_byte(2)));
+        let Polled { updates, .. } = watcher.poll(&provider).await.unwrap();
+        let mut retracted = 0;
+        for update in &updates {
+            retracted += second.handle(update).await.unwrap() as usize;
+        }
+        let credits = FileSink::read(&credits_path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(retracted, 1);
+        assert!(credits.is_empty());
+        assert!(asserter.read_q().is_empty());
+    }
+
+    /// Получатель, который не сразу принимает зачисления
+    #[derive(Default)]
+    struct FlakySink {
+        failures: usize,
+        inner: MemorySink,
+    }
+
+    impl L2Sink for FlakySink {
+        async fn credit(&mut self, credit: &Credit) -> eyre::Result<bool> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                eyre::bail!("l2 недоступен");
+            }
+            self.inner.credit(credit).await
+        }
+
+        async fn retract(&mut self, id: &DepositId) -> eyre::Result<bool> {
+            self.inner.retract(id).await
+        }
+
+        async fn credits_since(&self, block_number: u64) -> eyre::Result<Vec<Credit>> {
+            self.inner.credits_since(block_number).await
+        }
+    }
+
+    /// Изменения из шины обрабатываются по порядку, ошибка получателя повторяется
+    #[tokio::test]
+    async fn run_from_bus() {
+        let bridge = Address::repeat_byte(0xb);
+        let state_path =
+            std::env::temp_dir().join(format!("relayer-{}.json", rand::random::<u64>()));
+        let sink = FlakySink {
+            failures: 1,
+            ..Default::default()
+        };
+        let mut relayer = Relayer::new(bridge, sink, &state_path, 5).unwrap();
+
+        let bus = EventBus::new();
+        let mut updates = bus.subscribe(16, Overflow::Block).unwrap();
+        for update in deposits(bridge)
+            .into_iter()
+            .chain([WatchUpdate::Processed(6)])
+        {
+            bus.publish(update).await.unwrap();
+        }
+        drop(bus);
+
+        let changed = relayer
+            .run(&mut updates, std::future::pending())
+            .await
+            .unwrap();
+        let state = RelayerState::load(&state_path).unwrap().unwrap();
+        std::fs::remove_file(&state_path).unwrap();
+
+        assert_eq!(changed, 2);
+        assert_eq!(relayer.sink().inner.credits.len(), 2);
+        assert_eq!(state.next_block, 7);
+    }
+}
+
+/// Исполнитель заявок на вывод
+mod executor {
+    use std::{io::Write, time::Duration};
+
+    use alloy::{
+        eips::eip2718::Encodable2718,
+        network::TransactionBuilder,
+        primitives::{Address, U64, U256},
+        providers::WalletProvider as _,
+        rpc::types::{TransactionReceipt, TransactionRequest},
+        transports::mock::Asserter,
+    };
+    use tracing_test::traced_test;
+
+    use super::mock_wallet_provider;
+    use crate::{
+        contracts::{
+            Bridge::{self, BridgeInstance},
+            WalletProvider,
+        },
+        deployer::Deployer,
+        executor::{
+            ExecutorState, FileSource, IntentStatus, L2Source, MemorySource, WithdrawalExecutor,
+            WithdrawalIntent,
+        },
+        tests::init,
+    };
+
+    fn intent(id: &str, amount: u64) -> WithdrawalIntent {
+        WithdrawalIntent {
+            id: id.to_string(),
+            to: Address::repeat_byte(0xa),
+            token: None,
+            amount,
+        }
+    }
+
+    #[test]
+    fn accept_once() {
+        let mut state = ExecutorState::default();
+        assert_eq!(state.accept(vec![intent("1", 1), intent("2", 2)]), 2);
+        assert_eq!(state.accept(vec![intent("1", 1), intent("2", 5)]), 0);
+        assert_eq!(state.intents["2"].intent.amount, 2);
+
+        assert!(!state.retry("1"));
+        state.intents.get_mut("1").unwrap().status = IntentStatus::Failed {
+            reason: "revert".to_string(),
+        };
+        assert!(state.retry("1"));
+        assert_eq!(state.intents["1"].status, IntentStatus::Pending);
+    }
+
+    #[tokio::test]
+    async fn file_source() {
+        let path = std::env::temp_dir().join(format!("intents-{}.jsonl", rand::random::<u64>()));
+        let mut source = FileSource::new(&path);
+        assert!(source.fetch().await.unwrap().is_empty());
+
+        let mut file = std::fs::File::create(&path).unwrap();
+        writeln!(file, "{}", serde_json::to_string(&intent("1", 1)).unwrap()).unwrap();
+        // Недописанная строка не читается
+        write!(file, r#"{{"id":"2","#).unwrap();
+        assert_eq!(source.fetch().await.unwrap(), vec![intent("1", 1)]);
+
+        writeln!(file, r#""to":"{}","amount":2}}"#, Address::repeat_byte(0xa)).unwrap();
+        assert_eq!(source.fetch().await.unwrap(), vec![intent("2", 2)]);
+        assert!(source.fetch().await.unwrap().is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resubmit_when_nonce_taken() {
+        let asserter = Asserter::new();
+        let provider = mock_wallet_provider(&asserter);
+        let owner = provider.default_signer_address();
+        let wallet = provider.wallet().clone();
+        let bridge = Bridge::new(Address::repeat_byte(0xb), provider);
+
+        let envelope = TransactionRequest::default()
+            .with_from(owner)
+            .with_to(*bridge.address())
+            .with_nonce(0)
+            .with_chain_id(1)
+            .with_gas_limit(100_000)
+            .with_max_fee_per_gas(100)
+            .with_max_priority_fee_per_gas(10)
+            .build(&wallet)
+            .await
+            .unwrap();
+        let tx_hash = *envelope.tx_hash();
+        let mut state = ExecutorState::default();
+        state.accept(vec![intent("1", 1)]);
+        state.intents.get_mut("1").unwrap().status = IntentStatus::Submitted {
+            tx_hash,
+            raw: envelope.encoded_2718().into(),
+        };
+        let state_path =
+            std::env::temp_dir().join(format!("executor-{}.json", rand::random::<u64>()));
+        state.save(&state_path).unwrap();
+
+        // Квитанции нет, но nonce 0 уже использован другой транзакцией owner
+        asserter.push_success(&Option::<TransactionReceipt>::None);
+        asserter.push_success(&U64::from(1));
+        asserter.push_success(&Option::<TransactionReceipt>::None);
+        let mut executor =
+            WithdrawalExecutor::new(bridge, MemorySource::default(), &state_path).unwrap();
+        executor.step().await.unwrap();
+        std::fs::remove_file(&state_path).unwrap();
+
+        assert_eq!(executor.state().intents["1"].status, IntentStatus::Pending);
+        assert!(asserter.read_q().is_empty());
+    }
+
+    /// Ошибка узла не останавливает исполнитель, проверка повторяется после задержки
+    #[tokio::test]
+    async fn retry_after_node_error() {
+        let asserter = Asserter::new();
+        let provider = mock_wallet_provider(&asserter);
+        let owner = provider.default_signer_address();
+        let wallet = provider.wallet().clone();
+        let bridge = Bridge::new(Address::repeat_byte(0xb), provider);
+
+        let envelope = TransactionRequest::default()
+            .with_from(owner)
+            .with_to(*bridge.address())
+            .with_nonce(0)
+            .with_chain_id(1)
+            .with_gas_limit(100_000)
+            .with_max_fee_per_gas(100)
+            .with_max_priority_fee_per_gas(10)
+            .build(&wallet)
+            .await
+            .unwrap();
+        let mut state = ExecutorState::default();
+        state.accept(vec![intent("1", 1)]);
+        state.intents.get_mut("1").unwrap().status = IntentStatus::Submitted {
+            tx_hash: *envelope.tx_hash(),
+            raw: envelope.encoded_2718().into(),
+        };
+        let state_path =
+            std::env::temp_dir().join(format!("executor-{}.json", rand::random::<u64>()));
+        state.save(&state_path).unwrap();
+
+        asserter.push_failure_msg("boom");
+        asserter.push_success(&Option::<TransactionReceipt>::None);
+        asserter.push_success(&U64::from(1));
+        asserter.push_success(&Option::<TransactionReceipt>::None);
+        let mut executor =
+            WithdrawalExecutor::new(bridge, MemorySource::default(), &state_path).unwrap();
+        executor
+            .run(
+                Duration::from_secs(60),
+                tokio::time::sleep(Duration::from_millis(1500)),
+            )
+            .await
+            .unwrap();
+        std::fs::remove_file(&state_path).unwrap();
+
+        assert_eq!(executor.state().intents["1"].status, IntentStatus::Pending);
+        assert!(asserter.read_q().is_empty());
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn approve_once() {
+        let acc = init().await.unwrap();
+        let owner = acc[0].clone();
+        let alice = acc[1].clone();
+        let owner_bridge = Deployer::connect(&owner)
+            .await
+            .unwrap()
+            .contract::<BridgeInstance<WalletProvider>>()
+            .await
+            .unwrap();
+        let alice_bridge = Deployer::connect(&alice)
+            .await
+            .unwrap()
+            .contract::<BridgeInstance<WalletProvider>>()
+            .await
+            .unwrap();
+
+        let state_path =
+            std::env::temp_dir().join(format!("executor-{}.json", rand::random::<u64>()));
+        let id = format!("withdraw-{}", rand::random::<u64>());
+        let intent = WithdrawalIntent {
+            id: id.clone(),
+            to: alice.address(),
+            token: None,
+            amount: 1,
+        };
+
+        let old_amount = alice_bridge.available_to_withdraw().call().await.unwrap();
+
+        let mut source = MemorySource::default();
+        source.push(intent.clone());
+        let mut executor =
+            WithdrawalExecutor::new(owner_bridge.into_inner(), source, &state_path).unwrap();
+        while !matches!(
+            executor.state().intents[&id].status,
+            IntentStatus::Confirmed { .. }
+        ) {
+            executor.step().await.unwrap();
+            assert!(
+                !matches!(
+                    executor.state().intents[&id].status,
+                    IntentStatus::Failed { .. }
+                ),
+                "{:?}",
+                executor.state().intents[&id]
+            );
+        }
+
+        // Повторная заявка с тем же идентификатором не одобряется
+        executor.source_mut().push(intent);
+        executor.step().await.unwrap();
+        std::fs::remove_file(&state_path).unwrap();
+
+        let new_amount = alice_bridge.available_to_withdraw().call().await.unwrap();
+        assert_eq!(new_amount - old_amount, U256::from(10_u64.pow(10)));
+    }
+}
+
+mod watcher {
+    use std::time::Duration;
+
+    use alloy::{
+        primitives::{Address, B256, keccak256},
+        rpc::types::Block,
+        transports::mock::Asserter,
+    };
+
+    use super::{event_log, mock_provider};
+    use crate::{
+        bus::{EventBus, Overflow},
+        contracts::Bridge,
+        events::EventFilter,
+        watcher::{Polled, WatchUpdate, Watcher, WatcherState},
+    };
+
+    fn block(hash: B256) -> Block {
+        let mut block: Block = Block::default();
+        block.header.hash = hash;
+        block
+    }
+
+    #[tokio::test]
+    async fn retract_on_reorg() {
+        let asserter = Asserter::new();
+        let provider = mock_provider(&asserter);
+        let dir = std::env::temp_dir().join(format!("watcher-{}", rand::random::<u64>()));
+        std::fs::create_dir(&dir).unwrap();
+        let state_path = dir.join("watcher.json");
+
+        let bridge = Address::repeat_byte(0xb);
+        let alice = Address::repeat_byte(0xa);
+        let deposit = |block_number, fork| {
+            let mut log = event_log(
+                bridge,
+                &Bridge::EventDeposit {
+                    from: alice,
+                    to: alice,
+                    value: 1,
+                },
+                block_number,
+                0,
+            );
+            log.block_hash = Some(B256::with_last_byte(fork));
+            log
+        };
+        let filter = EventFilter::new().address(bridge);
+
+        // Вершина 10, одно подтверждение: обрабатываются блоки 5..=9
+        let mut watcher = Watcher::new(filter.clone(), 1, Some(&state_path), 5).unwrap();
+        asserter.push_success(&10_u64);
+        asserter.push_success(&block(B256::with_last_byte(9)));
+        asserter.push_success(&vec![deposit(7, 1)]);
+        asserter.push_success(&block(B256::with_last_byte(9)));
+        let Polled { updates, state } = watcher.poll(&provider).await.unwrap();
+        assert!(matches!(
+            &updates[..],
+            [WatchUpdate::Confirmed(event), WatchUpdate::Processed(9)]
+                if event.meta.block_number == 7
+        ));
+        // До commit состояние не меняется
+        assert_eq!(watcher.state().next_block, 5);
+        watcher.commit(state).unwrap();
+
+        // Перезапуск: прогресс читается из файла
+        let mut watcher = Watcher::new(filter, 1, Some(&state_path), 0).unwrap();
+        assert_eq!(watcher.state().next_block, 10);
+
+        // Блок 9 заменён: событие из блока 7 отзывается
+        asserter.push_success(&11_u64);
+        asserter.push_success(&block(B256::with_last_byte(0x99)));
+        asserter.push_success(&block(B256::with_last_byte(2)));
+        let Polled { updates, state } = watcher.poll(&provider).await.unwrap();
+        assert!(matches!(
+            &updates[..],
+            [WatchUpdate::Retracted(old), WatchUpdate::Processed(4)] if old.block_number == 7
+        ));
+        watcher.commit(state).unwrap();
+        assert_eq!(watcher.state().next_block, 5);
+
+        // Блок 10 заменён во время чтения логов: события не выдаются
+        asserter.push_success(&11_u64);
+        asserter.push_success(&block(B256::with_last_byte(10)));
+        asserter.push_success(&vec![deposit(8, 2)]);
+        asserter.push_success(&block(B256::with_last_byte(0x10)));
+        let Polled { updates, state } = watcher.poll(&provider).await.unwrap();
+        assert!(updates.is_empty());
+        assert_eq!(&state, watcher.state());
+
+        // Депозит попал в блок 8 новой цепочки
+        asserter.push_success(&11_u64);
+        asserter.push_success(&block(B256::with_last_byte(0x10)));
+        asserter.push_success(&vec![deposit(8, 2)]);
+        asserter.push_success(&block(B256::with_last_byte(0x10)));
+        let Polled { updates, state } = watcher.poll(&provider).await.unwrap();
+        assert!(matches!(
+            &updates[..],
+            [WatchUpdate::Confirmed(new), WatchUpdate::Processed(10)]
+                if new.meta.block_number == 8
+        ));
+        watcher.commit(state).unwrap();
+
+        let state = WatcherState::load(&state_path).unwrap().unwrap();
+        assert_eq!(state.next_block, 11);
+        assert_eq!(state.start_block, 5);
+        assert_eq!(state.recent.len(), 1);
+        assert!(asserter.read_q().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Ошибка публикации перезапускает наблюдение, и неопубликованные события выдаются снова
+    #[tokio::test]
+    async fn republish_after_failure() {
+        let asserter = Asserter::new();
+        let provider = mock_provider(&asserter);
+        let bridge = Address::repeat_byte(0xb);
+        let logs = (7..=8)
+            .map(|block_number| {
+                let event = Bridge::EventDeposit {
+                    from: Address::repeat_byte(0xa),
+                    to: Address::repeat_byte(0xa),
+                    value: block_number,
+                };
+                event_log(bridge, &event, block_number, 0)
+            })
+            .collect::<Vec<_>>();
+        let poll = || {
+            asserter.push_success(&10_u64);
+            asserter.push_success(&block(keccak256(9_u64.to_be_bytes())));
+            asserter.push_success(&logs);
+            asserter.push_success(&block(keccak256(9_u64.to_be_bytes())));
+        };
+
+        let bus = EventBus::new();
+        let mut received = bus.subscribe(16, Overflow::Block).unwrap();
+        // Запись в файл очереди не удаётся: второе событие не публикуется
+        let spill = std::env::temp_dir().join(format!("spill-{}", rand::random::<u64>()));
+        std::os::unix::fs::symlink("/dev/full", &spill).unwrap();
+        let failing = bus.subscribe(1, Overflow::Spill(spill.clone())).unwrap();
+
+        let mut watcher = Watcher::new(EventFilter::new().address(bridge), 1, None, 5).unwrap();
+        poll();
+        let shutdown = tokio::time::sleep(Duration::from_secs(1));
+        assert!(
+            watcher
+                .run(&provider, Duration::from_secs(60), &bus, shutdown)
+                .await
+                .is_err()
+        );
+        assert_eq!(watcher.state().next_block, 5);
+
+        // Перезапуск задачи без сломанного подписчика
+        drop(failing);
+        assert!(!spill.exists());
+        poll();
+        let shutdown = tokio::time::sleep(Duration::from_millis(100));
+        watcher
+            .run(&provider, Duration::from_secs(60), &bus, shutdown)
+            .await
+            .unwrap();
+        assert_eq!(watcher.state().next_block, 10);
+        drop(bus);
+
+        let mut confirmed = Vec::new();
+        while let Some(update) = received.recv().await {
+            if let WatchUpdate::Confirmed(event) = update {
+                confirmed.push(event.meta.block_number);
+            }
+        }
+        // Первое событие получено дважды, второе не потеряно
+        assert_eq!(confirmed, [7, 8, 7, 8]);
+        assert!(asserter.read_q().is_empty());
+    }
+}
+
+/// Разбор причин отката
+mod errors {
+    use alloy::{
+        primitives::U256, providers::Provider, rpc::json_rpc::ErrorPayload,
+        transports::mock::Asserter,
+    };
+    use alloy_sol_types::{Panic, Revert, SolError};
+
+    use super::mock_provider;
+    use crate::errors::BridgeError;
+
+    #[test]
+    fn from_revert_data() {
+        for (reason, expected) in [
+            (
+                "This request can only be completed by the owner",
+                BridgeError::NotOwner,
+            ),
+            (
+                "Couldn't round up to the precision of 8",
+                BridgeError::Precision(8),
+            ),
+            ("No withdrawal requests", BridgeError::NoWithdrawalRequests),
+            ("unknown", BridgeError::Revert("unknown".to_string())),
+        ] {
+            let data = Revert::from(reason).abi_encode();
+            let err = BridgeError::from_revert_data(&data);
+            assert_eq!(err, expected);
+            assert_eq!(err.to_string(), reason);
+        }
+
+        let data = Panic {
+            code: U256::from(0x11),
+        }
+        .abi_encode();
+        let err = BridgeError::from_revert_data(&data);
+        assert_eq!(
+            err,
+            BridgeError::Panic {
+                code: U256::from(0x11)
+            }
+        );
+        assert!(err.to_string().contains("overflow"), "{err}");
+
+        assert_eq!(
+            BridgeError::from_revert_data(&[1, 2, 3]),
+            BridgeError::Raw(vec![1, 2, 3].into())
+        );
+    }
+
+    #[tokio::test]
+    async fn from_transport_error() {
+        let asserter = Asserter::new();
+        let provider = mock_provider(&asserter);
+
+        let data = alloy::hex::encode_prefixed(Revert::from("Failed to send").abi_encode());
+        asserter.push_failure(ErrorPayload {
+            code: 3,
+            message: "execution reverted: Failed to send".into(),
+            data: Some(serde_json::value::to_raw_value(&data).unwrap()),
+        });
+        let err = provider.get_block_number().await.unwrap_err();
+        assert_eq!(
+            BridgeError::from_transport_error(&err),
+            Some(BridgeError::TransferFailed)
+        );
+
+        // Узел без данных отката
+        asserter.push_failure(ErrorPayload {
+            code: -32000,
+            message: "execution reverted: The token has already been added".into(),
+            data: None,
+        });
+        let err = provider.get_block_number().await.unwrap_err();
+        assert_eq!(
+            BridgeError::from_transport_error(&err),
+            Some(BridgeError::TokenAlreadyAdded)
+        );
+
+        asserter.push_failure_msg("boom");
+        let err = provider.get_block_number().await.unwrap_err();
+        assert_eq!(BridgeError::from_transport_error(&err), None);
+    }
+}
+
+/// Клиент моста
+mod client {
+    use alloy::{
+        eips::BlockId,
+        network::TransactionBuilder,
+        primitives::{Address, B256, Bytes, U256},
+        rpc::{json_rpc::ErrorPayload, types::TransactionRequest},
+        transports::mock::Asserter,
+    };
+    use alloy_sol_types::{Revert, SolCall, SolError};
+    use tracing_test::traced_test;
+
+    use super::{mock_provider, mock_wallet_provider};
+    use crate::{
+        client::{BridgeClient, revert_error},
+        contracts::Bridge,
+        errors::BridgeError,
+        events::{BridgeEvent, EventKind},
+        tests::{Fixture, init},
+    };
+
+    #[tokio::test]
+    #[traced_test]
+    async fn deposit_outcome() {
+        let acc = init().await.unwrap();
+        let alice = acc[1].clone();
+        let client = BridgeClient::connect(alice.clone()).await.unwrap();
+
+        let outcome = client
+            .deposit(alice.address(), U256::from(10_u64.pow(10)))
+            .await
+            .unwrap();
+        assert!(outcome.gas_used > 0);
+        assert!(matches!(
+            &outcome.events[..],
+            [event] if event.event == BridgeEvent::Deposit(crate::contracts::Bridge::EventDeposit {
+                from: alice.address(),
+                to: alice.address(),
+                value: 1,
+            })
+        ));
+
+        // Откат возвращается как ошибка с причиной
+        let err = client
+            .deposit(alice.address(), U256::from(1))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            BridgeError::from_report(&err),
+            Some(BridgeError::Precision(8))
+        );
+    }
+
+    /// Сумма сверх `MAX_AMOUNT` отклоняется до отправки: контракт обрезал бы её до `uint64`
+    #[tokio::test]
+    async fn deposit_over_max_amount() {
+        let asserter = Asserter::new();
+        let provider = mock_wallet_provider(&asserter);
+        let client = BridgeClient::new(Bridge::new(Address::repeat_byte(0xb), provider));
+
+        let amount = (U256::from(u64::MAX) + U256::from(1)) * U256::from(10_u64.pow(10));
+        let err = client
+            .deposit(Address::repeat_byte(0xa), amount)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            BridgeError::from_report(&err),
+            Some(BridgeError::AmountExceedsMaximum)
+        );
+        let err = client
+            .deposit(Address::repeat_byte(0xa), U256::from(1))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            BridgeError::from_report(&err),
+            Some(BridgeError::Precision(8))
+        );
+    }
+
+    /// Откат в блоке возвращается с причиной из повторного `eth_call`
+    #[tokio::test]
+    async fn revert_in_block() {
+        let asserter = Asserter::new();
+        let provider = mock_provider(&asserter);
+        let request = TransactionRequest::default()
+            .with_from(Address::repeat_byte(0xa))
+            .with_to(Address::repeat_byte(0xb))
+            .with_input(Bridge::withdrawCall {}.abi_encode());
+
+        let data = alloy::hex::encode_prefixed(
+            Revert::from("There are no funds for withdrawal").abi_encode(),
+        );
+        asserter.push_failure(ErrorPayload {
+            code: 3,
+            message: "execution reverted: There are no funds for withdrawal".into(),
+            data: Some(serde_json::value::to_raw_value(&data).unwrap()),
+        });
+        let err = revert_error(&provider, request.clone(), B256::ZERO, BlockId::number(5)).await;
+        assert_eq!(
+            err.downcast_ref::<BridgeError>(),
+            Some(&BridgeError::NoFundsForWithdrawal)
+        );
+
+        // Вызов не откатился: причина неизвестна
+        asserter.push_success(&Bytes::new());
+        let err = revert_error(&provider, request, B256::ZERO, BlockId::number(5)).await;
+        assert_eq!(
+            err.downcast_ref::<BridgeError>(),
+            Some(&BridgeError::Raw(Bytes::new()))
+        );
+        assert!(asserter.read_q().is_empty());
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn deposit_erc20_with_approval() {
+        let fixture = Fixture::new().await.unwrap();
+        let owner = &fixture.owner;
+        let demo_address = fixture.tokens[0];
+
+        let owner_client = BridgeClient::new(fixture.bridge(owner).await);
+        owner_client.create_bridge(demo_address).await.unwrap();
+
+        let amount = U256::from(10).pow(U256::from(10));
+        let outcome = owner_client
+            .deposit_erc20(demo_address, owner.address(), amount)
+            .await
+            .unwrap();
+        let approval = outcome.approval.expect("новый мост без одобрения");
+        assert!(
+            approval
+                .events
+                .iter()
+                .any(|v| v.event.kind() == EventKind::Approval)
+        );
+        assert!(
+            outcome
+                .deposit
+                .events
+                .iter()
+                .any(|v| v.event.kind() == EventKind::DepositErc20)
+        );
+    }
+}
+
+/// Перевод сумм между точностями
+mod amount {
+    use alloy::primitives::U256;
+    use proptest::{prelude::*, test_runner::TestRunner};
+
+    use crate::{
+        amount::{
+            MAX_AMOUNT, TokenAmount, TokenUnits, convert_amount, convert_amount_to_l1,
+            convert_amount_to_l2, l2_decimals,
+        },
+        contracts::Bridge,
+        errors::BridgeError,
+        tests::{Fixture, revert_reason},
+    };
+
+    /// Суммы от маленьких до близких к `uint256::MAX`
+    fn any_amount() -> impl Strategy<Value = U256> {
+        prop_oneof![
+            any::<u64>().prop_map(U256::from),
+            (any::<u64>(), 0..30_u32)
+                .prop_map(|(v, p)| U256::from(v) * U256::from(10).pow(U256::from(p))),
+            any::<[u64; 4]>().prop_map(U256::from_limbs),
+        ]
+    }
+
+    #[test]
+    fn examples() {
+        assert_eq!(
+            convert_amount(U256::from(1_234), 0, 3),
+            Ok(U256::from(1_234_000))
+        );
+        assert_eq!(convert_amount(U256::from(1_230), 3, 2), Ok(U256::from(123)));
+        assert_eq!(
+            convert_amount(U256::from(1_230), 3, 0),
+            Err(BridgeError::Precision(0))
+        );
+        assert!(matches!(
+            convert_amount(U256::from(1), 0, 78),
+            Err(BridgeError::Panic { .. })
+        ));
+        assert!(matches!(
+            convert_amount(U256::MAX, 0, 1),
+            Err(BridgeError::Panic { .. })
+        ));
+
+        let info = Bridge::BridgeTokenInfo {
+            turn: true,
+            name: "Demo".to_string(),
+            symbol: "DEMO".to_string(),
+            base_decimals: 18,
+            decimals: 8,
+        };
+        let max_l1 = MAX_AMOUNT * U256::from(10).pow(U256::from(10));
+        assert_eq!(convert_amount_to_l2(&info, max_l1), Ok(MAX_AMOUNT));
+        assert_eq!(
+            convert_amount_to_l2(&info, max_l1 + U256::from(10).pow(U256::from(10))),
+            Err(BridgeError::AmountExceedsMaximum)
+        );
+        assert_eq!(convert_amount_to_l1(&info, MAX_AMOUNT), Ok(max_l1));
+
+        let info = Bridge::BridgeTokenInfo {
+            turn: false,
+            ..info
+        };
+        assert_eq!(
+            convert_amount_to_l2(&info, U256::ZERO),
+            Err(BridgeError::BridgeNotCreated)
+        );
+    }
+
+    #[test]
+    fn token_amount() {
+        let eth = TokenUnits::eth();
+        let amount = eth.parse("1.5 ETH").unwrap();
+        assert_eq!(
+            amount.value(),
+            U256::from(15) * U256::from(10).pow(U256::from(17))
+        );
+        assert_eq!(eth.format(amount), "1.5 ETH");
+        assert_eq!(eth.parse("0.5").unwrap().to_string(), "0.5");
+        assert_eq!(TokenAmount::from_tokens(10, 18).to_string(), "10");
+        assert_eq!(TokenAmount::new(U256::from(1), 8).to_string(), "0.00000001");
+        assert_eq!(TokenAmount::new(U256::from(1_230), 0).to_string(), "1230");
+
+        assert!(eth.parse("1.5 DERC").is_err());
+        assert!(eth.parse("1,5").is_err());
+        assert!(eth.parse("").is_err());
+        // Больше знаков, чем точность токена
+        assert!(TokenAmount::parse("0.001", 2).is_err());
+
+        // l1 => l2 только без потери разрядов
+        assert_eq!(
+            eth.to_l2(eth.parse("0.00000001").unwrap()),
+            Ok(TokenAmount::new(U256::from(1), 8))
+        );
+        assert_eq!(
+            eth.to_l2(eth.parse("0.000000001").unwrap()),
+            Err(BridgeError::Precision(8))
+        );
+        assert_eq!(
+            eth.to_l1(eth.l2(U256::from(1))).unwrap(),
+            eth.parse("0.00000001").unwrap()
+        );
+
+        // Токен с точностью меньше l2
+        let units = TokenUnits {
+            symbol: "EXM".to_string(),
+            decimals: 6,
+            l2_decimals: l2_decimals(6),
+        };
+        let amount = units.parse("1.000001 exm").unwrap();
+        assert_eq!(units.to_l2(amount).unwrap().value(), U256::from(1_000_001));
+    }
+
+    proptest! {
+        /// Разбор отформатированной суммы возвращает ту же сумму
+        #[test]
+        fn format_parse(amount in any_amount(), decimals in 0..40_u8) {
+            let amount = TokenAmount::new(amount, decimals);
+            prop_assert_eq!(TokenAmount::parse(&amount.to_string(), decimals).unwrap(), amount);
+        }
+
+        /// Увеличение точности и обратное уменьшение возвращают исходную сумму
+        #[test]
+        fn round_trip(amount in any::<u128>(), from in 0..20_u8, up in 0..20_u8) {
+            let amount = U256::from(amount);
+            let to = from + up;
+            let converted = convert_amount(amount, from, to).unwrap();
+            prop_assert_eq!(convert_amount(converted, to, from), Ok(amount));
+        }
+
+        /// Уменьшение точности отказывает только при ненулевых младших разрядах
+        #[test]
+        fn precision(amount in any_amount(), from in 0..40_u8, to in 0..40_u8) {
+            prop_assume!(from > to);
+            let r = U256::from(10).pow(U256::from(from - to));
+            match convert_amount(amount, from, to) {
+                Ok(v) => prop_assert_eq!(v * r, amount),
+                Err(err) => {
+                    prop_assert_eq!(err, BridgeError::Precision(to));
+                    prop_assert!(amount % r != U256::ZERO);
+                }
+            }
+        }
+    }
+
+    /// Сравнение с контрактом на случайных входных данных
+    #[tokio::test(flavor = "multi_thread")]
+    async fn differential() {
+        let fixture = Fixture::new().await.unwrap();
+        fixture.create_bridges().await.unwrap();
+        let bridge = fixture.bridge(&fixture.owner).await;
+        let demo_address = fixture.tokens[0];
+        let info = bridge
+            .status_bridge_erc20(demo_address)
+            .call()
+            .await
+            .unwrap();
+        assert!(info.turn);
+
+        let handle = tokio::runtime::Handle::current();
+        let mut runner = TestRunner::new(ProptestConfig::with_cases(64));
+        runner
+            .run(&(any_amount(), 0..90_u8, 0..90_u8), |(amount, from, to)| {
+                tokio::task::block_in_place(|| {
+                    handle.block_on(async {
+                        let res = bridge.convert_amount(amount, from, to).call().await;
+                        let onchain = res
+                            .as_ref()
+                            .map(|v| *v)
+                            .map_err(|_| revert_reason(&res).expect("Неизвестная ошибка"));
+                        prop_assert_eq!(convert_amount(amount, from, to), onchain);
+
+                        let res = bridge
+                            .convert_amount_to_l2(demo_address, amount)
+                            .call()
+                            .await;
+                        let onchain = res
+                            .as_ref()
+                            .map(|v| *v)
+                            .map_err(|_| revert_reason(&res).expect("Неизвестная ошибка"));
+                        prop_assert_eq!(convert_amount_to_l2(&info, amount), onchain);
+
+                        let res = bridge
+                            .convert_amount_to_l1(demo_address, amount)
+                            .call()
+                            .await;
+                        let onchain = res
+                            .as_ref()
+                            .map(|v| *v)
+                            .map_err(|_| revert_reason(&res).expect("Неизвестная ошибка"));
+                        prop_assert_eq!(convert_amount_to_l1(&info, amount), onchain);
+                        Ok(())
+                    })
+                })
+            })
+            .unwrap();
+    }
+}
+
+/// Контракты от имени аккаунта
+mod deployer {
+    use alloy::{
+        primitives::{Address, Bytes, U256},
+        transports::mock::Asserter,
+    };
+
+    use super::mock_wallet_provider;
+    use crate::{
+        contracts::{Bridge::BridgeInstance, WalletProvider},
+        deployer::{Contract, Deployer, token_balances},
+    };
+
+    fn deployer(asserter: &Asserter) -> Deployer {
+        Deployer::new(mock_wallet_provider(asserter))
+    }
+
+    #[test]
+    fn contract_at() {
+        let deployer = deployer(&Asserter::new());
+        let bridge = deployer.at::<BridgeInstance<WalletProvider>>(Address::repeat_byte(0xb));
+        assert_eq!(bridge.address(), Address::repeat_byte(0xb));
+        assert_eq!(*bridge.into_inner().address(), Address::repeat_byte(0xb));
+        assert_eq!(<BridgeInstance<WalletProvider>>::NAME, "Bridge");
+    }
+
+    #[tokio::test]
+    async fn balances() {
+        let asserter = Asserter::new();
+        let deployer = deployer(&asserter);
+        for balance in [5_u64, 7] {
+            asserter.push_success(&Bytes::from(U256::from(balance).to_be_bytes::<32>()));
+        }
+
+        let tokens = [Address::repeat_byte(1), Address::repeat_byte(2)];
+        let balances = token_balances(deployer.provider(), Address::repeat_byte(0xa), &tokens)
+            .await
+            .unwrap();
+        assert_eq!(balances, [U256::from(5), U256::from(7)]);
+    }
+}
+
+/// События через WebSocket
+mod pubsub {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    use alloy::{
+        consensus,
+        primitives::{Address, U64},
+        rpc::types::{Filter, Header},
+    };
+    use futures_util::{SinkExt, StreamExt};
+    use serde_json::{Value, json};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::Message;
+
+    use super::event_log;
+    use crate::{
+        config::Profile,
+        contracts::Bridge,
+        events::{BridgeEvent, EventFilter},
+        pubsub::subscribe_ws,
+    };
+
+    fn deposit(block_number: u64) -> alloy::rpc::types::Log {
+        let alice = Address::repeat_byte(0xa);
+        event_log(
+            Address::repeat_byte(0xb),
+            &Bridge::EventDeposit {
+                from: alice,
+                to: alice,
+                value: block_number,
+            },
+            block_number,
+            0,
+        )
+    }
+
+    /// Профиль сети 1 с узлом `url`. Общий профиль процесса не загружается
+    fn profile(url: String) -> Profile {
+        Profile {
+            chain_id: 1,
+            ws_url: Some(url),
+            ..Default::default()
+        }
+    }
+
+    fn head(number: u64) -> Value {
+        serde_json::to_value(Header::new(consensus::Header {
+            number,
+            ..Default::default()
+        }))
+        .unwrap()
+    }
+
+    /// Узел сети `chain_id` с событием `EventDeposit` в каждом блоке. Первое соединение
+    /// обрывается после блока 3, второе начинается с блока 7: блоки 4-6 вышли, пока подписки
+    /// не было
+    async fn serve(listener: TcpListener, chain_id: u64) {
+        let connections = Arc::new(AtomicUsize::new(0));
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let connection = connections.fetch_add(1, Ordering::SeqCst);
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            tokio::spawn(async move {
+                let (block_number, pushed) = if connection == 0 { (2, 3) } else { (6, 7) };
+                while let Some(Ok(Message::Text(text))) = ws.next().await {
+                    let request: Value = serde_json::from_str(&text).unwrap();
+                    let id = request["id"].clone();
+                    let result = match request["method"].as_str().unwrap() {
+                        "eth_chainId" => json!(U64::from(chain_id)),
+                        "eth_blockNumber" => json!(U64::from(block_number)),
+                        "eth_subscribe" => json!("0x1"),
+                        "eth_unsubscribe" => json!(true),
+                        "eth_getLogs" => {
+                            let filter: Filter =
+                                serde_json::from_value(request["params"][0].clone()).unwrap();
+                            let from = filter.get_from_block().unwrap();
+                            let to = filter.get_to_block().unwrap();
+                            json!((from..=to).map(deposit).collect::<Vec<_>>())
+                        }
+                        method => panic!("{method}"),
+                    };
+                    let response = json!({"jsonrpc": "2.0", "id": id, "result": result});
+                    ws.send(Message::text(response.to_string())).await.unwrap();
+
+                    match request["method"].as_str().unwrap() {
+                        "eth_subscribe" => {
+                            let notification = json!({
+                                "jsonrpc": "2.0",
+                                "method": "eth_subscription",
+                                "params": {"subscription": "0x1", "result": head(pushed)},
+                            });
+                            ws.send(Message::text(notification.to_string()))
+                                .await
+                                .unwrap();
+                        }
+                        // Обрыв соединения после чтения блока 3
+                        "eth_getLogs" if connection == 0 => return,
+                        _ => {}
+                    }
+                }
+            });
+        }
+    }
+
+    #[tokio::test]
+    async fn reconnect_without_gaps() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(serve(listener, 1));
+
+        let events = subscribe_ws(&profile(url), &EventFilter::new())
+            .await
+            .unwrap();
+        let values = tokio::time::timeout(
+            Duration::from_secs(10),
+            events
+                .map(|v| match v.event {
+                    BridgeEvent::Deposit(deposit) => deposit.value,
+                    event => panic!("{event:?}"),
+                })
+                .take(5)
+                .collect::<Vec<_>>(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(values, [3, 4, 5, 6, 7]);
+        server.abort();
+    }
+
+    /// Узел другой сети отклоняется при подключении
+    #[tokio::test]
+    async fn wrong_chain() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(serve(listener, 2));
+
+        assert!(
+            subscribe_ws(&profile(url), &EventFilter::new())
+                .await
+                .is_err()
+        );
+        server.abort();
+    }
+}
+
+/// Шина событий
+mod bus {
+    use std::time::Duration;
+
+    use alloy::primitives::Address;
+
+    use crate::{
+        bus::{EventBus, Overflow},
+        contracts::Bridge,
+        events::{EventLog, LogMeta},
+        tests::event_log,
+        watcher::WatchUpdate,
+    };
+
+    /// Каждый подписчик получает все события, после удаления шины - `None`
+    #[tokio::test]
+    async fn fan_out() {
+        let bus = EventBus::new();
+        let mut first = bus.subscribe(4, Overflow::Block).unwrap();
+        let mut second = bus.subscribe(4, Overflow::DropOldest).unwrap();
+
+        let publisher = bus.clone();
+        for value in 0..3u64 {
+            publisher.publish(value).await.unwrap();
+        }
+        drop(publisher);
+        drop(bus);
+
+        for subscriber in [&mut first, &mut second] {
+            let mut values = Vec::new();
+            while let Some(value) = subscriber.recv().await {
+                values.push(value);
+            }
+            assert_eq!(values, [0, 1, 2]);
+        }
+    }
+
+    /// Медленный подписчик теряет старые события, но не задерживает издателя
+    #[tokio::test]
+    async fn drop_oldest() {
+        let bus = EventBus::new();
+        let mut slow = bus.subscribe(2, Overflow::DropOldest).unwrap();
+        for value in 0..5u64 {
+            bus.publish(value).await.unwrap();
+        }
+        drop(bus);
+
+        assert_eq!(slow.dropped(), 3);
+        assert_eq!(slow.recv().await, Some(3));
+        assert_eq!(slow.recv().await, Some(4));
+        assert_eq!(slow.recv().await, None);
+    }
+
+    /// Издатель ждёт подписчика с заполненной очередью, удалённый подписчик его не держит
+    #[tokio::test]
+    async fn block() {
+        let bus = EventBus::new();
+        let mut subscriber = bus.subscribe(1, Overflow::Block).unwrap();
+        bus.publish(0u64).await.unwrap();
+
+        let publish = bus.publish(1);
+        tokio::pin!(publish);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), &mut publish)
+                .await
+                .is_err()
+        );
+        assert_eq!(subscriber.recv().await, Some(0));
+        publish.await.unwrap();
+        assert_eq!(subscriber.recv().await, Some(1));
+
+        bus.publish(2).await.unwrap();
+        drop(subscriber);
+        bus.publish(3).await.unwrap();
+        assert_eq!(bus.subscribers(), 0);
+    }
+
+    /// События сверх очереди сохраняются в файл и читаются по порядку
+    #[tokio::test]
+    async fn spill() {
+        let path = std::env::temp_dir().join(format!("bus-{}.jsonl", rand::random::<u64>()));
+        let bus = EventBus::new();
+        let mut subscriber = bus.subscribe(2, Overflow::Spill(path.clone())).unwrap();
+
+        let bridge = Address::repeat_byte(0xb0);
+        let updates = (1..=5u64)
+            .map(|block_number| {
+                let event = Bridge::EventDeposit {
+                    from: Address::repeat_byte(1),
+                    to: Address::repeat_byte(2),
+                    value: block_number,
+                };
+                let log = event_log(bridge, &event, block_number, 0);
+                WatchUpdate::Confirmed(EventLog::from_log(&log).unwrap().unwrap())
+            })
+            .chain([WatchUpdate::Retracted(LogMeta {
+                address: bridge,
+                block_number: 5,
+                block_hash: Default::default(),
+                tx_hash: Default::default(),
+                log_index: 0,
+            })])
+            .collect::<Vec<_>>();
+
+        for update in &updates[..4] {
+            bus.publish(update.clone()).await.unwrap();
+        }
+        assert_eq!(subscriber.spilled(), 2);
+        assert_eq!(subscriber.recv().await.as_ref(), Some(&updates[0]));
+        for update in &updates[4..] {
+            bus.publish(update.clone()).await.unwrap();
+        }
+        drop(bus);
+
+        let mut received = vec![updates[0].clone()];
+        while let Some(update) = subscriber.recv().await {
+            received.push(update);
+        }
+        assert_eq!(received, updates);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+
+        drop(subscriber);
+        assert!(!path.exists());
+    }
+}
+
+/// Фоновые задачи
+mod tasks {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    use eyre::bail;
+    use tokio::sync::mpsc;
+
+    use crate::tasks::{TaskStatus, TaskSupervisor};
+
+    fn supervisor() -> TaskSupervisor {
+        TaskSupervisor::default().restart_delay(Duration::from_millis(10), Duration::from_secs(1))
+    }
+
+    /// Ошибки и паника перезапускают задачу, штатное завершение - нет
+    #[tokio::test]
+    async fn restart_on_error_and_panic() {
+        let mut tasks = supervisor();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let (sender, mut done) = mpsc::channel(1);
+        tasks.spawn("flaky", {
+            let runs = runs.clone();
+            move |_| {
+                let (runs, sender) = (runs.clone(), sender.clone());
+                async move {
+                    match runs.fetch_add(1, Ordering::SeqCst) {
+                        0 => bail!("узел недоступен"),
+                        1 => panic!("паника"),
+                        _ => {
+                            sender.send(()).await?;
+                            Ok(())
+                        }
+                    }
+                }
+            }
+        });
+
+        done.recv().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        assert_eq!(tasks.health()["flaky"], TaskStatus::Stopped);
+        tasks.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn health() {
+        let mut tasks = TaskSupervisor::default()
+            .restart_delay(Duration::from_secs(60), Duration::from_secs(60));
+        tasks.spawn("broken", |_| async {
+            bail!("узел недоступен")
+        });
+        tasks.spawn("broken", |token| async move {
+            token.cancelled().await;
+            Ok(())
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let health = tasks.health();
+        assert_eq!(
+            health["broken"],
+            TaskStatus::Restarting {
+                restarts: 1,
+                error: "узел недоступен".to_string()
+            }
+        );
+        assert_eq!(health["broken #1"], TaskStatus::Running);
+        assert!(!tasks.is_healthy());
+
+        // Отмена прерывает ожидание перезапуска
+        tokio::time::timeout(Duration::from_secs(1), tasks.shutdown())
+            .await
+            .unwrap();
+    }
+
+    /// При остановке задача отдаёт всё, что успела получить
+    #[tokio::test]
+    async fn shutdown_flushes_events() {
+        let mut tasks = supervisor();
+        let (sender, mut events) = mpsc::channel(100);
+        tasks.spawn("producer", move |token| {
+            let sender = sender.clone();
+            async move {
+                let mut buffered = Vec::new();
+                for i in 0.. {
+                    tokio::select! {
+                        _ = token.cancelled() => break,
+                        _ = tokio::time::sleep(Duration::from_millis(1)) => buffered.push(i),
+                    }
+                }
+                for event in buffered {
+                    sender.send(event).await?;
+                }
+                Ok(())
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        tasks.shutdown().await;
+
+        let mut received = Vec::new();
+        while let Some(event) = events.recv().await {
+            received.push(event);
+        }
+        assert!(!received.is_empty());
+        assert_eq!(received, (0..received.len()).collect::<Vec<_>>());
+    }
+}
+
+/// Локальный индекс операций моста
+mod indexer {
+    use std::collections::HashMap;
+
+    use alloy::{
+        consensus::{
+            Eip658Value, Receipt, ReceiptEnvelope, ReceiptWithBloom, Signed, TxEnvelope, TxLegacy,
+        },
+        primitives::{Address, B256, Bytes, LogData, Signature, TxKind, U256},
+        rpc::types::{Block, BlockTransactions, Header, Log, Transaction, TransactionReceipt},
+        transports::mock::Asserter,
+    };
+    use alloy_sol_types::SolCall;
+
+    use super::{event_log, mock_provider};
+    use crate::{
+        contracts::{Bridge, DemoERC20},
+        indexer::{
+            Indexer, Record, RecordKind, RecordMeta, RecordQuery, block_records, fetch_block,
+            fill_eth_withdrawals,
+        },
+    };
+
+    const BRIDGE: Address = Address::repeat_byte(0xb);
+    const TOKEN: Address = Address::repeat_byte(0x7);
+    const OWNER: Address = Address::repeat_byte(0x1);
+    const ALICE: Address = Address::repeat_byte(0xa);
+    const BOB: Address = Address::repeat_byte(0xc);
+
+    /// Транзакция к мосту с квитанцией
+    fn call(
+        index: u64,
+        from: Address,
+        input: Vec<u8>,
+        status: bool,
+        logs: Vec<Log>,
+    ) -> (Transaction, TransactionReceipt) {
+        let hash = B256::with_last_byte(index as u8 + 1);
+        let tx = TxLegacy {
+            to: TxKind::Call(BRIDGE),
+            input: Bytes::from(input),
+            ..Default::default()
+        };
+        let tx = Transaction {
+            inner: alloy::consensus::transaction::Recovered::new_unchecked(
+                TxEnvelope::Legacy(Signed::new_unchecked(tx, Signature::test_signature(), hash)),
+                from,
+            ),
+            block_hash: None,
+            block_number: Some(1),
+            transaction_index: Some(index),
+            effective_gas_price: None,
+        };
+        let receipt = TransactionReceipt {
+            inner: ReceiptEnvelope::Legacy(ReceiptWithBloom {
+                receipt: Receipt {
+                    status: Eip658Value::Eip658(status),
+                    cumulative_gas_used: 0,
+                    logs,
+                },
+                logs_bloom: Default::default(),
+            }),
+            transaction_hash: hash,
+            transaction_index: Some(index),
+            block_hash: None,
+            block_number: Some(1),
+            gas_used: 0,
+            effective_gas_price: 0,
+            blob_gas_used: None,
+            blob_gas_price: None,
+            from,
+            to: Some(BRIDGE),
+            contract_address: None,
+        };
+        (tx, receipt)
+    }
+
+    fn block(calls: Vec<(Transaction, TransactionReceipt)>) -> (Block, Vec<TransactionReceipt>) {
+        let (transactions, receipts): (Vec<_>, Vec<_>) = calls.into_iter().unzip();
+        let mut header = Header::<alloy::consensus::Header>::default();
+        header.inner.number = 1;
+        header.hash = B256::repeat_byte(0x11);
+        let block = Block {
+            header,
+            uncles: Vec::new(),
+            transactions: BlockTransactions::Full(transactions),
+            withdrawals: None,
+        };
+        (block, receipts)
+    }
+
+    fn records() -> Vec<Record> {
+        let deposit = Bridge::EventDeposit {
+            from: ALICE,
+            to: BOB,
+            value: 5,
+        };
+        let create = Bridge::EventCreateBridge {
+            _0: Bridge::BridgeTokenInfo {
+                turn: true,
+                name: "Demo".to_string(),
+                symbol: "DEMO".to_string(),
+                base_decimals: 18,
+                decimals: 8,
+            },
+        };
+        let transfer = DemoERC20::Transfer {
+            from: BRIDGE,
+            to: ALICE,
+            value: U256::from(700),
+        };
+        // Лог, который не удаётся разобрать, пропускается
+        let mut malformed = event_log(BRIDGE, &deposit, 1, 3);
+        malformed.inner.data = LogData::new_unchecked(malformed.topics().to_vec(), Bytes::new());
+        let (block, receipts) = block(vec![
+            call(
+                0,
+                ALICE,
+                Bridge::depositCall { receiver: BOB }.abi_encode(),
+                true,
+                vec![event_log(BRIDGE, &deposit, 1, 0), malformed],
+            ),
+            call(
+                1,
+                OWNER,
+                Bridge::create_bridge_erc20Call {
+                    tokenContract: TOKEN,
+                }
+                .abi_encode(),
+                true,
+                vec![event_log(BRIDGE, &create, 1, 1)],
+            ),
+            call(
+                2,
+                OWNER,
+                Bridge::apply_withdrawal_request_erc20Call {
+                    tokenContract: TOKEN,
+                    to: ALICE,
+                    amount: 7,
+                }
+                .abi_encode(),
+                true,
+                Vec::new(),
+            ),
+            // Отменённый вывод не записывается
+            call(
+                3,
+                BOB,
+                Bridge::withdrawCall {}.abi_encode(),
+                false,
+                Vec::new(),
+            ),
+            call(
+                4,
+                ALICE,
+                Bridge::withdraw_erc20Call {
+                    tokenContract: TOKEN,
+                }
+                .abi_encode(),
+                true,
+                vec![event_log(TOKEN, &transfer, 1, 2)],
+            ),
+        ]);
+        block_records(BRIDGE, &block, &receipts).unwrap()
+    }
+
+    #[test]
+    fn decode_block() {
+        let records = records();
+        let summary = records
+            .iter()
+            .map(|v| (v.kind, v.sender, v.receiver, v.token, v.amount))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                (
+                    RecordKind::Deposit,
+                    ALICE,
+                    Some(BOB),
+                    None,
+                    Some(U256::from(5))
+                ),
+                (RecordKind::CreateBridge, OWNER, None, Some(TOKEN), None),
+                (
+                    RecordKind::WithdrawalApproval,
+                    OWNER,
+                    Some(ALICE),
+                    Some(TOKEN),
+                    Some(U256::from(7))
+                ),
+                (
+                    RecordKind::Withdrawal,
+                    ALICE,
+                    Some(ALICE),
+                    Some(TOKEN),
+                    Some(U256::from(700))
+                ),
+            ]
+        );
+        assert_eq!(
+            records[0].meta,
+            RecordMeta {
+                block_number: 1,
+                block_hash: B256::repeat_byte(0x11),
+                tx_hash: B256::with_last_byte(1),
+                tx_index: 0,
+                log_index: Some(0),
+            }
+        );
+        assert_eq!(records[2].meta.log_index, None);
+    }
+
+    /// Одобрение и вывод ETH в одном блоке: сумма учитывает одобрение перед выводом
+    #[test]
+    fn eth_withdrawal_same_block() {
+        let (block, receipts) = block(vec![
+            call(
+                0,
+                OWNER,
+                Bridge::apply_withdrawal_request_erc20Call {
+                    tokenContract: TOKEN,
+                    to: BOB,
+                    amount: 7,
+                }
+                .abi_encode(),
+                true,
+                Vec::new(),
+            ),
+            call(
+                1,
+                OWNER,
+                Bridge::apply_withdrawal_requestCall { to: BOB, amount: 2 }.abi_encode(),
+                true,
+                Vec::new(),
+            ),
+            call(
+                2,
+                BOB,
+                Bridge::withdrawCall {}.abi_encode(),
+                true,
+                Vec::new(),
+            ),
+            // Одобрение после вывода не входит в его сумму
+            call(
+                3,
+                OWNER,
+                Bridge::apply_withdrawal_requestCall { to: BOB, amount: 5 }.abi_encode(),
+                true,
+                Vec::new(),
+            ),
+            call(
+                4,
+                BOB,
+                Bridge::withdrawCall {}.abi_encode(),
+                true,
+                Vec::new(),
+            ),
+        ]);
+        let mut records = block_records(BRIDGE, &block, &receipts).unwrap();
+        // Единица l2 в wei
+        let unit = U256::from(10).pow(U256::from(10));
+        fill_eth_withdrawals(&mut records, HashMap::from([(BOB, U256::from(1))])).unwrap();
+
+        let withdrawals = records
+            .iter()
+            .filter(|v| v.kind == RecordKind::Withdrawal)
+            .map(|v| v.amount)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            withdrawals,
+            [
+                Some(U256::from(1) + U256::from(2) * unit),
+                Some(U256::from(5) * unit)
+            ]
+        );
+    }
+
+    #[test]
+    fn query() {
+        let mut indexer = Indexer::in_memory(BRIDGE).unwrap();
+        let records = records();
+        indexer.insert_block(1, &records).unwrap();
+        assert_eq!(indexer.next_block().unwrap(), Some(2));
+
+        let kinds = |query: RecordQuery| {
+            let page = indexer.query(&query).unwrap();
+            assert_eq!(page.next, None);
+            page.records.iter().map(|v| v.kind).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            kinds(RecordQuery::new().sender(ALICE)),
+            [RecordKind::Deposit, RecordKind::Withdrawal]
+        );
+        assert_eq!(
+            kinds(RecordQuery::new().receiver(ALICE)),
+            [RecordKind::WithdrawalApproval, RecordKind::Withdrawal]
+        );
+        assert_eq!(kinds(RecordQuery::new().eth()), [RecordKind::Deposit]);
+        assert_eq!(
+            kinds(
+                RecordQuery::new()
+                    .token(TOKEN)
+                    .kinds([RecordKind::CreateBridge, RecordKind::Withdrawal])
+            ),
+            [RecordKind::CreateBridge, RecordKind::Withdrawal]
+        );
+        assert!(kinds(RecordQuery::new().blocks(Some(2), None)).is_empty());
+
+        // Записи читаются обратно без потерь
+        let page = indexer.query(&RecordQuery::new()).unwrap();
+        assert_eq!(page.records, records);
+    }
+
+    #[test]
+    fn pagination() {
+        let mut indexer = Indexer::in_memory(BRIDGE).unwrap();
+        let records = records();
+        indexer.insert_block(1, &records).unwrap();
+        indexer.insert_block(2, &records).unwrap();
+
+        let mut query = RecordQuery::new().limit(3);
+        let mut pages = Vec::new();
+        loop {
+            let page = indexer.query(&query).unwrap();
+            pages.push(page.records.len());
+            match page.next {
+                Some(next) => query = query.after(next),
+                None => break,
+            }
+        }
+        assert_eq!(pages, [3, 3, 2]);
+    }
+
+    #[test]
+    fn reopen() {
+        let path = std::env::temp_dir().join(format!("indexer-{}.db", rand::random::<u64>()));
+        {
+            let mut indexer = Indexer::open(&path, BRIDGE).unwrap();
+            assert_eq!(indexer.next_block().unwrap(), None);
+            indexer.insert_block(1, &records()).unwrap();
+        }
+        let indexer = Indexer::open(&path, BRIDGE).unwrap();
+        assert_eq!(indexer.next_block().unwrap(), Some(2));
+        assert_eq!(indexer.query(&RecordQuery::new()).unwrap().records.len(), 4);
+        drop(indexer);
+
+        // База другого моста не открывается
+        assert!(Indexer::open(&path, TOKEN).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// Блок без квитанций не записывается пустым, а читается заново
+    #[tokio::test]
+    async fn fetch_without_receipts() {
+        let asserter = Asserter::new();
+        let provider = mock_provider(&asserter);
+        let (block, _) = block(Vec::new());
+        asserter.push_success(&block);
+        asserter.push_success(&Option::<Vec<TransactionReceipt>>::None);
+
+        assert!(fetch_block(&provider, BRIDGE, 1).await.is_err());
+        assert!(asserter.read_q().is_empty());
+    }
+}
+
+/// Восстановление невыведенных сумм
+mod withdrawals {
+    use alloy::{
+        primitives::{Address, B256, Bytes, U256},
+        transports::mock::Asserter,
+    };
+    use alloy_sol_types::SolCall;
+
+    use super::mock_provider;
+    use crate::{
+        contracts::Bridge::{self, BridgeTokenInfo},
+        indexer::{Indexer, Record, RecordKind, RecordMeta},
+        withdrawals::{Mismatch, PendingWithdrawal, PendingWithdrawals, reconstruct},
+    };
+
+    const BRIDGE: Address = Address::repeat_byte(0xb);
+    const TOKEN: Address = Address::repeat_byte(0x7);
+    const OWNER: Address = Address::repeat_byte(0x1);
+    const ALICE: Address = Address::repeat_byte(0xa);
+    const BOB: Address = Address::repeat_byte(0xc);
+
+    fn info() -> BridgeTokenInfo {
+        BridgeTokenInfo {
+            turn: true,
+            name: "Demo".to_string(),
+            symbol: "DEMO".to_string(),
+            base_decimals: 18,
+            decimals: 8,
+        }
+    }
+
+    fn approval(to: Address, token: Option<Address>, amount: u64) -> Record {
+        Record {
+            meta: RecordMeta {
+                block_number: 1,
+                block_hash: B256::ZERO,
+                tx_hash: B256::ZERO,
+                tx_index: 0,
+                log_index: None,
+            },
+            kind: RecordKind::WithdrawalApproval,
+            sender: OWNER,
+            receiver: Some(to),
+            token,
+            amount: Some(U256::from(amount)),
+        }
+    }
+
+    fn withdrawal(user: Address, token: Option<Address>) -> Record {
+        Record {
+            kind: RecordKind::Withdrawal,
+            sender: user,
+            receiver: Some(user),
+            amount: None,
+            ..approval(user, token, 0)
+        }
+    }
+
+    fn wei(amount: u64) -> U256 {
+        U256::from(amount) * U256::from(10_u64.pow(10))
+    }
+
+    #[test]
+    fn replay() {
+        let mut pending = PendingWithdrawals::default();
+        for record in [
+            approval(ALICE, None, 5),
+            approval(ALICE, None, 2),
+            approval(BOB, None, 1),
+            approval(ALICE, Some(TOKEN), 3),
+            withdrawal(BOB, None),
+        ] {
+            pending.apply(&record, Some(&info())).unwrap();
+        }
+        assert_eq!(pending.get(ALICE, None), wei(7));
+        assert_eq!(pending.get(BOB, None), U256::ZERO);
+        assert_eq!(
+            pending.pending().collect::<Vec<_>>(),
+            [
+                PendingWithdrawal {
+                    user: ALICE,
+                    token: None,
+                    amount: wei(7),
+                },
+                PendingWithdrawal {
+                    user: ALICE,
+                    token: Some(TOKEN),
+                    amount: wei(3),
+                },
+            ]
+        );
+
+        // Без точностей моста одобрение ERC20 не пересчитать
+        assert!(pending.apply(&approval(BOB, Some(TOKEN), 1), None).is_err());
+    }
+
+    #[tokio::test]
+    async fn verify() {
+        let asserter = Asserter::new();
+        let provider = mock_provider(&asserter);
+        let mut pending = PendingWithdrawals::default();
+        pending.apply(&approval(ALICE, None, 5), None).unwrap();
+        pending.apply(&approval(BOB, None, 1), None).unwrap();
+        pending.apply(&withdrawal(BOB, None), None).unwrap();
+
+        // Ячейки читаются в порядке адресов: ALICE, BOB
+        asserter.push_success(&wei(5));
+        asserter.push_success(&wei(1));
+        let mismatches = pending.verify(&provider, BRIDGE, 1).await.unwrap();
+        assert_eq!(
+            mismatches,
+            [Mismatch {
+                user: BOB,
+                token: None,
+                expected: U256::ZERO,
+                actual: wei(1),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn reconstruct_from_index() {
+        let asserter = Asserter::new();
+        let provider = mock_provider(&asserter);
+        let mut indexer = Indexer::in_memory(BRIDGE).unwrap();
+        indexer
+            .insert_block(
+                1,
+                &[
+                    approval(ALICE, Some(TOKEN), 3),
+                    approval(BOB, Some(TOKEN), 4),
+                    withdrawal(ALICE, Some(TOKEN)),
+                    approval(ALICE, Some(TOKEN), 1),
+                ],
+            )
+            .unwrap();
+
+        // Точности моста запрашиваются один раз на токен
+        asserter.push_success(&Bytes::from(
+            Bridge::status_bridge_erc20Call::abi_encode_returns(&info()),
+        ));
+        let pending = reconstruct(&provider, &indexer).await.unwrap();
+        assert_eq!(pending.get(ALICE, Some(TOKEN)), wei(1));
+        assert_eq!(pending.get(BOB, Some(TOKEN)), wei(4));
+        assert!(asserter.read_q().is_empty());
+    }
+}
+
+/// Хранилище моста
+mod storage {
+    use alloy::{
+        primitives::{Address, B256, U256, keccak256},
+        transports::mock::Asserter,
+    };
+    use alloy_sol_types::SolValue;
+
+    use super::mock_provider;
+    use crate::{
+        contracts::Bridge::BridgeTokenInfo,
+        storage::{
+            BridgeStorage, bridge_token_slot, withdraw_request_erc20_slot, withdraw_request_slot,
+        },
+    };
+
+    const BRIDGE: Address = Address::repeat_byte(0xb);
+    const TOKEN: Address = Address::repeat_byte(0x7);
+    const ALICE: Address = Address::repeat_byte(0xa);
+
+    /// Ячейки совпадают с `keccak256(abi.encode(key, slot))`
+    #[test]
+    fn slots() {
+        let slot = |key: Address, slot: B256| keccak256((key, slot).abi_encode());
+        let index = |v: u64| B256::from(U256::from(v));
+        assert_eq!(bridge_token_slot(TOKEN), slot(TOKEN, index(1)));
+        assert_eq!(withdraw_request_slot(ALICE), slot(ALICE, index(2)));
+        assert_eq!(
+            withdraw_request_erc20_slot(ALICE, TOKEN),
+            slot(TOKEN, slot(ALICE, index(3)))
+        );
+    }
+
+    /// Короткая строка в слоте
+    fn short_string(s: &str) -> U256 {
+        let mut word = [0u8; 32];
+        word[..s.len()].copy_from_slice(s.as_bytes());
+        word[31] = s.len() as u8 * 2;
+        U256::from_be_bytes(word)
+    }
+
+    #[tokio::test]
+    async fn bridge_token() {
+        let asserter = Asserter::new();
+        let provider = mock_provider(&asserter);
+        let storage = BridgeStorage::new(provider, BRIDGE).at(7);
+
+        // Имя длиннее 31 байта хранится в двух слотах после keccak256(slot)
+        let name = "Demo token with a rather long name";
+        let mut data = name.as_bytes().to_vec();
+        data.resize(64, 0);
+        asserter.push_success(&U256::from(1));
+        asserter.push_success(&U256::from(name.len() * 2 + 1));
+        asserter.push_success(&U256::from_be_slice(&data[..32]));
+        asserter.push_success(&U256::from_be_slice(&data[32..]));
+        asserter.push_success(&short_string("DEMO"));
+        asserter.push_success(&U256::from(18 | (8 << 8)));
+
+        assert_eq!(
+            storage.bridge_token(TOKEN).await.unwrap(),
+            BridgeTokenInfo {
+                turn: true,
+                name: name.to_string(),
+                symbol: "DEMO".to_string(),
+                base_decimals: 18,
+                decimals: 8,
+            }
+        );
+        assert!(asserter.read_q().is_empty());
+    }
+
+    #[tokio::test]
+    async fn owner_and_requests() {
+        let asserter = Asserter::new();
+        let provider = mock_provider(&asserter);
+        let storage = BridgeStorage::new(provider, BRIDGE);
+
+        asserter.push_success(&U256::from_be_slice(ALICE.as_slice()));
+        asserter.push_success(&U256::from(5));
+        asserter.push_success(&U256::from(7));
+        assert_eq!(storage.owner().await.unwrap(), ALICE);
+        assert_eq!(
+            storage.withdraw_request(ALICE).await.unwrap(),
+            U256::from(5)
+        );
+        assert_eq!(
+            storage.withdraw_request_erc20(ALICE, TOKEN).await.unwrap(),
+            U256::from(7)
+        );
+
+        // Повреждённая длина строки
+        asserter.push_success(&U256::from(u64::MAX));
+        assert!(storage.string(B256::ZERO).await.is_err());
+    }
+}
+
+/// Аудит депозитов
+mod audit {
+    use alloy::{
+        consensus::{Signed, TxEnvelope, TxLegacy},
+        primitives::{Address, B256, Signature, TxKind, U256},
+        rpc::types::Transaction,
+        transports::mock::Asserter,
+    };
+
+    use super::{event_log, mock_provider};
+    use crate::{
+        amount::MAX_AMOUNT,
+        audit::{Discrepancy, audit_deposits, check_deposit},
+        contracts::Bridge,
+    };
+
+    fn transaction(to: Address, value: U256, hash: B256) -> Transaction {
+        let tx = TxLegacy {
+            to: TxKind::Call(to),
+            value,
+            ..Default::default()
+        };
+        Transaction {
+            inner: alloy::consensus::transaction::Recovered::new_unchecked(
+                TxEnvelope::Legacy(Signed::new_unchecked(tx, Signature::test_signature(), hash)),
+                Address::repeat_byte(0xa),
+            ),
+            block_hash: None,
+            block_number: None,
+            transaction_index: None,
+            effective_gas_price: None,
+        }
+    }
+
+    #[test]
+    fn check() {
+        let bridge = Address::repeat_byte(0xb);
+        let alice = Address::repeat_byte(0xa);
+        let event = |value| Bridge::EventDeposit {
+            from: alice,
+            to: alice,
+            value,
+        };
+        let wei = |v: U256| v * U256::from(10_u64.pow(10));
+
+        assert_eq!(
+            check_deposit(bridge, &event(5), Some(bridge), wei(U256::from(5))),
+            None
+        );
+        // 2^64 + 5 обрезано до 5
+        let expected = MAX_AMOUNT + U256::from(6);
+        assert_eq!(
+            check_deposit(bridge, &event(5), Some(bridge), wei(expected)),
+            Some(Discrepancy::Truncated { expected })
+        );
+        assert_eq!(
+            check_deposit(bridge, &event(5), Some(alice), wei(U256::from(5))),
+            Some(Discrepancy::IndirectCall { to: Some(alice) })
+        );
+    }
+
+    #[tokio::test]
+    async fn audit_history() {
+        let asserter = Asserter::new();
+        let provider = mock_provider(&asserter);
+        let bridge = Address::repeat_byte(0xb);
+        let alice = Address::repeat_byte(0xa);
+        let deposit = |block, value| {
+            event_log(
+                bridge,
+                &Bridge::EventDeposit {
+                    from: alice,
+                    to: alice,
+                    value,
+                },
+                block,
+                0,
+            )
+        };
+        let wei = |v: U256| v * U256::from(10_u64.pow(10));
+
+        asserter.push_success(&vec![deposit(1, 7), deposit(2, 7)]);
+        asserter.push_success(&Some(transaction(
+            bridge,
+            wei(U256::from(7)),
+            B256::with_last_byte(1),
+        )));
+        asserter.push_success(&Some(transaction(
+            bridge,
+            wei(MAX_AMOUNT + U256::from(8)),
+            B256::with_last_byte(2),
+        )));
+
+        let report = audit_deposits(&provider, bridge, 0, 10).await.unwrap();
+        assert_eq!(report.checked, 2);
+        assert_eq!(report.findings.len(), 1);
+        let finding = &report.findings[0];
+        assert_eq!(finding.meta.block_number, 2);
+        assert_eq!(finding.event.value, 7);
+        assert_eq!(
+            finding.discrepancy,
+            Discrepancy::Truncated {
+                expected: MAX_AMOUNT + U256::from(8)
+            }
+        );
+    }
+}
+
+/// Общий счётчик nonce
+mod nonce {
+    use std::time::Duration;
+
+    use alloy::{
+        primitives::{Address, U64},
+        transports::mock::Asserter,
+    };
+
+    use super::mock_provider;
+    use crate::nonce::Nonces;
+
+    #[tokio::test]
+    async fn follow_node() {
+        let asserter = Asserter::new();
+        let provider = mock_provider(&asserter);
+        let alice = Address::repeat_byte(0xa);
+        let nonces = Nonces::default().gap_timeout(Duration::from_secs(3600));
+        // Количество транзакций в пуле и в блоках
+        let next = |pending: u64, mined: u64| {
+            asserter.push_success(&U64::from(pending));
+            asserter.push_success(&U64::from(mined));
+            nonces.next(&provider, alice)
+        };
+
+        // Выданный nonce ещё не в пуле: следующий выдаётся локально
+        for (pending, expected) in [(5, 5), (5, 6), (7, 7), (10, 10)] {
+            assert_eq!(next(pending, 5).await.unwrap(), expected);
+        }
+        // Nonce 10 был в пуле и пропал из него, не попав в блок
+        assert_eq!(next(11, 10).await.unwrap(), 11);
+        assert_eq!(next(10, 10).await.unwrap(), 10);
+
+        // Nonce не появился в пуле, и у аккаунта нет новых транзакций в блоках
+        let nonces = Nonces::default().gap_timeout(Duration::ZERO);
+        for (pending, expected) in [(3, 3), (3, 3), (4, 4)] {
+            asserter.push_success(&U64::from(pending));
+            asserter.push_success(&U64::from(3));
+            assert_eq!(nonces.next(&provider, alice).await.unwrap(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent() {
+        let asserter = Asserter::new();
+        let provider = mock_provider(&asserter);
+        let alice = Address::repeat_byte(0xa);
+        let nonces = Nonces::default();
+
+        let tasks = (0..8)
+            .map(|_| {
+                asserter.push_success(&U64::ZERO);
+                asserter.push_success(&U64::ZERO);
+                let provider = provider.clone();
+                let nonces = nonces.clone();
+                tokio::spawn(async move { nonces.next(&provider, alice).await.unwrap() })
+            })
+            .collect::<Vec<_>>();
+        let mut issued = Vec::new();
+        for task in tasks {
+            issued.push(task.await.unwrap());
+        }
+        issued.sort();
+        assert_eq!(issued, (0..8).collect::<Vec<_>>());
+    }
+}
+
+/// Сопровождение транзакций
+mod supervisor {
+    use std::time::Duration;
+
+    use alloy::{
+        consensus::{Receipt, ReceiptEnvelope, ReceiptWithBloom},
+        network::TransactionBuilder,
+        primitives::{Address, B256, Bloom, U64, U256},
+        providers::WalletProvider as _,
+        rpc::types::{TransactionReceipt, TransactionRequest},
+        transports::mock::Asserter,
+    };
+
+    use super::mock_wallet_provider;
+    use crate::{
+        contracts::WalletProvider,
+        supervisor::{TxResult, TxSupervisor},
+    };
+
+    /// Запрос с заполненными газом и nonce: провайдер только подписывает его
+    fn request(from: Address) -> TransactionRequest {
+        TransactionRequest::default()
+            .with_from(from)
+            .with_to(Address::repeat_byte(0xb))
+            .with_value(U256::from(1))
+            .with_nonce(0)
+            .with_chain_id(1)
+            .with_gas_limit(21_000)
+            .with_max_fee_per_gas(100)
+            .with_max_priority_fee_per_gas(10)
+    }
+
+    fn receipt(tx_hash: B256, from: Address) -> TransactionReceipt {
+        TransactionReceipt {
+            inner: ReceiptEnvelope::Eip1559(ReceiptWithBloom {
+                receipt: Receipt {
+                    status: true.into(),
+                    cumulative_gas_used: 21_000,
+                    logs: vec![],
+                },
+                logs_bloom: Bloom::default(),
+            }),
+            transaction_hash: tx_hash,
+            transaction_index: Some(0),
+            block_hash: Some(B256::repeat_byte(1)),
+            block_number: Some(1),
+            gas_used: 21_000,
+            effective_gas_price: 100,
+            blob_gas_used: None,
+            blob_gas_price: None,
+            from,
+            to: None,
+            contract_address: None,
+        }
+    }
+
+    fn supervisor(provider: WalletProvider, max_bumps: usize) -> TxSupervisor {
+        TxSupervisor::new(provider)
+            .timeout(Duration::ZERO)
+            .poll_interval(Duration::ZERO)
+            .max_bumps(max_bumps)
+    }
+
+    #[tokio::test]
+    async fn bump_until_mined() {
+        let asserter = Asserter::new();
+        let provider = mock_wallet_provider(&asserter);
+        let alice = provider.default_signer_address();
+        let supervisor = supervisor(provider, 3);
+        let (first, second) = (B256::repeat_byte(1), B256::repeat_byte(2));
+
+        asserter.push_success(&first);
+        let mut pending = supervisor.send(request(alice)).await.unwrap();
+
+        // Первый вариант не в блоке, цена поднимается
+        asserter.push_success(&Option::<TransactionReceipt>::None);
+        asserter.push_success(&U64::ZERO);
+        asserter.push_success(&second);
+        // Второй вариант включён в блок
+        asserter.push_success(&Option::<TransactionReceipt>::None);
+        asserter.push_success(&Some(receipt(second, alice)));
+
+        let result = supervisor.wait(&mut pending).await.unwrap();
+        assert!(matches!(result, TxResult::Mined(receipt) if receipt.transaction_hash == second));
+        assert_eq!(pending.hashes(), [first, second]);
+        assert_eq!(pending.bumps(), 1);
+        assert_eq!(pending.max_fee_per_gas(), 115);
+        assert_eq!(pending.max_priority_fee_per_gas(), 12);
+    }
+
+    #[tokio::test]
+    async fn cancel() {
+        let asserter = Asserter::new();
+        let provider = mock_wallet_provider(&asserter);
+        let alice = provider.default_signer_address();
+        let supervisor = supervisor(provider, 3);
+        let (first, cancel) = (B256::repeat_byte(1), B256::repeat_byte(2));
+
+        asserter.push_success(&first);
+        let mut pending = supervisor.send(request(alice)).await.unwrap();
+        asserter.push_success(&cancel);
+        assert_eq!(supervisor.cancel(&mut pending).await.unwrap(), cancel);
+        assert!(pending.is_cancelling());
+
+        asserter.push_success(&Option::<TransactionReceipt>::None);
+        asserter.push_success(&Some(receipt(cancel, alice)));
+        let result = supervisor.wait(&mut pending).await.unwrap();
+        assert!(matches!(result, TxResult::Cancelled(_)));
+    }
+
+    #[tokio::test]
+    async fn stuck_and_replaced() {
+        let asserter = Asserter::new();
+        let provider = mock_wallet_provider(&asserter);
+        let alice = provider.default_signer_address();
+        let supervisor = supervisor(provider, 0);
+
+        asserter.push_success(&B256::repeat_byte(1));
+        let mut pending = supervisor.send(request(alice)).await.unwrap();
+
+        asserter.push_success(&Option::<TransactionReceipt>::None);
+        asserter.push_success(&U64::ZERO);
+        let result = supervisor.wait(&mut pending).await.unwrap();
+        assert!(matches!(result, TxResult::Stuck));
+
+        // Nonce использован транзакцией, которой нет среди отправленных
+        asserter.push_success(&Option::<TransactionReceipt>::None);
+        asserter.push_success(&U64::from(1));
+        asserter.push_success(&Option::<TransactionReceipt>::None);
+        let result = supervisor.wait(&mut pending).await.unwrap();
+        assert!(matches!(result, TxResult::Replaced));
+    }
+}
+
+/// Локальный узел
+mod node {
+    use crate::node::NodeKind;
+
+    #[test]
+    fn kind_from_str() {
+        assert_eq!("anvil".parse::<NodeKind>().unwrap(), NodeKind::Anvil);
+        assert_eq!("Geth".parse::<NodeKind>().unwrap(), NodeKind::Geth);
+        assert_eq!("external".parse::<NodeKind>().unwrap(), NodeKind::External);
+        assert!("hardhat".parse::<NodeKind>().is_err());
+    }
+}
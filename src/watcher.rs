@@ -0,0 +1,309 @@
+//! Наблюдатель событий с сохранением прогресса и защитой от реорганизаций
+//!
+//! Событие выдаётся как [`WatchUpdate::Confirmed`] только после `confirmations` подтверждений.
+//! Прогресс (последний обработанный блок и его хэш) сохраняется в файл, поэтому после
+//! перезапуска обработка продолжается с места остановки. Опрос не меняет состояние: оно
+//! принимается [`Watcher::commit`] после передачи изменений, поэтому изменения, которые
+//! не удалось передать, выдаются повторно.
+//!
+//! Перед каждым опросом хэш последнего обработанного блока сверяется с сетью. Если блок
+//! больше не входит в основную цепочку, события из брошенных блоков выдаются как
+//! [`WatchUpdate::Retracted`], а последние [`REORG_DEPTH`] блоков читаются заново. Хэш
+//! сохраняемого блока берётся до чтения логов и сверяется после: если блок заменили во время
+//! чтения, события не выдаются и диапазон читается заново.
+//!
+//! После событий каждого обработанного диапазона выдаётся [`WatchUpdate::Processed`], по которому
+//! подписчик может сохранять собственный прогресс.
+
+use std::{
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use alloy::{eips::BlockNumberOrTag, primitives::B256, providers::Provider};
+use eyre::{Context, ContextCompat, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::{
+    bus::EventBus,
+    events::{Backfill, EventFilter, EventLog, LogMeta},
+};
+
+/// Глубина реорганизации, которую может обработать наблюдатель
+pub const REORG_DEPTH: u64 = 64;
+
+/// Последний обработанный блок
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub block_number: u64,
+    pub block_hash: B256,
+}
+
+/// Изменение, которое наблюдатель передаёт обработчику
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WatchUpdate {
+    /// Событие получило нужное количество подтверждений
+    Confirmed(EventLog),
+    /// Ранее выданное событие оказалось в брошенном блоке
+    Retracted(LogMeta),
+    /// Все события до блока включительно выданы. После реорганизации номер уменьшается
+    Processed(u64),
+}
+
+/// Результат опроса: изменения и состояние наблюдателя после их обработки
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Polled {
+    pub updates: Vec<WatchUpdate>,
+    pub state: WatcherState,
+}
+
+/// Сохраняемое состояние наблюдателя
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatcherState {
+    /// Блок, с которого начато наблюдение
+    pub start_block: u64,
+    /// Первый ещё не обработанный блок
+    pub next_block: u64,
+    pub checkpoint: Option<Checkpoint>,
+    /// Выданные события последних [`REORG_DEPTH`] блоков
+    pub recent: Vec<LogMeta>,
+}
+
+impl WatcherState {
+    pub fn new(start_block: u64) -> Self {
+        WatcherState {
+            start_block,
+            next_block: start_block,
+            checkpoint: None,
+            recent: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path).context("Не удалось прочитать состояние")?;
+        serde_json::from_str(&content)
+            .map(Some)
+            .context("Ошибка в файле состояния")
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content =
+            serde_json::to_string_pretty(self).context("Неудалось преобразовать в JSON")?;
+        if let Some(dir) = path.parent()
+            && !dir.as_os_str().is_empty()
+        {
+            fs::create_dir_all(dir).context("Не удалось создать директорию состояния")?;
+        }
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, content).context("Ошибка при записи состояния")?;
+        fs::rename(&tmp_path, path).context("Ошибка при записи состояния")
+    }
+}
+
+/// Наблюдатель событий
+pub struct Watcher {
+    filter: EventFilter,
+    confirmations: u64,
+    /// Без файла состояние хранится только в памяти
+    state_path: Option<PathBuf>,
+    state: WatcherState,
+}
+
+impl Watcher {
+    /// Состояние читается из `state_path`. Если файла нет, наблюдение начинается с `start_block`
+    pub fn new(
+        filter: EventFilter,
+        confirmations: u64,
+        state_path: Option<&Path>,
+        start_block: u64,
+    ) -> Result<Self> {
+        let state = match state_path {
+            Some(path) => WatcherState::load(path)?,
+            None => None,
+        }
+        .unwrap_or(WatcherState::new(start_block));
+        info!(
+            "Наблюдение начинается с блока {}, подтверждений: {confirmations}",
+            state.next_block
+        );
+
+        Ok(Watcher {
+            filter,
+            confirmations,
+            state_path: state_path.map(Path::to_path_buf),
+            state,
+        })
+    }
+
+    pub fn state(&self) -> &WatcherState {
+        &self.state
+    }
+
+    /// Сохранение прогресса
+    pub fn save(&self) -> Result<()> {
+        match &self.state_path {
+            Some(path) => self.state.save(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Переход к состоянию из [`Polled`] и его сохранение. Вызывается после того, как все
+    /// изменения переданы дальше, иначе при падении процесса они потеряются
+    pub fn commit(&mut self, state: WatcherState) -> Result<()> {
+        self.state = state;
+        self.save()
+    }
+
+    /// Одна итерация: проверка реорганизации и обработка подтверждённых блоков.
+    /// Состояние наблюдателя не меняется до вызова [`Watcher::commit`]
+    pub async fn poll<P: Provider>(&self, provider: &P) -> Result<Polled> {
+        let head = provider
+            .get_block_number()
+            .await
+            .context("Не удалось получить номер блока")?;
+
+        // Отозванные события выдаются сразу, чтобы ошибка при чтении новых блоков их не потеряла
+        if let Some(checkpoint) = self.state.checkpoint
+            && block_hash(provider, checkpoint.block_number).await? != Some(checkpoint.block_hash)
+        {
+            return self.rewind(provider, checkpoint).await;
+        }
+
+        let unchanged = Polled {
+            updates: Vec::new(),
+            state: self.state.clone(),
+        };
+        let Some(safe_block) = head.checked_sub(self.confirmations) else {
+            return Ok(unchanged);
+        };
+        if safe_block < self.state.next_block {
+            return Ok(unchanged);
+        }
+
+        let safe_hash = block_hash(provider, safe_block)
+            .await?
+            .with_context(|| format!("Блок {safe_block} не найден"))?;
+        let events = Backfill::new(self.filter.clone(), self.state.next_block, safe_block)
+            .collect(provider)
+            .await?;
+        // Логи и хэш должны относиться к одной цепочке: если блок заменили во время чтения,
+        // диапазон читается заново при следующем опросе
+        let replaced = events
+            .iter()
+            .any(|v| v.meta.block_number == safe_block && v.meta.block_hash != safe_hash)
+            || block_hash(provider, safe_block).await? != Some(safe_hash);
+        if replaced {
+            warn!("Блок {safe_block} заменён во время чтения логов");
+            return Ok(unchanged);
+        }
+
+        let mut state = unchanged.state;
+        let mut updates = Vec::new();
+        for event in events {
+            if state.recent.contains(&event.meta) {
+                continue;
+            }
+            state.recent.push(event.meta);
+            updates.push(WatchUpdate::Confirmed(event));
+        }
+        updates.push(WatchUpdate::Processed(safe_block));
+
+        state.checkpoint = Some(Checkpoint {
+            block_number: safe_block,
+            block_hash: safe_hash,
+        });
+        state.next_block = safe_block + 1;
+        state
+            .recent
+            .retain(|v| v.block_number + REORG_DEPTH > safe_block);
+        Ok(Polled { updates, state })
+    }
+
+    /// Отзыв событий из брошенных блоков и возврат к началу окна [`REORG_DEPTH`]
+    async fn rewind<P: Provider>(&self, provider: &P, checkpoint: Checkpoint) -> Result<Polled> {
+        warn!(
+            "Блок {} {} больше не в основной цепочке",
+            checkpoint.block_number, checkpoint.block_hash
+        );
+
+        let mut updates = Vec::new();
+        let mut recent = Vec::new();
+        for meta in &self.state.recent {
+            if block_hash(provider, meta.block_number).await? == Some(meta.block_hash) {
+                recent.push(*meta);
+            } else {
+                warn!("Событие отозвано: {meta:?}");
+                updates.push(WatchUpdate::Retracted(*meta));
+            }
+        }
+        let next_block = checkpoint
+            .block_number
+            .saturating_sub(REORG_DEPTH - 1)
+            .max(self.state.start_block);
+        debug!("Повторное чтение с блока {next_block}");
+        if let Some(block_number) = next_block.checked_sub(1) {
+            updates.push(WatchUpdate::Processed(block_number));
+        }
+
+        Ok(Polled {
+            updates,
+            state: WatcherState {
+                start_block: self.state.start_block,
+                next_block,
+                checkpoint: None,
+                recent,
+            },
+        })
+    }
+
+    /// Обработка блоков с интервалом `interval` до завершения `shutdown`. Изменения публикуются
+    /// в `bus`, прогресс сохраняется только после публикации всех изменений итерации.
+    /// Ошибки узла не прерывают наблюдение, запрос повторяется на следующей итерации.
+    /// Ошибка публикации возвращается: после перезапуска те же изменения выдаются повторно
+    pub async fn run<P: Provider>(
+        &mut self,
+        provider: &P,
+        interval: Duration,
+        bus: &EventBus<WatchUpdate>,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<()> {
+        tokio::pin!(shutdown);
+        loop {
+            match self.poll(provider).await {
+                Ok(Polled { updates, state }) => {
+                    let publish = async {
+                        for update in updates {
+                            bus.publish(update).await?;
+                        }
+                        Ok::<_, eyre::Report>(())
+                    };
+                    tokio::select! {
+                        _ = &mut shutdown => return Ok(()),
+                        published = publish => published?,
+                    }
+                    self.commit(state)?;
+                }
+                Err(err) => warn!("Ошибка наблюдения: {err:#}. Повтор через {interval:?}"),
+            }
+            tokio::select! {
+                _ = &mut shutdown => return Ok(()),
+                _ = tokio::time::sleep(interval) => (),
+            }
+        }
+    }
+}
+
+/// Хэш блока основной цепочки
+async fn block_hash<P: Provider>(provider: &P, block_number: u64) -> Result<Option<B256>> {
+    let block = provider
+        .get_block_by_number(BlockNumberOrTag::Number(block_number))
+        .await
+        .with_context(|| format!("Не удалось получить блок {block_number}"))?;
+    Ok(block.map(|v| v.header.hash))
+}
@@ -0,0 +1,170 @@
+//! Восстановление невыведенных сумм по истории моста
+//!
+//! `WithdrawRequest` и `WithdrawRequestErc20` - закрытые отображения, а `available_to_withdraw*`
+//! возвращают сумму только для `msg.sender`. [`PendingWithdrawals`] повторяет изменения
+//! отображений по операциям из [`Indexer`]: одобрение увеличивает сумму на одобренное значение
+//! в точности l1, вывод обнуляет её. Результат сверяется с ячейками отображений,
+//! прочитанными [`BridgeStorage`].
+//!
+//! Операции берутся из транзакций, отправленных прямо мосту. Вызовы через другие контракты
+//! в историю не попадают и обнаруживаются только при сверке.
+
+use std::collections::{BTreeMap, HashMap, hash_map::Entry};
+
+use alloy::{
+    primitives::{Address, U256},
+    providers::Provider,
+};
+use eyre::{Context, ContextCompat, Result, bail};
+use tracing::debug;
+
+use crate::{
+    amount::{ETH_DECIMALS, L2_DECIMALS, convert_amount, convert_amount_to_l1},
+    contracts::Bridge::{self, BridgeTokenInfo},
+    indexer::{Indexer, Record, RecordKind, RecordQuery},
+    storage::BridgeStorage,
+};
+
+/// Невыведенная сумма
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingWithdrawal {
+    pub user: Address,
+    /// `None` для ETH
+    pub token: Option<Address>,
+    /// Сумма в точности l1
+    pub amount: U256,
+}
+
+/// Расхождение восстановленной суммы с хранилищем моста
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mismatch {
+    pub user: Address,
+    pub token: Option<Address>,
+    /// Сумма по истории
+    pub expected: U256,
+    /// Сумма в хранилище
+    pub actual: U256,
+}
+
+/// Таблица невыведенных сумм по пользователям и токенам
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PendingWithdrawals {
+    /// Включая обнулённые выводом, чтобы их тоже можно было сверить
+    balances: BTreeMap<(Address, Option<Address>), U256>,
+}
+
+impl PendingWithdrawals {
+    /// Изменение суммы по операции. Для одобрения ERC20 нужны точности моста токена
+    pub fn apply(&mut self, record: &Record, info: Option<&BridgeTokenInfo>) -> Result<()> {
+        match record.kind {
+            RecordKind::WithdrawalApproval => {
+                let user = record.receiver.context("В одобрении нет получателя")?;
+                let amount = record.amount.context("В одобрении нет суммы")?;
+                let amount = match record.token {
+                    None => convert_amount(amount, L2_DECIMALS, ETH_DECIMALS)?,
+                    Some(token) => convert_amount_to_l1(
+                        info.with_context(|| format!("Нет точностей моста токена {token}"))?,
+                        amount,
+                    )?,
+                };
+                let balance = self.balances.entry((user, record.token)).or_default();
+                *balance = balance.saturating_add(amount);
+            }
+            RecordKind::Withdrawal => {
+                self.balances
+                    .insert((record.sender, record.token), U256::ZERO);
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Невыведенная сумма `user`. `token` - `None` для ETH
+    pub fn get(&self, user: Address, token: Option<Address>) -> U256 {
+        self.balances
+            .get(&(user, token))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Ненулевые суммы по пользователям и токенам
+    pub fn pending(&self) -> impl Iterator<Item = PendingWithdrawal> + '_ {
+        self.balances
+            .iter()
+            .filter(|(_, amount)| !amount.is_zero())
+            .map(|(&(user, token), &amount)| PendingWithdrawal {
+                user,
+                token,
+                amount,
+            })
+    }
+
+    /// Сверка всех известных сумм с хранилищем моста в блоке `block_number`
+    pub async fn verify<P: Provider>(
+        &self,
+        provider: &P,
+        bridge: Address,
+        block_number: u64,
+    ) -> Result<Vec<Mismatch>> {
+        let storage = BridgeStorage::new(provider, bridge).at(block_number);
+        let mut mismatches = Vec::new();
+        for (&(user, token), &expected) in &self.balances {
+            let actual = storage.pending(user, token).await?;
+            if actual != expected {
+                debug!("Расхождение для {user} {token:?}: {expected} != {actual}");
+                mismatches.push(Mismatch {
+                    user,
+                    token,
+                    expected,
+                    actual,
+                });
+            }
+        }
+        Ok(mismatches)
+    }
+}
+
+/// Невыведенные суммы по всем операциям в базе `indexer`. Точности токенов читаются
+/// из `status_bridge_erc20`
+pub async fn reconstruct<P: Provider>(
+    provider: &P,
+    indexer: &Indexer,
+) -> Result<PendingWithdrawals> {
+    let bridge = Bridge::new(indexer.bridge(), provider);
+    let mut tokens = HashMap::new();
+    let mut pending = PendingWithdrawals::default();
+    let mut query =
+        RecordQuery::new().kinds([RecordKind::WithdrawalApproval, RecordKind::Withdrawal]);
+
+    loop {
+        let page = indexer.query(&query)?;
+        for record in &page.records {
+            let info = match record.token {
+                Some(token) if record.kind == RecordKind::WithdrawalApproval => {
+                    if let Entry::Vacant(entry) = tokens.entry(token) {
+                        let info = bridge
+                            .status_bridge_erc20(token)
+                            .call()
+                            .await
+                            .with_context(|| {
+                                format!("Не удалось получить состояние моста токена {token}")
+                            })?;
+                        if !info.turn {
+                            bail!("Мост для токена {token} не создан");
+                        }
+                        entry.insert(info);
+                    }
+                    tokens.get(&token)
+                }
+                _ => None,
+            };
+            pending
+                .apply(record, info)
+                .with_context(|| format!("Ошибка в операции {}", record.meta.tx_hash))?;
+        }
+        match page.next {
+            Some(next) => query = query.after(next),
+            None => return Ok(pending),
+        }
+    }
+}